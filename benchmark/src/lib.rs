@@ -0,0 +1,316 @@
+//! Bit and frame error rates of a demodulator and deframer pair, over a simulated channel.
+//!
+//! Random frames are modulated, shifted in frequency, buried in white noise at each SNR of a
+//! sweep, and decoded. The demodulated bits are compared to those sent, and the deframed frames
+//! to the frames sent.
+
+use demod::Demodulator;
+use framing::{deframer::Deframer, frame::Frame};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use sdr::{
+    block::{Complex, SampleBlock, Timestamp},
+    modulation::Modulation,
+    sim::Awgn,
+};
+use std::{f64::consts::PI, io, ops::Range, time::Duration};
+
+/// Bits of idle between frames: alternating, so that the symbol clock keeps locked.
+const IDLE_BITS: usize = 32;
+/// Samples per block handed to the demodulator.
+const BLOCK_LEN: usize = 4096;
+/// Bits the demodulator output may be ahead or behind the bits sent.
+const MAX_LAG: isize = 64;
+/// Bits sent compared at a time, each window at its own lag, so that a bit slipped or repeated by
+/// the demodulator only costs the errors of its window.
+const WINDOW_BITS: usize = 256;
+/// Bits the lag may change by from one window to the next.
+const MAX_SLIP: isize = 4;
+
+/// Bits decoded by the deframer. Boxed, so that any deframer generic over its input fits.
+pub type Bits = Box<dyn Iterator<Item = Vec<bool>>>;
+
+/// What to send, and over what channel.
+#[derive(Debug, Clone)]
+pub struct Benchmark {
+    pub modulation: Modulation,
+    pub sample_rate: f64,
+    /// Frequency offset of the signal from the tuned frequency, in Hz.
+    pub frequency_offset: f64,
+    /// Frames sent at each SNR.
+    pub frames: usize,
+    /// Bytes in each frame.
+    pub payload_len: usize,
+    /// Seeds the frames and the noise, so that runs can be compared.
+    pub seed: u64,
+}
+
+/// Errors at one SNR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    /// Over the whole sampled bandwidth, in dB.
+    pub snr_db: f64,
+    pub ebn0_db: f64,
+    pub bits: usize,
+    pub bit_errors: usize,
+    pub frames: usize,
+    /// Frames sent that weren't deframed.
+    pub frame_errors: usize,
+}
+
+impl Point {
+    pub fn ber(&self) -> f64 {
+        self.bit_errors as f64 / self.bits.max(1) as f64
+    }
+
+    pub fn fer(&self) -> f64 {
+        self.frame_errors as f64 / self.frames.max(1) as f64
+    }
+}
+
+impl Benchmark {
+    /// Runs the benchmark at each SNR in `snrs_db`.
+    pub fn run<D, F>(&self, demodulator: &D, deframer: &F, snrs_db: &[f64]) -> Vec<Point>
+    where
+        D: Demodulator<std::vec::IntoIter<SampleBlock>>,
+        D::Output: 'static,
+        F: Deframer<Vec<bool>, Frame, Input = Bits>,
+    {
+        snrs_db
+            .iter()
+            .map(|snr_db| self.run_at(demodulator, deframer, *snr_db))
+            .collect()
+    }
+
+    fn run_at<D, F>(&self, demodulator: &D, deframer: &F, snr_db: f64) -> Point
+    where
+        D: Demodulator<std::vec::IntoIter<SampleBlock>>,
+        D::Output: 'static,
+        F: Deframer<Vec<bool>, Frame, Input = Bits>,
+    {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let payloads: Vec<Vec<u8>> = (0..self.frames)
+            .map(|_| (0..self.payload_len).map(|_| rng.random()).collect())
+            .collect();
+
+        let mut sent = idle();
+        for payload in &payloads {
+            sent.extend(Frame::new(Some(payload.clone())).to_bits());
+            sent.extend(idle());
+        }
+
+        let blocks = self.channel(&sent, snr_db);
+
+        // The bits are kept for the bit error rate as they pass to the deframer.
+        let (bits_tx, bits_rx) = std::sync::mpsc::channel();
+        let bits: Bits = Box::new(
+            demodulator
+                .bits(blocks.into_iter())
+                .filter_map(Result::ok)
+                .inspect(move |bits| {
+                    let _ = bits_tx.send(bits.clone());
+                }),
+        );
+
+        let mut deframed: Vec<Vec<u8>> = deframer.frames(bits).filter_map(|f| f.info).collect();
+        let received: Vec<bool> = bits_rx.try_iter().flatten().collect();
+
+        let frame_errors = payloads
+            .iter()
+            .filter(
+                |payload| match deframed.iter().position(|d| d == *payload) {
+                    Some(i) => {
+                        deframed.remove(i);
+                        false
+                    }
+                    None => true,
+                },
+            )
+            .count();
+
+        Point {
+            snr_db,
+            ebn0_db: snr_db + 10.0 * (self.sample_rate / self.modulation.baud()).log10(),
+            bits: sent.len(),
+            bit_errors: bit_errors(&sent, &received),
+            frames: payloads.len(),
+            frame_errors,
+        }
+    }
+
+    /// The samples received for `bits`, in blocks.
+    fn channel(&self, bits: &[bool], snr_db: f64) -> Vec<SampleBlock> {
+        let mut noise = Awgn::seeded(snr_db, self.seed);
+        let step = 2.0 * PI * self.frequency_offset / self.sample_rate;
+
+        let samples: Vec<Complex<f32>> = self
+            .modulation
+            .modulate(bits, self.sample_rate)
+            .into_iter()
+            .enumerate()
+            .map(|(n, sample)| {
+                noise.add(sample * Complex::from_polar(1.0, (step * n as f64 % (2.0 * PI)) as f32))
+            })
+            .collect();
+
+        samples
+            .chunks(BLOCK_LEN)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let time = (i * BLOCK_LEN) as f64 / self.sample_rate;
+                SampleBlock::new(
+                    chunk.to_vec(),
+                    self.sample_rate,
+                    0.0,
+                    Timestamp::Hardware(Duration::from_secs_f64(time)),
+                )
+            })
+            .collect()
+    }
+}
+
+fn idle() -> Vec<bool> {
+    (0..IDLE_BITS).map(|i| i % 2 == 0).collect()
+}
+
+/// Bits of `sent` that differ from `received`, realigned for every window of bits at the lag of
+/// `received` with the fewest, so that slips don't offset the rest of the bits. Bits missing from
+/// `received` are errors.
+fn bit_errors(sent: &[bool], received: &[bool]) -> usize {
+    // Errors in `window` of `sent`, with `received` `lag` bits behind.
+    let errors = |window: Range<usize>, lag: isize| {
+        window
+            .filter(|i| {
+                let r = *i as isize + lag;
+                r < 0 || received.get(r as usize) != Some(&sent[*i])
+            })
+            .count()
+    };
+
+    let mut lag = 0;
+    let mut total = 0;
+    for start in (0..sent.len()).step_by(WINDOW_BITS) {
+        let window = start..(start + WINDOW_BITS).min(sent.len());
+        let reach = if start == 0 { MAX_LAG } else { MAX_SLIP };
+
+        // Closest lags first, so that ties keep the lag.
+        let (best, window_errors) = (0..=reach)
+            .flat_map(|d| [lag + d, lag - d])
+            .map(|lag| (lag, errors(window.clone(), lag)))
+            .min_by_key(|(_, errors)| *errors)
+            .expect("lags to try");
+        lag = best;
+        total += window_errors;
+    }
+
+    total
+}
+
+/// Writes `points` as CSV, with a header.
+pub fn write_csv(mut out: impl io::Write, points: &[Point]) -> io::Result<()> {
+    writeln!(
+        out,
+        "snr_db,ebn0_db,bits,bit_errors,ber,frames,frame_errors,fer"
+    )?;
+    for p in points {
+        writeln!(
+            out,
+            "{:.1},{:.1},{},{},{:.3e},{},{},{:.3}",
+            p.snr_db,
+            p.ebn0_db,
+            p.bits,
+            p.bit_errors,
+            p.ber(),
+            p.frames,
+            p.frame_errors,
+            p.fer()
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use demod::fsk::{Detector, Fsk, FskParams};
+    use framing::hdlc_deframer::HdlcDeframer;
+
+    fn benchmark() -> Benchmark {
+        Benchmark {
+            modulation: Modulation::Gfsk {
+                baud: 1200.0,
+                deviation: 500.0,
+                bt: 1.0,
+            },
+            sample_rate: 48_000.0,
+            frequency_offset: 50.0,
+            frames: 5,
+            payload_len: 16,
+            seed: 44,
+        }
+    }
+
+    #[test]
+    fn no_errors_without_noise() {
+        let fsk = Fsk::new(FskParams::binary(1200.0, 1000.0, Detector::MatchedFilter)).unwrap();
+
+        let points = benchmark().run(&fsk, &HdlcDeframer::new(), &[40.0]);
+
+        assert_eq!(points[0].frame_errors, 0);
+        assert!(points[0].ber() < 1e-3, "{:?}", points[0]);
+    }
+
+    #[test]
+    fn every_frame_lost_in_noise() {
+        let fsk = Fsk::new(FskParams::binary(1200.0, 1000.0, Detector::MatchedFilter)).unwrap();
+
+        let points = benchmark().run(&fsk, &HdlcDeframer::new(), &[-20.0]);
+
+        assert_eq!(points[0].frame_errors, 5);
+        assert!(points[0].ber() > 0.3, "{:?}", points[0]);
+    }
+
+    #[test]
+    fn counts_bit_errors_at_the_best_lag() {
+        let sent = vec![true, false, false, true, true, false, true, false];
+        let mut received = vec![false, false];
+        received.extend(&sent);
+        received[5] = !received[5];
+
+        assert_eq!(bit_errors(&sent, &received), 1);
+        assert_eq!(bit_errors(&sent, &sent[..6]), 2);
+    }
+
+    #[test]
+    fn realigns_after_a_slipped_bit() {
+        let mut rng = StdRng::seed_from_u64(45);
+        let sent: Vec<bool> = (0..2000).map(|_| rng.random()).collect();
+        let mut received = sent.clone();
+        received.remove(1000);
+
+        // Only the bits of the slip's window after it can differ, not all the bits after it.
+        let errors = bit_errors(&sent, &received);
+        assert!(errors < 24, "{} errors", errors);
+    }
+
+    #[test]
+    fn writes_csv() {
+        let point = Point {
+            snr_db: 10.0,
+            ebn0_db: 26.0,
+            bits: 1000,
+            bit_errors: 5,
+            frames: 10,
+            frame_errors: 1,
+        };
+        let mut out = Vec::new();
+
+        write_csv(&mut out, &[point]).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "snr_db,ebn0_db,bits,bit_errors,ber,frames,frame_errors,fer\n\
+             10.0,26.0,1000,5,5.000e-3,10,1,0.100\n"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Measures the bit and frame error rates of the FSK demodulator with the HDLC deframer, and
+//! writes them as CSV to stdout.
+//!
+//! ```text
+//! cargo run --release -p benchmark -- --detector discriminator --snr -5:15:1 > discriminator.csv
+//! ```
+
+use benchmark::{Benchmark, write_csv};
+use demod::fsk::{Detector, Fsk, FskParams};
+use framing::hdlc_deframer::HdlcDeframer;
+use sdr::modulation::Modulation;
+use std::{env, io, process};
+
+const USAGE: &str = "\
+Usage: benchmark [options]
+
+  --baud <baud>             default 1200
+  --deviation <hz>          GFSK deviation, default 500
+  --bt <bt>                 GFSK bandwidth-time product, default 0.5
+  --detector <detector>     matched_filter (default) | discriminator
+  --sample-rate <hz>        default 48000
+  --offset <hz>             frequency offset, default 0
+  --snr <from:to:step>      SNR sweep over the sampled bandwidth in dB, default -10:20:1
+  --frames <n>              frames per SNR, default 100
+  --payload <bytes>         bytes per frame, default 32
+  --seed <seed>             default 1";
+
+fn main() {
+    let mut baud = 1200.0;
+    let mut deviation = 500.0;
+    let mut bt = 0.5;
+    let mut detector = Detector::MatchedFilter;
+    let mut snr = (-10.0, 20.0, 1.0);
+    let mut sample_rate = 48_000.0;
+    let mut frequency_offset = 0.0;
+    let mut frames = 100;
+    let mut payload_len = 32;
+    let mut seed = 1;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--baud" => baud = parse(&value),
+            "--deviation" => deviation = parse(&value),
+            "--bt" => bt = parse(&value),
+            "--detector" => {
+                detector = match value.as_str() {
+                    "matched_filter" => Detector::MatchedFilter,
+                    "discriminator" => Detector::Discriminator,
+                    _ => usage(),
+                }
+            }
+            "--sample-rate" => sample_rate = parse(&value),
+            "--offset" => frequency_offset = parse(&value),
+            "--snr" => {
+                let sweep: Vec<f64> = value.split(':').map(parse).collect();
+                let [from, to, step] = sweep[..] else { usage() };
+                snr = (from, to, step);
+            }
+            "--frames" => frames = parse(&value),
+            "--payload" => payload_len = parse(&value),
+            "--seed" => seed = parse(&value),
+            _ => usage(),
+        }
+    }
+
+    if snr.2 <= 0.0 {
+        usage();
+    }
+    let snrs: Vec<f64> = (0..)
+        .map(|i| snr.0 + i as f64 * snr.2)
+        .take_while(|snr_db| *snr_db <= snr.1 + 1e-9)
+        .collect();
+
+    let benchmark = Benchmark {
+        modulation: Modulation::Gfsk {
+            baud,
+            deviation,
+            bt,
+        },
+        sample_rate,
+        frequency_offset,
+        frames,
+        payload_len,
+        seed,
+    };
+    let fsk = Fsk::new(FskParams::binary(baud, 2.0 * deviation, detector))
+        .unwrap_or_else(|err| panic!("Invalid FSK parameters: {:?}", err));
+
+    let points = benchmark.run(&fsk, &HdlcDeframer::new(), &snrs);
+    write_csv(io::stdout().lock(), &points).unwrap();
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> T {
+    value.parse().unwrap_or_else(|_| usage())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2)
+}
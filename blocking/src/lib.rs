@@ -0,0 +1,124 @@
+//! Streams read as iterators, to run sync code such as the demodulators and deframers on them.
+//!
+//! The sync code runs on a blocking thread behind [`run_blocking`], reading the input stream as a
+//! [`BlockingStream`]. Dropping the output stream cancels it: its input ends, and so does the
+//! thread.
+
+use std::pin::Pin;
+use tokio::{runtime::Handle, sync::mpsc};
+use tokio_stream::{Stream, StreamExt, wrappers::ReceiverStream};
+
+/// A stream read as an iterator, by blocking on it. It ends with the stream, or early once
+/// cancelled.
+pub struct BlockingStream<S> {
+    stream: Pin<Box<S>>,
+    handle: Handle,
+    /// Resolves once reading is cancelled.
+    cancelled: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl<S> BlockingStream<S> {
+    /// Reads `stream` until it ends. Must be created within a Tokio runtime, and read outside of
+    /// its async tasks, e.g. on a blocking thread.
+    pub fn new(stream: S) -> Self {
+        Self::cancellable(stream, std::future::pending())
+    }
+
+    /// Reads `stream` until it ends or `cancelled` resolves.
+    fn cancellable(stream: S, cancelled: impl Future<Output = ()> + Send + 'static) -> Self {
+        Self {
+            stream: Box::pin(stream),
+            handle: Handle::current(),
+            cancelled: Some(Box::pin(cancelled)),
+        }
+    }
+}
+
+impl<S> Iterator for BlockingStream<S>
+where
+    S: Stream,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cancelled = self.cancelled.as_mut()?;
+        let stream = &mut self.stream;
+
+        let item = self.handle.block_on(async {
+            tokio::select! {
+                item = stream.next() => Some(item),
+                () = cancelled => None,
+            }
+        });
+
+        // Cancellation is final.
+        item.unwrap_or_else(|| {
+            self.cancelled = None;
+            None
+        })
+    }
+}
+
+/// Streams the output of the iterator `f` makes of `input`, running it on a blocking thread, up
+/// to `queue_len` items ahead of the consumer. Dropping the output stream ends the input, and so
+/// the thread.
+///
+/// Must be called within a Tokio runtime.
+pub fn run_blocking<S, I>(
+    input: S,
+    queue_len: usize,
+    f: impl FnOnce(BlockingStream<S>) -> I,
+) -> ReceiverStream<I::Item>
+where
+    S: Stream + Send + 'static,
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(queue_len);
+    let output = tx.clone();
+    let items = f(BlockingStream::cancellable(input, async move {
+        output.closed().await
+    }));
+
+    tokio::task::spawn_blocking(move || {
+        for item in items {
+            if tx.blocking_send(item).is_err() {
+                break;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn outputs_what_the_iterator_does() {
+        let input = tokio_stream::iter(1..=4);
+
+        let output: Vec<_> = run_blocking(input, 1, |input| input.map(|n| n * 2))
+            .collect()
+            .await;
+
+        assert_eq!(output, vec![2, 4, 6, 8]);
+    }
+
+    #[tokio::test]
+    async fn dropping_the_output_stops_reading_the_input() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut output = run_blocking(ReceiverStream::new(rx), 1, |input| input);
+
+        tx.send(1).await.unwrap();
+        assert_eq!(output.next().await, Some(1));
+        drop(output);
+
+        // The input is dropped along with the iterator once its thread ends.
+        tokio::time::timeout(Duration::from_secs(5), tx.closed())
+            .await
+            .unwrap();
+    }
+}
@@ -1,13 +1,18 @@
 use demod::{Demodulator, afsk1200::Afsk1200};
 use itertools::Itertools;
+use sdr::block::{Complex, SampleBlock, Timestamp};
 
 use std::{
     fs::File,
     io::{BufReader, BufWriter, Read, Write},
     path::PathBuf,
+    sync::atomic::Ordering,
+    time::Duration,
 };
 
 const SAMPLES_BATCH_SIZE: usize = 10_000;
+/// Decimated to 48 kHz by the flowgraph.
+const SAMPLE_RATE: f64 = 1_920_000.0;
 
 fn main() {
     println!("Running afsk_demod.py");
@@ -15,23 +20,36 @@ fn main() {
     let here = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     let flowgraph_path = here.join("flowgraphs").join("afsk_demod.py");
 
-    let demod = Afsk1200::new(flowgraph_path).unwrap();
+    let demod = Afsk1200::new(flowgraph_path, SAMPLE_RATE).unwrap();
+    let restarts = demod.restarts();
 
     let reader = BufReader::new(File::open(here.join("examples").join("samples.iq")).unwrap());
 
-    // Convert the input bytes to f64 batches.
-    let samples: Vec<Vec<f64>> = reader
+    // Convert the input bytes to blocks of complex samples.
+    let samples: Vec<SampleBlock> = reader
         .bytes()
         .map(|b| b.unwrap())
-        .chunks(8) // 8 bytes per f64
+        .chunks(8) // 8 bytes per complex sample (f32 I, f32 Q)
         .into_iter()
         .map(|chunk| {
             let bytes: [u8; 8] = chunk.collect::<Vec<u8>>().try_into().unwrap();
-            f64::from_ne_bytes(bytes)
+            let i = f32::from_ne_bytes(bytes[0..4].try_into().unwrap());
+            let q = f32::from_ne_bytes(bytes[4..8].try_into().unwrap());
+            Complex::new(i, q)
         })
         .chunks(SAMPLES_BATCH_SIZE)
         .into_iter()
-        .map(|batch| batch.collect::<Vec<f64>>())
+        .enumerate()
+        .map(|(n, batch)| {
+            // Time from the start of the recording.
+            let offset = Duration::from_secs_f64((n * SAMPLES_BATCH_SIZE) as f64 / SAMPLE_RATE);
+            SampleBlock::new(
+                batch.collect(),
+                SAMPLE_RATE,
+                0.0,
+                Timestamp::Hardware(offset),
+            )
+        })
         .collect();
 
     let bits_iter = demod.bits(samples.into_iter());
@@ -39,6 +57,12 @@ fn main() {
     let mut writer = BufWriter::new(File::create("./demod/examples/output.bit").unwrap());
 
     for bits in bits_iter {
+        let bits = match bits {
+            Ok(bits) => bits,
+            Err(err) if err.is_fatal() => panic!("Demodulation failed: {:?}", err),
+            Err(_) => continue,
+        };
+
         // write bits as 0/1 bytes
         let bytes: Vec<u8> = bits
             .into_iter()
@@ -47,7 +71,10 @@ fn main() {
         writer.write_all(&bytes).unwrap();
     }
 
-    println!("Finished demodulation. Bits written to output.bit");
+    println!(
+        "Finished demodulation, restarting the flowgraph {} times. Bits written to output.bit",
+        restarts.load(Ordering::Relaxed)
+    );
 
     writer.flush().unwrap();
 }
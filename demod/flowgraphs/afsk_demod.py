@@ -29,34 +29,40 @@ from gnuradio import zeromq
 
 class afsk_demod(gr.top_block):
 
-    def __init__(self):
+    def __init__(self, baud_rate=1200, bit_address='tcp://127.0.0.1:5557', freq_deviation=1000, samp_rate=48000, sample_address='tcp://127.0.0.1:5556'):
         gr.top_block.__init__(self, "AFSK demod", catch_exceptions=True)
 
+        ##################################################
+        # Parameters
+        ##################################################
+        self.baud_rate = baud_rate
+        self.bit_address = bit_address
+        self.freq_deviation = freq_deviation
+        self.samp_rate = samp_rate
+        self.sample_address = sample_address
+
         ##################################################
         # Variables
         ##################################################
-        self.samp_rate = samp_rate = 48000
         self.pi = pi = 3.1415
-        self.freq_deviation = freq_deviation = 1000
-        self.baud_rate = baud_rate = 1200
 
         ##################################################
         # Blocks
         ##################################################
 
-        self.zeromq_sub_source_0 = zeromq.sub_source(gr.sizeof_gr_complex, 1, 'tcp://127.0.0.1:5556', 100, False, (-1), '')
-        self.zeromq_pub_sink_0 = zeromq.pub_sink(gr.sizeof_char, 1, 'tcp://127.0.0.1:5557', 0, False, (-1), '', True)
+        self.zeromq_sub_source_0 = zeromq.sub_source(gr.sizeof_gr_complex, 1, sample_address, 100, False, (-1), '')
+        self.zeromq_pub_sink_0 = zeromq.pub_sink(gr.sizeof_char, 1, bit_address, 0, False, (-1), '', True)
         self.rational_resampler_xxx_0 = filter.rational_resampler_ccc(
                 interpolation=1,
                 decimation=40,
                 taps=[],
                 fractional_bw=0.4)
-        self.digital_clock_recovery_mm_xx_0 = digital.clock_recovery_mm_ff((samp_rate/baud_rate), (0.25*0.175*0.175), 0.5, 0.175, 0.005)
+        self.digital_clock_recovery_mm_xx_0 = digital.clock_recovery_mm_ff((self.samp_rate/self.baud_rate), (0.25*0.175*0.175), 0.5, 0.175, 0.005)
         self.digital_binary_slicer_fb_0_0 = digital.binary_slicer_fb()
         self.blocks_multiply_xx_1 = blocks.multiply_vcc(1)
         self.blocks_add_const_vxx_0 = blocks.add_const_ff((-0.5))
-        self.analog_sig_source_x_0_0 = analog.sig_source_c(samp_rate, analog.GR_SIN_WAVE, (-1200), 1, 0, 0)
-        self.analog_quadrature_demod_cf_0 = analog.quadrature_demod_cf((samp_rate/(pi*freq_deviation*2)))
+        self.analog_sig_source_x_0_0 = analog.sig_source_c(self.samp_rate, analog.GR_SIN_WAVE, (-1200), 1, 0, 0)
+        self.analog_quadrature_demod_cf_0 = analog.quadrature_demod_cf((self.samp_rate/(pi*self.freq_deviation*2)))
 
 
         ##################################################
@@ -81,6 +87,18 @@ class afsk_demod(gr.top_block):
         self.analog_sig_source_x_0_0.set_sampling_freq(self.samp_rate)
         self.digital_clock_recovery_mm_xx_0.set_omega((self.samp_rate/self.baud_rate))
 
+    def get_bit_address(self):
+        return self.bit_address
+
+    def set_bit_address(self, bit_address):
+        self.bit_address = bit_address
+
+    def get_sample_address(self):
+        return self.sample_address
+
+    def set_sample_address(self, sample_address):
+        self.sample_address = sample_address
+
     def get_pi(self):
         return self.pi
 
@@ -105,8 +123,30 @@ class afsk_demod(gr.top_block):
 
 
 
+def argument_parser():
+    parser = ArgumentParser()
+    parser.add_argument(
+        "--baud-rate", dest="baud_rate", type=eng_float, default=eng_notation.num_to_str(float(1200)),
+        help="Set Baud rate [default=%(default)r]")
+    parser.add_argument(
+        "--bit-address", dest="bit_address", type=str, default='tcp://127.0.0.1:5557',
+        help="Set Bit address [default=%(default)r]")
+    parser.add_argument(
+        "--freq-deviation", dest="freq_deviation", type=eng_float, default=eng_notation.num_to_str(float(1000)),
+        help="Set Frequency deviation [default=%(default)r]")
+    parser.add_argument(
+        "--samp-rate", dest="samp_rate", type=eng_float, default=eng_notation.num_to_str(float(48000)),
+        help="Set Sample rate [default=%(default)r]")
+    parser.add_argument(
+        "--sample-address", dest="sample_address", type=str, default='tcp://127.0.0.1:5556',
+        help="Set Sample address [default=%(default)r]")
+    return parser
+
+
 def main(top_block_cls=afsk_demod, options=None):
-    tb = top_block_cls()
+    if options is None:
+        options = argument_parser().parse_args()
+    tb = top_block_cls(baud_rate=options.baud_rate, bit_address=options.bit_address, freq_deviation=options.freq_deviation, samp_rate=options.samp_rate, sample_address=options.sample_address)
 
     def sig_handler(sig=None, frame=None):
         tb.stop()
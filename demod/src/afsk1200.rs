@@ -1,121 +1,168 @@
-use crate::Demodulator;
+use crate::{
+    Demodulator, DemodulatorError,
+    gnuradio::{Flowgraph, FlowgraphConfig, FlowgraphError},
+};
+use sdr::block::SampleBlock;
 use std::{
-    io,
-    path::{Path, PathBuf},
-    process, thread,
-    time::Duration,
+    path::Path,
+    sync::{Arc, Mutex, atomic::AtomicU32},
 };
 
-// TODO: separate build and run steps
-// TODO: implement cleanup to kill child process
-
-// to where i send the samples for demodulation
-const SAMPLE_SINK: &str = "tcp://127.0.0.1:5556";
-// from where i read the demodulated bits
-const BIT_SOURCE: &str = "tcp://127.0.0.1:5557";
-
-#[derive(Debug)]
-pub enum DemodulatorError {
-    NoSuchFlowgraph,
-    GnuRadioProcess(io::Error),
-}
+/// The flowgraph decimates its input by this much before demodulating it.
+const DECIMATION: f64 = 40.0;
+const BAUD_RATE: f64 = 1200.0;
+const FREQ_DEVIATION: f64 = 1000.0;
 
+/// AFSK 1200 demodulator, run by a GNU Radio flowgraph.
 pub struct Afsk1200 {
-    #[allow(dead_code)]
-    python_proc: process::Child, // keep it alive
+    flowgraph: Arc<Mutex<Flowgraph>>,
+    /// Sample rate of the input, in Hz.
+    sample_rate: f64,
 }
 
 impl Afsk1200 {
-    /// Create a new `Afsk1200` demodulator, with a valid GNU Radio flowgraph, and run the process.
-    pub fn new(flowgraph_path: impl AsRef<Path>) -> Result<Self, DemodulatorError> {
-        if !flowgraph_path.as_ref().exists() {
-            return Err(DemodulatorError::NoSuchFlowgraph);
-        }
+    /// Create a new `Afsk1200` demodulator for samples at `sample_rate` Hz, with a valid GNU Radio
+    /// flowgraph, and run the process with the default interpreter and ports.
+    pub fn new(flowgraph_path: impl AsRef<Path>, sample_rate: f64) -> Result<Self, FlowgraphError> {
+        Self::with_config(
+            FlowgraphConfig::new(flowgraph_path)
+                .parameter("baud-rate", BAUD_RATE)
+                .parameter("freq-deviation", FREQ_DEVIATION),
+            sample_rate,
+        )
+    }
 
-        // let child = process::Command::new("python3")
-        let python_path =
-            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../modem/gnuradio/python");
-        dbg!(&python_path);
+    /// Create a new `Afsk1200` demodulator for samples at `sample_rate` Hz, running the flowgraph
+    /// as configured. Its `samp-rate` parameter is the rate after decimation, so it's derived from
+    /// `sample_rate` rather than configured.
+    pub fn with_config(config: FlowgraphConfig, sample_rate: f64) -> Result<Self, FlowgraphError> {
+        let flowgraph = Flowgraph::start(config.parameter("samp-rate", sample_rate / DECIMATION))?;
 
-        let child = process::Command::new(python_path)
-            .arg(flowgraph_path.as_ref())
-            .spawn()
-            .map_err(DemodulatorError::GnuRadioProcess)?;
+        Ok(Self {
+            flowgraph: Arc::new(Mutex::new(flowgraph)),
+            sample_rate,
+        })
+    }
 
-        Ok(Self { python_proc: child })
+    /// Times the flowgraph was restarted after crashing, as it goes on.
+    pub fn restarts(&self) -> Arc<AtomicU32> {
+        self.flowgraph.lock().unwrap().restarts()
     }
 }
 
 pub struct Afsk1200Iterator<I>
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
     inner: I,
-    zmq_pub: zmq::Socket,
-    zmq_sub: zmq::Socket,
-}
-
-impl<I> Afsk1200Iterator<I>
-where
-    I: Iterator<Item = Vec<f64>>,
-{
-    fn new(inner: I) -> Self {
-        let ctx = zmq::Context::new();
-
-        let pub_sock = ctx.socket(zmq::PUB).unwrap();
-        pub_sock.bind(SAMPLE_SINK).unwrap();
-
-        let sub_sock = ctx.socket(zmq::SUB).unwrap();
-        sub_sock.connect(BIT_SOURCE).unwrap();
-        sub_sock.set_subscribe(b"").unwrap();
-        sub_sock.set_rcvtimeo(1000).unwrap();
-
-        // This sleep is needed to give time to the GNU Radio process to subscribe.
-        // TODO: there must be a better way. Maybe if I do the wrapper crate I can handle this better.
-        thread::sleep(Duration::from_millis(200));
-
-        Self {
-            inner,
-            zmq_pub: pub_sock,
-            zmq_sub: sub_sock,
-        }
-    }
+    flowgraph: Arc<Mutex<Flowgraph>>,
+    sample_rate: f64,
+    /// A fatal error was output, so the output is over.
+    failed: bool,
 }
 
 impl<I> Iterator for Afsk1200Iterator<I>
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
-    type Item = Vec<bool>;
+    type Item = Result<Vec<bool>, DemodulatorError>;
 
-    // TODO: handle errors, timeout... Return Option<Result<...>> ?
+    /// Sends the next block to the flowgraph and returns the next bits it output. Times out if
+    /// there are none for a second, and ends once the input has and there are no bits left. Fails
+    /// on a block at another sample rate than the flowgraph was started for.
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(samples) = self.inner.next() {
-            let byte_slice = unsafe {
-                std::slice::from_raw_parts(
-                    samples.as_ptr() as *const u8,
-                    samples.len() * std::mem::size_of::<f64>(),
-                )
-            };
-
-            self.zmq_pub.send(byte_slice, 0).unwrap();
+        if self.failed {
+            return None;
         }
 
-        if let Ok(msg) = self.zmq_sub.recv_bytes(0) {
-            Some(msg.iter().map(|b| *b != 0).collect())
-        } else {
-            None
+        let mut flowgraph = self.flowgraph.lock().unwrap();
+
+        let input_ended = match self.inner.next() {
+            Some(block) => {
+                if block.sample_rate != self.sample_rate {
+                    self.failed = true;
+                    return Some(Err(DemodulatorError::SampleRate {
+                        expected: self.sample_rate,
+                        actual: block.sample_rate,
+                    }));
+                }
+                if let Err(err) = flowgraph.send(&block) {
+                    self.failed = true;
+                    return Some(Err(DemodulatorError::Flowgraph(err)));
+                }
+                false
+            }
+            None => true,
+        };
+
+        match flowgraph.recv_bits() {
+            Ok(Some(bits)) => Some(Ok(bits)),
+            Ok(None) if input_ended => None,
+            Ok(None) => Some(Err(DemodulatorError::Timeout)),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(DemodulatorError::Flowgraph(err)))
+            }
         }
     }
 }
 
 impl<I> Demodulator<I> for Afsk1200
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
     type Output = Afsk1200Iterator<I>;
 
     fn bits(&self, input: I) -> Self::Output {
-        Afsk1200Iterator::new(input)
+        Afsk1200Iterator {
+            inner: input,
+            flowgraph: self.flowgraph.clone(),
+            sample_rate: self.sample_rate,
+            failed: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdr::block::{Complex, Timestamp};
+    use std::{fs, path::PathBuf, process, thread, time::Duration};
+
+    #[test]
+    fn fails_on_samples_at_another_rate() {
+        let script = std::env::temp_dir().join(format!("afsk-rate-{}.sh", process::id()));
+        fs::write(&script, "sleep 30").unwrap();
+        let config = FlowgraphConfig {
+            interpreter: PathBuf::from("sh"),
+            sample_port: 35700,
+            bit_port: 35701,
+            ..FlowgraphConfig::new(script)
+        };
+
+        // Stands in for the flowgraph's ZMQ SUB source.
+        thread::spawn(|| {
+            thread::sleep(Duration::from_millis(200));
+            let socket = zmq::Context::new().socket(zmq::SUB).unwrap();
+            socket.connect("tcp://127.0.0.1:35700").unwrap();
+            socket.set_subscribe(b"").unwrap();
+            thread::sleep(Duration::from_secs(5));
+        });
+
+        let demod = Afsk1200::with_config(config, 1_920_000.0).unwrap();
+        let block = SampleBlock::new(
+            vec![Complex::new(0.0, 0.0); 16],
+            48_000.0,
+            0.0,
+            Timestamp::Hardware(Duration::ZERO),
+        );
+        let mut bits = demod.bits(vec![block.clone(), block].into_iter());
+
+        assert!(matches!(
+            bits.next(),
+            Some(Err(DemodulatorError::SampleRate { expected, actual }))
+                if expected == 1_920_000.0 && actual == 48_000.0
+        ));
+        assert!(bits.next().is_none());
     }
 }
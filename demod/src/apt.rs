@@ -0,0 +1,366 @@
+//! NOAA APT (automatic picture transmission) image decoder.
+//!
+//! APT is an FM carrier whose audio is a 2400 Hz subcarrier, amplitude modulated by the image at
+//! 4160 pixels per second. Each 0.5 s line carries two channels, each starting with its sync
+//! pattern:
+//!
+//! | Sync A | Space A | Image A | Telemetry A | Sync B | Space B | Image B | Telemetry B |
+//! |--------|---------|---------|-------------|--------|---------|---------|-------------|
+//! | 39     | 47      | 909     | 45          | 39     | 47      | 909     | 45          |
+//!
+//! The decoder FM demodulates the samples, takes the envelope of the subcarrier at the pixel rate
+//! and aligns every line on the sync patterns.
+
+use crate::filter::low_pass;
+use sdr::block::{Complex, SampleBlock};
+use std::{collections::VecDeque, f64::consts::PI, io};
+
+/// Pixels per line, both channels included.
+pub const LINE_PIXELS: usize = 2080;
+/// Pixels per second.
+const PIXEL_RATE: f64 = 4160.0;
+const SUBCARRIER_FREQUENCY: f64 = 2400.0;
+/// Cutoff of the envelope low-pass filter, in Hz.
+const ENVELOPE_CUTOFF: f64 = 2080.0;
+/// Length of the envelope low-pass filter, in seconds.
+const ENVELOPE_FILTER_DURATION: f64 = 0.004;
+/// Pixels the sync is searched for around where it's expected, once locked.
+const SYNC_TRACKING: usize = 8;
+/// Minimum sum of the normalized correlations with sync A and sync B for a line to be in sync.
+const SYNC_THRESHOLD: f32 = 1.0;
+
+/// Seven cycles of a 1040 Hz square wave.
+const SYNC_A: &str = "000011001100110011001100110011000000000";
+/// Seven pulses at 832 pulses per second.
+const SYNC_B: &str = "000011100111001110011100111001110011100";
+
+/// A decoded image, one row per line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AptImage {
+    /// Subcarrier envelope of every pixel, in arbitrary units.
+    pub lines: Vec<Vec<f32>>,
+    /// Lines aligned on their sync patterns. The others were placed where the previous line
+    /// predicted.
+    pub synced_lines: usize,
+}
+
+impl AptImage {
+    /// 8-bit grayscale pixels, stretching the 1st to 99th percentile of the envelope to the full
+    /// range.
+    pub fn to_grayscale(&self) -> Vec<u8> {
+        let mut sorted: Vec<f32> = self.lines.iter().flatten().copied().collect();
+        if sorted.is_empty() {
+            return Vec::new();
+        }
+        sorted.sort_by(f32::total_cmp);
+
+        let low = sorted[sorted.len() / 100];
+        let high = sorted[sorted.len() * 99 / 100];
+        let scale = if high > low {
+            255.0 / (high - low)
+        } else {
+            0.0
+        };
+
+        self.lines
+            .iter()
+            .flatten()
+            .map(|pixel| ((pixel - low) * scale).clamp(0.0, 255.0).round() as u8)
+            .collect()
+    }
+
+    /// Encodes the image as an 8-bit grayscale PNG.
+    pub fn to_png(&self) -> io::Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, LINE_PIXELS as u32, self.lines.len() as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.to_grayscale()))
+            .map_err(io::Error::other)?;
+
+        Ok(png)
+    }
+}
+
+/// Decodes APT lines from a sample stream, centered on the carrier.
+pub struct AptDecoder {
+    sample_rate: f64,
+    previous: Complex<f32>,
+    subcarrier_phase: f64,
+    /// Low-pass filter of the mixed down subcarrier, at the sample rate.
+    taps: Vec<f32>,
+    /// Latest mixed down samples, as many as taps.
+    mixed: VecDeque<Complex<f32>>,
+    /// Samples seen since the sample rate was set.
+    samples: u64,
+    /// Sample at which the next pixel falls.
+    next_pixel: f64,
+    /// Pixels not assigned to a line yet.
+    pixels: Vec<f32>,
+    /// Where the next line should start in `pixels`, once locked.
+    expected: Option<usize>,
+    image: AptImage,
+}
+
+impl Default for AptDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AptDecoder {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 0.0,
+            previous: Complex::new(0.0, 0.0),
+            subcarrier_phase: 0.0,
+            taps: Vec::new(),
+            mixed: VecDeque::new(),
+            samples: 0,
+            next_pixel: 0.0,
+            pixels: Vec::new(),
+            expected: None,
+            image: AptImage::default(),
+        }
+    }
+
+    /// Feeds a block of samples. The sample rate must be high enough for the FM signal, about
+    /// 40 kHz.
+    pub fn feed(&mut self, block: &SampleBlock) {
+        if block.sample_rate != self.sample_rate {
+            self.set_sample_rate(block.sample_rate);
+        }
+
+        // Lost samples shift the rest of the line; the sync search realigns it.
+        if block.discontinuity {
+            self.expected = None;
+        }
+
+        let step = self.sample_rate / PIXEL_RATE;
+        let half = self.taps.len() as u64 / 2;
+
+        for sample in &block.samples {
+            // FM demodulation, then the subcarrier mixed down to baseband.
+            let audio = (sample * self.previous.conj()).arg();
+            self.previous = *sample;
+
+            let mixed = Complex::from_polar(audio, -self.subcarrier_phase as f32);
+            self.subcarrier_phase = (self.subcarrier_phase
+                + 2.0 * PI * SUBCARRIER_FREQUENCY / self.sample_rate)
+                % (2.0 * PI);
+
+            if self.mixed.len() == self.taps.len() {
+                self.mixed.pop_front();
+            }
+            self.mixed.push_back(mixed);
+            self.samples += 1;
+
+            // The filter is centered on the pixel, so wait for the samples after it.
+            while self.mixed.len() == self.taps.len()
+                && self.next_pixel.round() as u64 + half < self.samples
+            {
+                let envelope: Complex<f32> = self
+                    .mixed
+                    .iter()
+                    .zip(&self.taps)
+                    .map(|(sample, tap)| sample * tap)
+                    .sum();
+                self.pixels.push(envelope.norm());
+                self.next_pixel += step;
+            }
+        }
+
+        self.assemble_lines();
+    }
+
+    /// Image decoded so far.
+    pub fn image(&self) -> &AptImage {
+        &self.image
+    }
+
+    pub fn into_image(self) -> AptImage {
+        self.image
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.taps = low_pass(ENVELOPE_CUTOFF, sample_rate, ENVELOPE_FILTER_DURATION);
+        self.mixed.clear();
+        self.samples = 0;
+        self.next_pixel = 0.0;
+        self.expected = None;
+    }
+
+    fn assemble_lines(&mut self) {
+        let sync_a = pattern(SYNC_A);
+        let sync_b = pattern(SYNC_B);
+
+        loop {
+            let (from, to) = match self.expected {
+                Some(expected) => (
+                    expected.saturating_sub(SYNC_TRACKING),
+                    expected + SYNC_TRACKING + 1,
+                ),
+                None => (0, LINE_PIXELS),
+            };
+            if self.pixels.len() < to + LINE_PIXELS {
+                return;
+            }
+
+            let (best, score) = (from..to)
+                .map(|start| {
+                    let score = correlation(&self.pixels[start..], &sync_a)
+                        + correlation(&self.pixels[start + LINE_PIXELS / 2..], &sync_b);
+                    (start, score)
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .expect("the search range isn't empty");
+
+            let start = if score >= SYNC_THRESHOLD {
+                self.image.synced_lines += 1;
+                best
+            } else if let Some(expected) = self.expected {
+                expected
+            } else {
+                // Not locked yet, e.g. before the satellite rises.
+                self.pixels.drain(..LINE_PIXELS);
+                continue;
+            };
+
+            self.image
+                .lines
+                .push(self.pixels[start..start + LINE_PIXELS].to_vec());
+
+            let consumed = start + LINE_PIXELS - SYNC_TRACKING;
+            self.pixels.drain(..consumed);
+            self.expected = Some(SYNC_TRACKING);
+        }
+    }
+}
+
+/// Zero mean pattern of a sync, from its pixels.
+fn pattern(pixels: &str) -> Vec<f32> {
+    let levels: Vec<f32> = pixels
+        .bytes()
+        .map(|pixel| if pixel == b'1' { 1.0 } else { -1.0 })
+        .collect();
+    let mean = levels.iter().sum::<f32>() / levels.len() as f32;
+
+    levels.iter().map(|level| level - mean).collect()
+}
+
+/// Normalized correlation between the start of `pixels` and `pattern`, from -1 to 1.
+fn correlation(pixels: &[f32], pattern: &[f32]) -> f32 {
+    let pixels = &pixels[..pattern.len()];
+    let mean = pixels.iter().sum::<f32>() / pixels.len() as f32;
+
+    let (mut product, mut energy) = (0.0, 0.0);
+    for (pixel, level) in pixels.iter().zip(pattern) {
+        product += (pixel - mean) * level;
+        energy += (pixel - mean) * (pixel - mean);
+    }
+    let pattern_energy: f32 = pattern.iter().map(|level| level * level).sum();
+
+    if energy > 0.0 {
+        product / (energy * pattern_energy).sqrt()
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdr::block::Timestamp;
+    use std::time::Duration;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+    /// Peak FM deviation of the carrier, in Hz.
+    const DEVIATION: f64 = 17_000.0;
+
+    /// A line whose image A is a left to right ramp and image B its opposite.
+    fn line() -> Vec<f64> {
+        let level = |pixel: u8| if pixel == b'1' { 1.0 } else { 0.0 };
+        let mut line: Vec<f64> = SYNC_A.bytes().map(level).collect();
+        line.extend([0.0; 47]);
+        line.extend((0..909).map(|x| x as f64 / 908.0));
+        line.extend([0.5; 45]);
+        line.extend(SYNC_B.bytes().map(level));
+        line.extend([1.0; 47]);
+        line.extend((0..909).map(|x| 1.0 - x as f64 / 908.0));
+        line.extend([0.5; 45]);
+
+        assert_eq!(line.len(), LINE_PIXELS);
+        line
+    }
+
+    /// `lines` lines, starting `offset` pixels into a line, as FM modulated APT.
+    fn modulate(lines: usize, offset: usize) -> Vec<SampleBlock> {
+        let pixels: Vec<f64> =
+            line().repeat(lines + 1)[offset..offset + lines * LINE_PIXELS].to_vec();
+        let n_samples = (pixels.len() as f64 * SAMPLE_RATE / PIXEL_RATE) as usize;
+
+        let mut phase = 0.0;
+        let samples: Vec<Complex<f32>> = (0..n_samples)
+            .map(|n| {
+                let t = n as f64 / SAMPLE_RATE;
+                let pixel = pixels[((t * PIXEL_RATE) as usize).min(pixels.len() - 1)];
+                let audio = (0.1 + 0.8 * pixel) * (2.0 * PI * SUBCARRIER_FREQUENCY * t).sin();
+                phase = (phase + 2.0 * PI * DEVIATION * audio / SAMPLE_RATE) % (2.0 * PI);
+                Complex::from_polar(1.0, phase as f32)
+            })
+            .collect();
+
+        samples
+            .chunks(4800)
+            .map(|chunk| {
+                SampleBlock::new(
+                    chunk.to_vec(),
+                    SAMPLE_RATE,
+                    137.1e6,
+                    Timestamp::Hardware(Duration::ZERO),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aligns_lines_on_the_sync() {
+        let mut decoder = AptDecoder::new();
+        for block in modulate(12, 777) {
+            decoder.feed(&block);
+        }
+        let image = decoder.into_image();
+
+        // The partial first line is skipped, as are the last ones missing the next sync.
+        assert!(image.lines.len() >= 9, "{}", image.lines.len());
+        assert_eq!(image.synced_lines, image.lines.len());
+
+        let gray = image.to_grayscale();
+        for line in gray.chunks(LINE_PIXELS) {
+            // Sync A high pixels, then the ramps of both channels.
+            assert!(line[5] > 200 && line[7] < 50, "{:?}", &line[..39]);
+            assert!(line[86 + 100] < line[86 + 450] && line[86 + 450] < line[86 + 800]);
+            assert!(line[1126 + 100] > line[1126 + 800]);
+        }
+    }
+
+    #[test]
+    fn encodes_png() {
+        let image = AptImage {
+            lines: vec![vec![0.0; LINE_PIXELS], vec![1.0; LINE_PIXELS]],
+            synced_lines: 2,
+        };
+        let png = image.to_png().unwrap();
+
+        assert_eq!(
+            png[..8],
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']
+        );
+        // IHDR: width and height.
+        assert_eq!(png[16..24], [0, 0, 0x08, 0x20, 0, 0, 0, 2]);
+    }
+}
@@ -0,0 +1,98 @@
+//! Internal state of the native demodulators at each symbol, for seeing why a downlink doesn't
+//! decode: the constellation and eye diagram show how well the symbols are separated, the timing
+//! error how well the symbol clock is locked, and the frequency and phase errors how far off the
+//! tuning is.
+//!
+//! Demodulators send them to a [`DiagnosticsTap`] if they were given one, and do nothing extra
+//! otherwise.
+
+use sdr::block::Complex;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+    mpsc::{self, TrySendError},
+};
+
+/// Queues the diagnostics of every symbol for a receiver. Sending never blocks: the symbols that
+/// don't fit in the queue are dropped and counted, and the demodulator goes on if the receiver is
+/// dropped.
+#[derive(Debug, Clone)]
+pub struct DiagnosticsTap {
+    tx: mpsc::SyncSender<SymbolDiagnostics>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl DiagnosticsTap {
+    /// A tap queuing up to `queue_len` symbols, and the receiver of the queue.
+    pub fn new(queue_len: usize) -> (Self, mpsc::Receiver<SymbolDiagnostics>) {
+        let (tx, rx) = mpsc::sync_channel(queue_len);
+        let tap = Self {
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+
+        (tap, rx)
+    }
+
+    pub fn send(&self, diagnostics: SymbolDiagnostics) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(diagnostics) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counter of the symbols dropped because the queue was full. It outlives the tap, to be read
+    /// once the demodulator is done.
+    pub fn dropped(&self) -> Arc<AtomicU64> {
+        self.dropped.clone()
+    }
+}
+
+/// State of a demodulator when it decided a symbol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolDiagnostics {
+    /// Symbols decided before this one.
+    pub index: u64,
+    /// Symbol after the filter, as a point of the constellation.
+    pub symbol: Complex<f32>,
+    /// Filter output at every sample since the previous symbol: a trace of the eye diagram.
+    pub trace: Vec<Complex<f32>>,
+    /// Correction of the symbol clock, in symbols.
+    pub timing_error: f64,
+    /// Carrier phase error, in radians. Only for coherent demodulators.
+    pub phase_error: Option<f64>,
+    /// Frequency of the signal from the frequency expected for the symbol, in Hz.
+    pub frequency_error: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(index: u64) -> SymbolDiagnostics {
+        SymbolDiagnostics {
+            index,
+            symbol: Complex::new(1.0, 0.0),
+            trace: Vec::new(),
+            timing_error: 0.0,
+            phase_error: None,
+            frequency_error: None,
+        }
+    }
+
+    #[test]
+    fn drops_and_counts_the_symbols_that_dont_fit() {
+        let (tap, rx) = DiagnosticsTap::new(2);
+        let dropped = tap.dropped();
+
+        (0..5).for_each(|index| tap.send(symbol(index)));
+
+        let received: Vec<u64> = rx.try_iter().map(|symbol| symbol.index).collect();
+        assert_eq!(received, vec![0, 1]);
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+
+        // Without a receiver, symbols are neither queued nor counted as dropped.
+        drop(rx);
+        tap.send(symbol(5));
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    }
+}
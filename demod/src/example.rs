@@ -1,4 +1,5 @@
-use crate::Demodulator;
+use crate::{Demodulator, DemodulatorError};
+use sdr::block::SampleBlock;
 
 pub struct ExampleDemod {}
 
@@ -16,16 +17,16 @@ impl ExampleDemod {
 
 pub struct ExampleDemodIterator<I>
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
     inner: I,
 }
 
 impl<I> Iterator for ExampleDemodIterator<I>
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
-    type Item = Vec<bool>;
+    type Item = Result<Vec<bool>, DemodulatorError>;
 
     // Returns a bit every 10 sample reads.
     fn next(&mut self) -> Option<Self::Item> {
@@ -33,13 +34,13 @@ where
             self.inner.next()?;
         }
 
-        Some(vec![true])
+        Some(Ok(vec![true]))
     }
 }
 
 impl<I> Demodulator<I> for ExampleDemod
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
     type Output = ExampleDemodIterator<I>;
 
@@ -51,15 +52,23 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sdr::block::{Complex, Timestamp};
+    use std::time::Duration;
 
     #[test]
     fn example() {
         let demodulator = ExampleDemod {};
-        let samples = vec![vec![1f64]; 20];
+        let block = SampleBlock::new(
+            vec![Complex::new(1.0, 0.0)],
+            48_000.0,
+            0.0,
+            Timestamp::Hardware(Duration::ZERO),
+        );
+        let samples = vec![block; 20];
         let mut bits = demodulator.bits(samples.into_iter());
 
-        assert_eq!(bits.next(), Some(vec![true]));
-        assert_eq!(bits.next(), Some(vec![true]));
-        assert_eq!(bits.next(), None);
+        assert_eq!(bits.next().unwrap().unwrap(), vec![true]);
+        assert_eq!(bits.next().unwrap().unwrap(), vec![true]);
+        assert!(bits.next().is_none());
     }
 }
@@ -0,0 +1,25 @@
+//! Filters shared by the demodulators.
+
+use std::f64::consts::PI;
+
+/// Blackman windowed sinc low-pass filter, with unit DC gain.
+pub(crate) fn low_pass(cutoff: f64, sample_rate: f64, duration: f64) -> Vec<f32> {
+    let half = ((duration * sample_rate / 2.0).round() as i64).max(1);
+    let fc = cutoff / sample_rate;
+
+    let taps: Vec<f64> = (-half..=half)
+        .map(|k| {
+            let sinc = if k == 0 {
+                2.0 * fc
+            } else {
+                (2.0 * PI * fc * k as f64).sin() / (PI * k as f64)
+            };
+            let x = (k + half) as f64 / (2 * half) as f64;
+            let window = 0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos();
+            sinc * window
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+
+    taps.iter().map(|tap| (tap / sum) as f32).collect()
+}
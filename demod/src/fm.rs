@@ -0,0 +1,347 @@
+//! Analog FM audio demodulator, for voice and other audio downlinks (e.g. SSTV) that are recorded
+//! rather than decoded.
+//!
+//! The samples are FM demodulated, low-pass filtered to the audio bandwidth and resampled to
+//! [`AUDIO_RATE`], then DC blocked, optionally de-emphasized and muted by the squelch. Muted and
+//! lost samples are output as silence, so that the audio keeps the timing of the samples.
+
+use crate::filter::low_pass;
+use sdr::block::{Complex, SampleBlock, Timestamp};
+use std::{collections::VecDeque, f64::consts::PI, time::Duration};
+
+/// Sample rate of the audio, in Hz.
+pub const AUDIO_RATE: f64 = 48_000.0;
+/// Length of the audio low-pass filter, in seconds.
+const AUDIO_FILTER_DURATION: f64 = 0.001;
+/// Cutoff of the DC blocker, in Hz.
+const DC_CUTOFF: f64 = 20.0;
+/// Time constant of the signal power measured by the squelch, in seconds.
+const SQUELCH_TIME_CONSTANT: f64 = 0.01;
+/// How far below its threshold the signal must fall for the squelch to close again, in dB.
+const SQUELCH_HYSTERESIS: f32 = 3.0;
+/// Longest run of lost samples replaced by silence. Longer gaps are assumed to be bogus
+/// timestamps.
+const MAX_GAP: Duration = Duration::from_secs(60);
+
+/// Parameters of an FM audio downlink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FmParams {
+    /// Peak deviation, in Hz. Audio at this deviation comes out at full scale.
+    pub deviation: f64,
+    /// Highest audio frequency kept, in Hz.
+    pub audio_bandwidth: f64,
+    /// Time constant of the de-emphasis, in seconds (e.g. 75 µs). Not de-emphasized if not set.
+    pub deemphasis: Option<f64>,
+    /// Signal power under which the audio is muted, in dBFS. Never muted if not set.
+    pub squelch: Option<f32>,
+}
+
+impl FmParams {
+    /// Narrowband FM voice, as on amateur satellites and the ISS: 5 kHz deviation, 4 kHz of
+    /// audio, without de-emphasis or squelch.
+    pub fn narrowband() -> Self {
+        Self {
+            deviation: 5_000.0,
+            audio_bandwidth: 4_000.0,
+            deemphasis: None,
+            squelch: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FmError {
+    Deviation(f64),
+    /// The audio bandwidth must be below half the audio sample rate.
+    AudioBandwidth(f64),
+    Deemphasis(f64),
+}
+
+/// Demodulates FM into audio at [`AUDIO_RATE`], as described by [`FmParams`].
+pub struct FmDemodulator {
+    params: FmParams,
+    sample_rate: f64,
+    previous: Complex<f32>,
+    /// Low-pass filter of the demodulated audio, at the sample rate.
+    taps: Vec<f32>,
+    /// Latest demodulated samples, as many as taps.
+    demodulated: VecDeque<f32>,
+    /// Samples seen since the sample rate was set.
+    samples: u64,
+    /// Sample at which the next audio sample falls.
+    next_audio: f64,
+    /// Previous input and output of the DC blocker.
+    dc: (f32, f32),
+    deemphasized: f32,
+    /// Smoothed signal power, linear.
+    power: f32,
+    open: bool,
+    /// Timestamp the next block should have if no samples are lost.
+    next_timestamp: Option<Timestamp>,
+}
+
+impl FmDemodulator {
+    pub fn new(params: FmParams) -> Result<Self, FmError> {
+        if params.deviation.is_nan() || params.deviation <= 0.0 {
+            return Err(FmError::Deviation(params.deviation));
+        }
+        if !(params.audio_bandwidth > 0.0 && params.audio_bandwidth < AUDIO_RATE / 2.0) {
+            return Err(FmError::AudioBandwidth(params.audio_bandwidth));
+        }
+        if let Some(deemphasis) = params.deemphasis
+            && (deemphasis.is_nan() || deemphasis <= 0.0)
+        {
+            return Err(FmError::Deemphasis(deemphasis));
+        }
+
+        Ok(Self {
+            open: params.squelch.is_none(),
+            params,
+            sample_rate: 0.0,
+            previous: Complex::new(0.0, 0.0),
+            taps: Vec::new(),
+            demodulated: VecDeque::new(),
+            samples: 0,
+            next_audio: 0.0,
+            dc: (0.0, 0.0),
+            deemphasized: 0.0,
+            power: 0.0,
+            next_timestamp: None,
+        })
+    }
+
+    /// Demodulates a block of samples into audio, from -1 to 1 at the peak deviation. The sample
+    /// rate should be at least [`AUDIO_RATE`].
+    pub fn feed(&mut self, block: &SampleBlock) -> Vec<f32> {
+        let mut audio = Vec::new();
+
+        if block.sample_rate != self.sample_rate {
+            self.set_sample_rate(block.sample_rate);
+        } else if block.discontinuity {
+            // Silence in place of the lost samples, if the timestamps tell how many.
+            if let Some(gap) = self
+                .next_timestamp
+                .and_then(|expected| elapsed(expected, block.timestamp))
+                .filter(|gap| *gap <= MAX_GAP)
+            {
+                audio.resize((gap.as_secs_f64() * AUDIO_RATE).round() as usize, 0.0);
+            }
+            self.set_sample_rate(block.sample_rate);
+        }
+        self.next_timestamp = Some(block.timestamp.offset(block.duration()));
+
+        let step = self.sample_rate / AUDIO_RATE;
+        let half = self.taps.len() as u64 / 2;
+        let scale = (self.sample_rate / (2.0 * PI * self.params.deviation)) as f32;
+        let power_alpha = (1.0 - (-1.0 / (SQUELCH_TIME_CONSTANT * self.sample_rate)).exp()) as f32;
+
+        for sample in &block.samples {
+            self.demodulated
+                .push_back((sample * self.previous.conj()).arg() * scale);
+            if self.demodulated.len() > self.taps.len() {
+                self.demodulated.pop_front();
+            }
+            self.previous = *sample;
+            self.power += power_alpha * (sample.norm_sqr() - self.power);
+            self.samples += 1;
+
+            // The filter is centered on the audio sample, so wait for the samples after it.
+            while self.demodulated.len() == self.taps.len()
+                && self.next_audio.round() as u64 + half < self.samples
+            {
+                let filtered: f32 = self
+                    .demodulated
+                    .iter()
+                    .zip(&self.taps)
+                    .map(|(sample, tap)| sample * tap)
+                    .sum();
+                audio.push(self.post_process(filtered));
+                self.next_audio += step;
+            }
+        }
+
+        audio
+    }
+
+    /// Whether the squelch lets the audio through.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// DC blocks, de-emphasizes and squelches an audio sample.
+    fn post_process(&mut self, audio: f32) -> f32 {
+        let r = (1.0 - 2.0 * PI * DC_CUTOFF / AUDIO_RATE) as f32;
+        let (previous_in, previous_out) = self.dc;
+        let blocked = audio - previous_in + r * previous_out;
+        self.dc = (audio, blocked);
+        let mut audio = blocked;
+
+        if let Some(deemphasis) = self.params.deemphasis {
+            let alpha = (1.0 - (-1.0 / (deemphasis * AUDIO_RATE)).exp()) as f32;
+            self.deemphasized += alpha * (audio - self.deemphasized);
+            audio = self.deemphasized;
+        }
+
+        if let Some(threshold) = self.params.squelch {
+            let power = 10.0 * self.power.log10();
+            if power >= threshold {
+                self.open = true;
+            } else if power < threshold - SQUELCH_HYSTERESIS {
+                self.open = false;
+            }
+        }
+
+        if self.open { audio } else { 0.0 }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+        self.taps = low_pass(
+            self.params.audio_bandwidth,
+            sample_rate,
+            AUDIO_FILTER_DURATION,
+        );
+        self.previous = Complex::new(0.0, 0.0);
+        self.demodulated.clear();
+        self.samples = 0;
+        self.next_audio = 0.0;
+    }
+}
+
+/// Time from `from` to `to`, if both come from the same clock and `to` isn't earlier.
+fn elapsed(from: Timestamp, to: Timestamp) -> Option<Duration> {
+    match (from, to) {
+        (Timestamp::Hardware(from), Timestamp::Hardware(to)) => to.checked_sub(from),
+        (Timestamp::Monotonic(from), Timestamp::Monotonic(to)) => to.checked_duration_since(from),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    const SAMPLE_RATE: f64 = 240_000.0;
+
+    /// `duration` seconds of a `frequency` Hz tone at `deviation` Hz, with an amplitude of
+    /// `amplitude` and noise of unit power, starting at `start` seconds.
+    fn modulate(
+        frequency: f64,
+        deviation: f64,
+        amplitude: f32,
+        duration: f64,
+        start: f64,
+    ) -> SampleBlock {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut gaussian = move || {
+            let u1: f64 = 1.0 - rng.random::<f64>();
+            let u2: f64 = rng.random();
+            ((-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos() * 0.5f64.sqrt()) as f32
+        };
+        let mut phase = 0.0;
+
+        let samples = (0..(duration * SAMPLE_RATE) as usize)
+            .map(|n| {
+                let t = n as f64 / SAMPLE_RATE;
+                phase = (phase
+                    + 2.0 * PI * deviation * (2.0 * PI * frequency * t).sin() / SAMPLE_RATE)
+                    % (2.0 * PI);
+                Complex::from_polar(amplitude, phase as f32) + Complex::new(gaussian(), gaussian())
+            })
+            .collect();
+
+        SampleBlock::new(
+            samples,
+            SAMPLE_RATE,
+            145.8e6,
+            Timestamp::Hardware(Duration::from_secs_f64(start)),
+        )
+    }
+
+    /// Frequency of the tone in `audio`, from its zero crossings.
+    fn tone_frequency(audio: &[f32]) -> f64 {
+        let crossings = audio
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f64 * AUDIO_RATE / audio.len() as f64
+    }
+
+    #[test]
+    fn demodulates_a_tone() {
+        let mut fm = FmDemodulator::new(FmParams::narrowband()).unwrap();
+        let audio = fm.feed(&modulate(1_000.0, 3_000.0, 30.0, 1.0, 0.0));
+
+        // Only the filter delay is missing.
+        assert!(
+            audio.len() > 47_900 && audio.len() <= 48_000,
+            "{}",
+            audio.len()
+        );
+
+        let settled = &audio[4_800..];
+        let frequency = tone_frequency(settled);
+        assert!((frequency - 1_000.0).abs() < 5.0, "{}", frequency);
+
+        // 3 kHz out of a 5 kHz peak deviation.
+        let peak = settled
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - 0.6).abs() < 0.05, "{}", peak);
+    }
+
+    #[test]
+    fn squelches_noise() {
+        let params = FmParams {
+            squelch: Some(10.0),
+            ..FmParams::narrowband()
+        };
+        let mut fm = FmDemodulator::new(params).unwrap();
+
+        // Noise alone is at 0 dBFS.
+        let noise = fm.feed(&modulate(1_000.0, 3_000.0, 0.0, 0.5, 0.0));
+        assert!(noise.iter().all(|sample| *sample == 0.0));
+        assert!(!fm.is_open());
+
+        let signal = fm.feed(&modulate(1_000.0, 3_000.0, 10.0, 0.5, 0.5));
+        assert!(fm.is_open());
+        assert!((tone_frequency(&signal[2_400..]) - 1_000.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn fills_lost_samples_with_silence() {
+        let mut fm = FmDemodulator::new(FmParams::narrowband()).unwrap();
+        let first = fm.feed(&modulate(1_000.0, 3_000.0, 30.0, 0.5, 0.0));
+
+        // Half a second lost after the first block.
+        let mut block = modulate(1_000.0, 3_000.0, 30.0, 0.5, 1.0);
+        block.discontinuity = true;
+        let second = fm.feed(&block);
+
+        assert!(second[..24_000].iter().all(|sample| *sample == 0.0));
+        assert!(second[24_000..].iter().any(|sample| *sample != 0.0));
+        assert!((first.len() + second.len()).abs_diff(72_000) < 100);
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        let no_deviation = FmParams {
+            deviation: 0.0,
+            ..FmParams::narrowband()
+        };
+        assert_eq!(
+            FmDemodulator::new(no_deviation).err(),
+            Some(FmError::Deviation(0.0))
+        );
+
+        let too_wide = FmParams {
+            audio_bandwidth: 30_000.0,
+            ..FmParams::narrowband()
+        };
+        assert_eq!(
+            FmDemodulator::new(too_wide).err(),
+            Some(FmError::AudioBandwidth(30_000.0))
+        );
+    }
+}
@@ -0,0 +1,585 @@
+//! Parameterized M-FSK demodulator, for downlinks that are plain 2-FSK or 4-FSK at arbitrary
+//! shifts and baud rates.
+//!
+//! Each tone stands for a symbol of `log2(M)` bits. Symbols are detected on a window one symbol
+//! long that slides over the samples, and a symbol clock decides when to take a decision. The
+//! clock is nudged every time the detected tone changes, since the window is then half way over
+//! a symbol boundary.
+//!
+//! With a [`DiagnosticsTap`], the detector output is also given for every symbol, from -1 at the
+//! lowest tone to 1 at the highest, along with the timing and frequency errors. FSK is detected
+//! non-coherently, so there's no phase error.
+
+use crate::{
+    Demodulator, DemodulatorError,
+    diagnostics::{DiagnosticsTap, SymbolDiagnostics},
+};
+use sdr::block::{Complex, SampleBlock};
+use std::f64::consts::PI;
+
+/// Fraction of the timing error corrected at every tone change.
+const TIMING_GAIN: f64 = 0.05;
+
+/// How the tone of each symbol is detected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detector {
+    /// Averages the instantaneous frequency over the symbol and picks the nearest tone. Cheap,
+    /// but falls apart at low SNR.
+    Discriminator,
+    /// Correlates the symbol with every tone and picks the strongest, i.e. a non-coherent
+    /// matched filter. Costs a correlator per tone, but holds up several dB better in noise.
+    MatchedFilter,
+}
+
+/// Parameters of an M-FSK downlink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FskParams {
+    /// Tone frequencies relative to the tuned frequency, in Hz, from the lowest to the highest.
+    pub tones: Vec<f64>,
+    pub baud: f64,
+    /// Symbol sent on each tone, in the same order as `tones`. Its bits are output most
+    /// significant first.
+    pub mapping: Vec<u8>,
+    pub detector: Detector,
+}
+
+impl FskParams {
+    /// 2-FSK with tones `shift` Hz apart around the tuned frequency. The upper tone is a `1`.
+    pub fn binary(baud: f64, shift: f64, detector: Detector) -> Self {
+        Self {
+            tones: vec![-shift / 2.0, shift / 2.0],
+            baud,
+            mapping: vec![0, 1],
+            detector,
+        }
+    }
+
+    /// 4-FSK with tones at ±`deviation` and ±3 `deviation` Hz from the tuned frequency, Gray
+    /// coded as in DMR and P25: `11`, `10`, `00` and `01` from the lowest tone to the highest.
+    pub fn quaternary(baud: f64, deviation: f64, detector: Detector) -> Self {
+        Self {
+            tones: vec![-3.0 * deviation, -deviation, deviation, 3.0 * deviation],
+            baud,
+            mapping: vec![0b11, 0b10, 0b00, 0b01],
+            detector,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FskError {
+    /// The number of tones must be a power of two, and at least 2.
+    ToneCount(usize),
+    UnsortedTones,
+    /// The mapping must assign every symbol to exactly one tone.
+    Mapping,
+    Baud(f64),
+}
+
+/// Demodulates M-FSK into bits, as described by [`FskParams`].
+#[derive(Debug, Clone)]
+pub struct Fsk {
+    params: FskParams,
+    tap: Option<DiagnosticsTap>,
+}
+
+impl Fsk {
+    pub fn new(params: FskParams) -> Result<Self, FskError> {
+        let tones = params.tones.len();
+        if tones < 2 || !tones.is_power_of_two() || tones > 256 {
+            return Err(FskError::ToneCount(tones));
+        }
+
+        if params.tones.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(FskError::UnsortedTones);
+        }
+
+        let mut symbols: Vec<usize> = params.mapping.iter().map(|s| *s as usize).collect();
+        symbols.sort_unstable();
+        if symbols != (0..tones).collect::<Vec<_>>() {
+            return Err(FskError::Mapping);
+        }
+
+        if params.baud.is_nan() || params.baud <= 0.0 {
+            return Err(FskError::Baud(params.baud));
+        }
+
+        Ok(Self { params, tap: None })
+    }
+
+    pub fn params(&self) -> &FskParams {
+        &self.params
+    }
+
+    /// Sends the diagnostics of every symbol to `tap`.
+    pub fn with_diagnostics(mut self, tap: DiagnosticsTap) -> Self {
+        self.tap = Some(tap);
+        self
+    }
+}
+
+/// Detection state for a given sample rate.
+struct SymbolDetector {
+    sample_rate: f64,
+    samples_per_symbol: f64,
+    /// Position of the newest sample in the window ring buffers.
+    index: usize,
+    /// Samples in the window, up to its length.
+    filled: usize,
+    /// Discriminator: instantaneous frequency of each sample in the window, and their sum.
+    previous: Complex<f64>,
+    frequencies: Vec<f64>,
+    frequency_sum: f64,
+    /// Matched filter: for each tone, the phase of its reference, the products of the reference
+    /// and each sample in the window, and their sum.
+    phases: Vec<f64>,
+    products: Vec<Vec<Complex<f64>>>,
+    correlations: Vec<Complex<f64>>,
+    /// Symbol clock, in symbols. A decision is taken when it wraps.
+    clock: f64,
+    last_tone: Option<usize>,
+    /// Diagnostics are kept: the detector output since the last decision, and the timing error
+    /// at the latest tone change since then.
+    diagnostics: bool,
+    trace: Vec<Complex<f32>>,
+    timing_error: f64,
+}
+
+impl SymbolDetector {
+    fn new(params: &FskParams, sample_rate: f64, diagnostics: bool) -> Self {
+        let samples_per_symbol = sample_rate / params.baud;
+        let window = (samples_per_symbol.round() as usize).max(1);
+        let (mut frequencies, products) = match params.detector {
+            Detector::Discriminator => (vec![0.0; window], Vec::new()),
+            Detector::MatchedFilter => (
+                Vec::new(),
+                vec![vec![Complex::new(0.0, 0.0); window]; params.tones.len()],
+            ),
+        };
+        // The frequency errors come from the discriminator.
+        if diagnostics {
+            frequencies = vec![0.0; window];
+        }
+
+        Self {
+            sample_rate,
+            samples_per_symbol,
+            index: 0,
+            filled: 0,
+            previous: Complex::new(0.0, 0.0),
+            frequencies,
+            frequency_sum: 0.0,
+            phases: vec![0.0; params.tones.len()],
+            correlations: vec![Complex::new(0.0, 0.0); products.len()],
+            products,
+            clock: 0.0,
+            last_tone: None,
+            diagnostics,
+            trace: Vec::new(),
+            timing_error: 0.0,
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.frequencies
+            .len()
+            .max(self.products.first().map_or(0, Vec::len))
+    }
+
+    /// Pushes a sample, returning the tone of the symbol that ended with it, if any.
+    fn push(&mut self, params: &FskParams, sample: Complex<f32>) -> Option<usize> {
+        let sample = Complex::new(sample.re as f64, sample.im as f64);
+        let i = self.index;
+
+        if !self.frequencies.is_empty() {
+            let frequency = (sample * self.previous.conj()).arg() * self.sample_rate / (2.0 * PI);
+            self.previous = sample;
+            self.frequency_sum += frequency - self.frequencies[i];
+            self.frequencies[i] = frequency;
+        }
+        if !self.products.is_empty() {
+            for (k, tone) in params.tones.iter().enumerate() {
+                let product = sample * Complex::from_polar(1.0, -self.phases[k]);
+                self.phases[k] =
+                    (self.phases[k] + 2.0 * PI * tone / self.sample_rate).rem_euclid(2.0 * PI);
+                self.correlations[k] += product - self.products[k][i];
+                self.products[k][i] = product;
+            }
+        }
+
+        let window = self.window();
+        self.index = (i + 1) % window;
+        if self.filled < window {
+            self.filled += 1;
+            return None;
+        }
+
+        let tone = self.detect(params);
+        if self.last_tone.is_some_and(|last| last != tone) {
+            self.timing_error = 0.5 - self.clock;
+            self.clock += TIMING_GAIN * self.timing_error;
+        }
+        if self.diagnostics {
+            self.trace.push(Complex::new(self.soft(params) as f32, 0.0));
+        }
+        self.last_tone = Some(tone);
+
+        self.clock += 1.0 / self.samples_per_symbol;
+        if self.clock >= 1.0 {
+            self.clock -= 1.0;
+            Some(tone)
+        } else {
+            None
+        }
+    }
+
+    /// Detected frequency over the current window, from -1 at the lowest tone to 1 at the
+    /// highest. The matched filter weighs the tones by the power of their correlations.
+    fn soft(&self, params: &FskParams) -> f64 {
+        let frequency = match params.detector {
+            Detector::Discriminator => self.mean_frequency(),
+            Detector::MatchedFilter => {
+                let powers = self.correlations.iter().map(|c| c.norm_sqr());
+                let total: f64 = powers.clone().sum();
+                let weighted: f64 = powers.zip(&params.tones).map(|(p, tone)| p * tone).sum();
+                weighted / total.max(f64::MIN_POSITIVE)
+            }
+        };
+
+        let (lowest, highest) = (params.tones[0], params.tones[params.tones.len() - 1]);
+        (2.0 * frequency - lowest - highest) / (highest - lowest)
+    }
+
+    fn mean_frequency(&self) -> f64 {
+        self.frequency_sum / self.frequencies.len() as f64
+    }
+
+    /// Diagnostics of the symbol just decided as `tone`, starting over for the next one.
+    fn take_diagnostics(
+        &mut self,
+        params: &FskParams,
+        tone: usize,
+        index: u64,
+    ) -> SymbolDiagnostics {
+        let trace = std::mem::take(&mut self.trace);
+
+        SymbolDiagnostics {
+            index,
+            symbol: trace.last().copied().unwrap_or_default(),
+            trace,
+            timing_error: std::mem::take(&mut self.timing_error),
+            phase_error: None,
+            frequency_error: Some(self.mean_frequency() - params.tones[tone]),
+        }
+    }
+
+    /// Tone over the current window.
+    fn detect(&self, params: &FskParams) -> usize {
+        match params.detector {
+            Detector::Discriminator => nearest(&params.tones, self.mean_frequency()),
+            Detector::MatchedFilter => self
+                .correlations
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.norm_sqr().total_cmp(&b.1.norm_sqr()))
+                .map(|(k, _)| k)
+                .expect("there are at least two tones"),
+        }
+    }
+}
+
+fn nearest(tones: &[f64], frequency: f64) -> usize {
+    tones
+        .iter()
+        .enumerate()
+        .min_by(|a, b| (a.1 - frequency).abs().total_cmp(&(b.1 - frequency).abs()))
+        .map(|(k, _)| k)
+        .expect("there are at least two tones")
+}
+
+pub struct FskIterator<I>
+where
+    I: Iterator<Item = SampleBlock>,
+{
+    inner: I,
+    params: FskParams,
+    bits_per_symbol: u32,
+    detector: Option<SymbolDetector>,
+    tap: Option<DiagnosticsTap>,
+    /// Symbols decided so far.
+    symbols: u64,
+}
+
+impl<I> Iterator for FskIterator<I>
+where
+    I: Iterator<Item = SampleBlock>,
+{
+    type Item = Result<Vec<bool>, DemodulatorError>;
+
+    /// Returns the bits demodulated from the next blocks, skipping blocks that didn't complete a
+    /// symbol. Only ever outputs `Ok`: demodulation runs in process, so there's no flowgraph to
+    /// fail or time out, and a change of sample rate starts it over instead of failing with
+    /// [`DemodulatorError::SampleRate`].
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let block = self.inner.next()?;
+
+            // Start over after lost samples or a change of sample rate.
+            if block.discontinuity
+                || self
+                    .detector
+                    .as_ref()
+                    .is_none_or(|detector| detector.sample_rate != block.sample_rate)
+            {
+                self.detector = Some(SymbolDetector::new(
+                    &self.params,
+                    block.sample_rate,
+                    self.tap.is_some(),
+                ));
+            }
+            let detector = self.detector.as_mut().expect("just created");
+
+            let mut bits = Vec::new();
+            for sample in &block.samples {
+                if let Some(tone) = detector.push(&self.params, *sample) {
+                    if let Some(tap) = &self.tap {
+                        tap.send(detector.take_diagnostics(&self.params, tone, self.symbols));
+                    }
+                    self.symbols += 1;
+
+                    let symbol = self.params.mapping[tone];
+                    bits.extend(
+                        (0..self.bits_per_symbol)
+                            .rev()
+                            .map(|bit| (symbol >> bit) & 1 == 1),
+                    );
+                }
+            }
+
+            if !bits.is_empty() {
+                return Some(Ok(bits));
+            }
+        }
+    }
+}
+
+impl<I> Demodulator<I> for Fsk
+where
+    I: Iterator<Item = SampleBlock>,
+{
+    type Output = FskIterator<I>;
+
+    fn bits(&self, input: I) -> Self::Output {
+        FskIterator {
+            inner: input,
+            params: self.params.clone(),
+            bits_per_symbol: self.params.tones.len().trailing_zeros(),
+            detector: None,
+            tap: self.tap.clone(),
+            symbols: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use sdr::{block::Timestamp, modulation::Modulation};
+    use std::time::Duration;
+
+    /// Continuous phase FSK of `symbols`, as indices into `tones`, with complex white noise of
+    /// `noise_power`, split into blocks of 1000 samples.
+    fn modulate(
+        symbols: &[usize],
+        tones: &[f64],
+        baud: f64,
+        sample_rate: f64,
+        noise_power: f64,
+    ) -> Vec<SampleBlock> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let std = (noise_power / 2.0).sqrt();
+        let mut gaussian = move || {
+            let u1: f64 = 1.0 - rng.random::<f64>();
+            let u2: f64 = rng.random();
+            ((-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos() * std) as f32
+        };
+
+        let n_samples = (symbols.len() as f64 * sample_rate / baud) as usize;
+        let mut phase = 0.0;
+        let samples: Vec<Complex<f32>> = (0..n_samples)
+            .map(|n| {
+                let symbol = symbols[(n as f64 * baud / sample_rate) as usize];
+                phase = (phase + 2.0 * PI * tones[symbol] / sample_rate) % (2.0 * PI);
+                Complex::from_polar(1.0, phase as f32) + Complex::new(gaussian(), gaussian())
+            })
+            .collect();
+
+        blocks(samples, sample_rate)
+    }
+
+    fn blocks(samples: Vec<Complex<f32>>, sample_rate: f64) -> Vec<SampleBlock> {
+        samples
+            .chunks(1000)
+            .map(|chunk| {
+                SampleBlock::new(
+                    chunk.to_vec(),
+                    sample_rate,
+                    435e6,
+                    Timestamp::Hardware(Duration::ZERO),
+                )
+            })
+            .collect()
+    }
+
+    fn random_bits(n: usize) -> Vec<bool> {
+        let mut rng = StdRng::seed_from_u64(2);
+        (0..n).map(|_| rng.random()).collect()
+    }
+
+    /// Bit errors of `received` against `sent`, once the clock settled, at the alignment with
+    /// the fewest errors.
+    fn bit_errors(sent: &[bool], received: &[bool]) -> usize {
+        let received = &received[64..];
+
+        (0..128)
+            .map(|offset| {
+                sent[offset..]
+                    .iter()
+                    .zip(received)
+                    .filter(|(a, b)| a != b)
+                    .count()
+            })
+            .min()
+            .unwrap()
+    }
+
+    fn demodulate(fsk: &Fsk, blocks: Vec<SampleBlock>) -> Vec<bool> {
+        fsk.bits(blocks.into_iter())
+            .flat_map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn binary_fsk_with_fractional_samples_per_symbol() {
+        let bits = random_bits(2000);
+        // 10.4 samples per symbol, with the Gaussian filter rounding the transitions.
+        let samples = Modulation::Gfsk {
+            baud: 4800.0,
+            deviation: 2400.0,
+            bt: 1.0,
+        }
+        .modulate(&bits, 50_000.0);
+
+        for detector in [Detector::Discriminator, Detector::MatchedFilter] {
+            let fsk = Fsk::new(FskParams::binary(4800.0, 4800.0, detector)).unwrap();
+            let received = demodulate(&fsk, blocks(samples.clone(), 50_000.0));
+
+            assert!(received.len() >= bits.len() - 4, "{:?}", detector);
+            assert_eq!(bit_errors(&bits, &received), 0, "{:?}", detector);
+        }
+    }
+
+    #[test]
+    fn diagnostics_of_every_symbol() {
+        let bits = random_bits(500);
+        let symbols: Vec<usize> = bits.iter().map(|bit| *bit as usize).collect();
+        // Tuned 100 Hz low.
+        let tones = [-400.0, 600.0];
+        let blocks = modulate(&symbols, &tones, 1200.0, 48_000.0, 0.0);
+
+        for detector in [Detector::Discriminator, Detector::MatchedFilter] {
+            let (tap, diagnostics) = DiagnosticsTap::new(1024);
+            let fsk = Fsk::new(FskParams::binary(1200.0, 1000.0, detector))
+                .unwrap()
+                .with_diagnostics(tap);
+            let received = demodulate(&fsk, blocks.clone());
+            let diagnostics: Vec<SymbolDiagnostics> = diagnostics.try_iter().collect();
+
+            assert_eq!(diagnostics.len(), received.len(), "{:?}", detector);
+            // Past the first symbols, while the clock locks.
+            for (symbol, bit) in diagnostics[64..].iter().zip(&received[64..]) {
+                assert_eq!(symbol.symbol.re > 0.0, *bit, "{:?}", detector);
+                assert!(symbol.symbol.re.abs() > 0.5, "{:?} {:?}", detector, symbol);
+                assert!((symbol.trace.len() as i32 - 40).abs() <= 1);
+                assert!(symbol.timing_error.abs() < 0.25, "{:?}", symbol);
+                assert!((symbol.frequency_error.unwrap() - 100.0).abs() < 50.0);
+                assert_eq!(symbol.phase_error, None);
+            }
+        }
+    }
+
+    #[test]
+    fn quaternary_fsk_with_gray_mapping() {
+        let bits = random_bits(4000);
+        let params = FskParams::quaternary(19_200.0, 2400.0, Detector::MatchedFilter);
+        let symbols: Vec<usize> = bits
+            .chunks(2)
+            .map(|pair| {
+                let symbol = (pair[0] as u8) << 1 | pair[1] as u8;
+                params.mapping.iter().position(|s| *s == symbol).unwrap()
+            })
+            .collect();
+
+        let fsk = Fsk::new(params.clone()).unwrap();
+        let blocks = modulate(&symbols, &params.tones, 19_200.0, 192_000.0, 0.0);
+        let received = demodulate(&fsk, blocks);
+
+        assert_eq!(bit_errors(&bits, &received), 0);
+    }
+
+    #[test]
+    fn matched_filter_beats_discriminator_in_noise() {
+        let bits = random_bits(4000);
+        let symbols: Vec<usize> = bits.iter().map(|bit| *bit as usize).collect();
+        let params = FskParams::binary(4800.0, 4800.0, Detector::MatchedFilter);
+        // 0 dB SNR over 48 kHz, 10 dB over the symbol rate.
+        let blocks = modulate(&symbols, &params.tones, 4800.0, 48_000.0, 1.0);
+
+        let matched = demodulate(&Fsk::new(params.clone()).unwrap(), blocks.clone());
+        let discriminator = demodulate(
+            &Fsk::new(FskParams {
+                detector: Detector::Discriminator,
+                ..params
+            })
+            .unwrap(),
+            blocks,
+        );
+
+        let matched_errors = bit_errors(&bits, &matched);
+        let discriminator_errors = bit_errors(&bits, &discriminator);
+        assert!(matched_errors < 40, "{}", matched_errors);
+        assert!(
+            matched_errors < discriminator_errors,
+            "{} vs {}",
+            matched_errors,
+            discriminator_errors
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        let valid = FskParams::binary(9600.0, 4800.0, Detector::Discriminator);
+
+        let three_tones = FskParams {
+            tones: vec![-1000.0, 0.0, 1000.0],
+            mapping: vec![0, 1, 2],
+            ..valid.clone()
+        };
+        assert_eq!(Fsk::new(three_tones).err(), Some(FskError::ToneCount(3)));
+
+        let unsorted = FskParams {
+            tones: vec![1000.0, -1000.0],
+            ..valid.clone()
+        };
+        assert_eq!(Fsk::new(unsorted).err(), Some(FskError::UnsortedTones));
+
+        let repeated_symbol = FskParams {
+            mapping: vec![1, 1],
+            ..valid.clone()
+        };
+        assert_eq!(Fsk::new(repeated_symbol).err(), Some(FskError::Mapping));
+
+        assert!(Fsk::new(valid).is_ok());
+    }
+}
@@ -0,0 +1,381 @@
+//! Runs a GNU Radio flowgraph as a subprocess that demodulates samples into bits.
+//!
+//! The flowgraph reads `gr_complex` samples from a ZMQ SUB source connected to the sample port,
+//! and writes one bit per byte to a ZMQ PUB sink bound to the bit port. Its parameters are passed
+//! as `--name value` options, as in the flowgraphs generated by GRC from parameter blocks, along
+//! with the addresses of both ports as `--sample-address` and `--bit-address`.
+
+use sdr::block::SampleBlock;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    process::{self, Child, ExitStatus, Stdio},
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// How long to wait for the bits of the samples sent, in ms.
+const BIT_TIMEOUT: i32 = 1000;
+/// How often the flowgraph is checked while waiting for it to be ready.
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How to run a flowgraph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowgraphConfig {
+    /// Python script generated by GRC.
+    pub flowgraph: PathBuf,
+    pub interpreter: PathBuf,
+    /// Local TCP port the samples are published on.
+    pub sample_port: u16,
+    /// Local TCP port the flowgraph publishes the bits on.
+    pub bit_port: u16,
+    /// Passed to the flowgraph as `--name value`.
+    pub parameters: Vec<(String, String)>,
+    /// How long the flowgraph has to connect to the sample port once started.
+    pub ready_timeout: Duration,
+    /// Times the flowgraph is restarted after crashing before giving up.
+    pub max_restarts: u32,
+}
+
+impl FlowgraphConfig {
+    /// Runs `flowgraph` with `python3` on ports 5556 and 5557, without parameters.
+    pub fn new(flowgraph: impl AsRef<Path>) -> Self {
+        Self {
+            flowgraph: flowgraph.as_ref().to_path_buf(),
+            interpreter: PathBuf::from("python3"),
+            sample_port: 5556,
+            bit_port: 5557,
+            parameters: Vec::new(),
+            ready_timeout: Duration::from_secs(10),
+            max_restarts: 3,
+        }
+    }
+
+    /// Adds a parameter of the flowgraph.
+    pub fn parameter(mut self, name: &str, value: impl fmt::Display) -> Self {
+        self.parameters.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+#[derive(Debug)]
+pub enum FlowgraphError {
+    NoSuchFlowgraph,
+    GnuRadioProcess(io::Error),
+    Zmq(zmq::Error),
+    /// The flowgraph didn't connect to the sample port in time.
+    NotReady,
+    /// The flowgraph exited, and won't be restarted.
+    Exited(ExitStatus),
+}
+
+/// A running flowgraph. It's restarted if it crashes, and killed when dropped. What it prints
+/// goes to the standard output and error of this process.
+pub struct Flowgraph {
+    config: FlowgraphConfig,
+    child: Child,
+    /// XPUB rather than PUB, to know when the flowgraph subscribes.
+    sample_sink: zmq::Socket,
+    bit_source: zmq::Socket,
+    restarts: Arc<AtomicU32>,
+}
+
+impl Flowgraph {
+    /// Starts the flowgraph and waits until it's ready for samples.
+    pub fn start(config: FlowgraphConfig) -> Result<Self, FlowgraphError> {
+        if !config.flowgraph.exists() {
+            return Err(FlowgraphError::NoSuchFlowgraph);
+        }
+
+        let ctx = zmq::Context::new();
+
+        let sample_sink = ctx.socket(zmq::XPUB).map_err(FlowgraphError::Zmq)?;
+        sample_sink
+            .set_xpub_verbose(true)
+            .and_then(|()| sample_sink.set_linger(0))
+            .and_then(|()| sample_sink.bind(&address(config.sample_port)))
+            .map_err(FlowgraphError::Zmq)?;
+
+        let bit_source = ctx.socket(zmq::SUB).map_err(FlowgraphError::Zmq)?;
+        bit_source
+            .connect(&address(config.bit_port))
+            .and_then(|()| bit_source.set_subscribe(b""))
+            .and_then(|()| bit_source.set_rcvtimeo(BIT_TIMEOUT))
+            .map_err(FlowgraphError::Zmq)?;
+
+        let child = spawn(&config)?;
+        let mut flowgraph = Self {
+            config,
+            child,
+            sample_sink,
+            bit_source,
+            restarts: Arc::new(AtomicU32::new(0)),
+        };
+        flowgraph.wait_ready()?;
+
+        Ok(flowgraph)
+    }
+
+    /// Sends a block of samples, restarting the flowgraph first if it crashed.
+    pub fn send(&mut self, block: &SampleBlock) -> Result<(), FlowgraphError> {
+        self.ensure_running()?;
+
+        // The flowgraph's ZMQ SUB Source expects gr_complex items: native endian f32 I/Q pairs.
+        let bytes: Vec<u8> = block
+            .samples
+            .iter()
+            .flat_map(|s| [s.re.to_ne_bytes(), s.im.to_ne_bytes()])
+            .flatten()
+            .collect();
+
+        self.sample_sink.send(bytes, 0).map_err(FlowgraphError::Zmq)
+    }
+
+    /// Times the flowgraph was restarted after crashing, as it goes on.
+    pub fn restarts(&self) -> Arc<AtomicU32> {
+        self.restarts.clone()
+    }
+
+    /// Receives the next bits, or `None` if there were none for a second.
+    pub fn recv_bits(&mut self) -> Result<Option<Vec<bool>>, FlowgraphError> {
+        match self.bit_source.recv_bytes(0) {
+            Ok(msg) => Ok(Some(msg.iter().map(|b| *b != 0).collect())),
+            Err(zmq::Error::EAGAIN) => Ok(None),
+            Err(err) => Err(FlowgraphError::Zmq(err)),
+        }
+    }
+
+    /// Restarts the flowgraph if it exited, unless it already was too many times.
+    fn ensure_running(&mut self) -> Result<(), FlowgraphError> {
+        let Some(status) = self
+            .child
+            .try_wait()
+            .map_err(FlowgraphError::GnuRadioProcess)?
+        else {
+            return Ok(());
+        };
+
+        if self.restarts.load(Ordering::Relaxed) >= self.config.max_restarts {
+            return Err(FlowgraphError::Exited(status));
+        }
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+
+        self.child = spawn(&self.config)?;
+        self.wait_ready()
+    }
+
+    /// Waits until the flowgraph subscribes to the samples, since any sent before would be lost.
+    fn wait_ready(&mut self) -> Result<(), FlowgraphError> {
+        let deadline = Instant::now() + self.config.ready_timeout;
+
+        while Instant::now() < deadline {
+            if let Some(status) = self
+                .child
+                .try_wait()
+                .map_err(FlowgraphError::GnuRadioProcess)?
+            {
+                return Err(FlowgraphError::Exited(status));
+            }
+
+            let events = self
+                .sample_sink
+                .poll(zmq::POLLIN, READY_POLL_INTERVAL.as_millis() as i64)
+                .map_err(FlowgraphError::Zmq)?;
+            if events == 0 {
+                continue;
+            }
+
+            // Subscriptions start with 1, unsubscriptions of a previous run with 0.
+            let msg = self
+                .sample_sink
+                .recv_bytes(0)
+                .map_err(FlowgraphError::Zmq)?;
+            if msg.first() == Some(&1) {
+                return Ok(());
+            }
+        }
+
+        Err(FlowgraphError::NotReady)
+    }
+}
+
+impl Drop for Flowgraph {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn address(port: u16) -> String {
+    format!("tcp://127.0.0.1:{}", port)
+}
+
+/// Starts the flowgraph process.
+fn spawn(config: &FlowgraphConfig) -> Result<Child, FlowgraphError> {
+    let mut command = process::Command::new(&config.interpreter);
+    command
+        .arg(&config.flowgraph)
+        .arg("--sample-address")
+        .arg(address(config.sample_port))
+        .arg("--bit-address")
+        .arg(address(config.bit_port));
+    for (name, value) in &config.parameters {
+        command.arg(format!("--{}", name)).arg(value);
+    }
+
+    // Stdin stays open, since GRC flowgraphs stop when it's closed.
+    command
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(FlowgraphError::GnuRadioProcess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, sync::atomic::AtomicU16, thread};
+
+    /// Ports of each test, so that they can run in parallel.
+    static NEXT_PORT: AtomicU16 = AtomicU16::new(35560);
+
+    /// A config running `script` with `sh` instead of a flowgraph.
+    fn shell(name: &str, script: &str) -> FlowgraphConfig {
+        let path = std::env::temp_dir().join(format!("{}-{}.sh", name, process::id()));
+        fs::write(&path, script).unwrap();
+
+        let port = NEXT_PORT.fetch_add(2, Ordering::Relaxed);
+        FlowgraphConfig {
+            interpreter: PathBuf::from("sh"),
+            sample_port: port,
+            bit_port: port + 1,
+            ready_timeout: Duration::from_millis(500),
+            ..FlowgraphConfig::new(path)
+        }
+    }
+
+    #[test]
+    fn rejects_missing_flowgraphs() {
+        let config = FlowgraphConfig::new("/nonexistent/flowgraph.py");
+
+        assert!(matches!(
+            Flowgraph::start(config),
+            Err(FlowgraphError::NoSuchFlowgraph)
+        ));
+    }
+
+    #[test]
+    fn reports_flowgraphs_that_exit() {
+        let config = shell("exits", "echo starting; exit 3");
+
+        match Flowgraph::start(config) {
+            Err(FlowgraphError::Exited(status)) => assert_eq!(status.code(), Some(3)),
+            other => panic!("{:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn times_out_if_never_ready() {
+        let config = shell("never-ready", "sleep 30");
+
+        let start = Instant::now();
+        assert!(matches!(
+            Flowgraph::start(config),
+            Err(FlowgraphError::NotReady)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn becomes_ready_when_the_flowgraph_subscribes() {
+        let config = shell("subscribes", "sleep 30");
+        let sample_address = address(config.sample_port);
+
+        // Stands in for the flowgraph's ZMQ SUB source.
+        let subscriber = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            let socket = zmq::Context::new().socket(zmq::SUB).unwrap();
+            socket.connect(&sample_address).unwrap();
+            socket.set_subscribe(b"").unwrap();
+            socket.recv_bytes(0).unwrap()
+        });
+
+        let mut flowgraph = Flowgraph::start(config).unwrap();
+        let block = SampleBlock::new(
+            vec![sdr::block::Complex::new(1.0, -1.0)],
+            48_000.0,
+            0.0,
+            sdr::block::Timestamp::Hardware(Duration::ZERO),
+        );
+        flowgraph.send(&block).unwrap();
+
+        let mut expected = 1.0f32.to_ne_bytes().to_vec();
+        expected.extend((-1.0f32).to_ne_bytes());
+        assert_eq!(subscriber.join().unwrap(), expected);
+    }
+
+    #[test]
+    fn counts_restarts_after_crashes() {
+        // Crashes the first time it runs only.
+        let ran = std::env::temp_dir().join(format!("crashed-{}", process::id()));
+        let _ = fs::remove_file(&ran);
+        let mut config = shell(
+            "crashes",
+            &format!(
+                "[ -e {0} ] && sleep 30; touch {0}; sleep 1; exit 1",
+                ran.display()
+            ),
+        );
+        config.ready_timeout = Duration::from_secs(3);
+        let sample_address = address(config.sample_port);
+
+        // Stands in for the flowgraph's ZMQ SUB source, subscribing again once restarted.
+        thread::spawn(move || {
+            let ctx = zmq::Context::new();
+            for delay in [200, 1300] {
+                thread::sleep(Duration::from_millis(delay));
+                let socket = ctx.socket(zmq::SUB).unwrap();
+                socket.connect(&sample_address).unwrap();
+                socket.set_subscribe(b"").unwrap();
+                thread::sleep(Duration::from_millis(1000));
+            }
+        });
+
+        let mut flowgraph = Flowgraph::start(config).unwrap();
+        let restarts = flowgraph.restarts();
+        assert_eq!(restarts.load(Ordering::Relaxed), 0);
+
+        thread::sleep(Duration::from_millis(1200));
+        let block = SampleBlock::new(
+            vec![sdr::block::Complex::new(1.0, -1.0)],
+            48_000.0,
+            0.0,
+            sdr::block::Timestamp::Hardware(Duration::ZERO),
+        );
+        flowgraph.send(&block).unwrap();
+
+        assert_eq!(restarts.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn passes_addresses_and_parameters() {
+        let out = std::env::temp_dir().join(format!("flowgraph-args-{}", process::id()));
+        let mut config = shell("args", &format!("echo \"$@\" > {}", out.display()))
+            .parameter("samp-rate", 48000)
+            .parameter("baud-rate", 1200);
+        config.max_restarts = 0;
+        let (sample_port, bit_port) = (config.sample_port, config.bit_port);
+
+        assert!(Flowgraph::start(config).is_err());
+        assert_eq!(
+            fs::read_to_string(out).unwrap().trim(),
+            format!(
+                "--sample-address tcp://127.0.0.1:{} --bit-address tcp://127.0.0.1:{} \
+                 --samp-rate 48000 --baud-rate 1200",
+                sample_port, bit_port
+            )
+        );
+    }
+}
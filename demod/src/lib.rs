@@ -1,12 +1,50 @@
 pub mod afsk1200;
+pub mod apt;
+pub mod diagnostics;
 pub mod example;
+mod filter;
+pub mod fm;
+pub mod fsk;
+pub mod gnuradio;
 pub mod gr_mock;
+pub mod line_coding;
+pub mod lora;
+pub mod sstv;
+pub mod stream;
 
+use gnuradio::FlowgraphError;
+use lora::LoraError;
+use sdr::block::SampleBlock;
+
+/// Why a demodulator output no bits.
+#[derive(Debug)]
+pub enum DemodulatorError {
+    /// No bits came out in time, e.g. for lack of signal. Demodulation goes on.
+    Timeout,
+    /// The flowgraph failed. Demodulation can't go on, so the output ends after it.
+    Flowgraph(FlowgraphError),
+    /// The samples weren't at the rate the demodulator was set up for, in Hz, so it can't go on.
+    SampleRate { expected: f64, actual: f64 },
+    /// The LoRa decoder can't decode the samples, so it can't go on.
+    Lora(LoraError),
+}
+
+impl DemodulatorError {
+    /// Whether the output ends after this error.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, Self::Timeout)
+    }
+}
+
+/// Demodulates samples into bits.
+///
+/// The output ends when the input does, or after a fatal error. Transient errors are output in
+/// between the bits.
 pub trait Demodulator<I>
 where
-    I: Iterator<Item = Vec<f64>>,
+    I: Iterator<Item = SampleBlock>,
 {
-    type Output: Iterator<Item = Vec<bool>>;
+    type Output: Iterator<Item = Result<Vec<bool>, DemodulatorError>>;
 
     fn bits(&self, input: I) -> Self::Output;
 }
@@ -0,0 +1,247 @@
+//! Transformations of the bits on the air, undone after demodulation: inverted polarity, e.g.
+//! from a receiver that swaps the FSK tones, G3RUH scrambling, as used by 9600 baud packet, and
+//! NRZI, as used by AX.25.
+
+/// Taps of the G3RUH polynomial, 1 + x^12 + x^17, as positions in the shift register.
+const G3RUH_TAPS: (u32, u32) = (11, 16);
+
+/// How the bits were coded before modulation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineCoding {
+    /// The bits were inverted.
+    pub inverted: bool,
+    /// The bits were scrambled with the G3RUH polynomial.
+    pub scrambled: bool,
+    /// The bits were NRZI coded before being scrambled, a 0 as a change of level and a 1 as none.
+    pub nrzi: bool,
+}
+
+impl LineCoding {
+    /// Every coding made of the allowed transformations. Inverted ones are left out with NRZI,
+    /// which only depends on the changes of level, so decodes inverted bits alike.
+    pub fn combinations(inverted: bool, scrambled: bool, nrzi: bool) -> Vec<Self> {
+        let mut codings = Vec::new();
+        for nrzi in [false, true].into_iter().filter(|n| nrzi || !n) {
+            for scrambled in [false, true].into_iter().filter(|s| scrambled || !s) {
+                for inverted in [false, true]
+                    .into_iter()
+                    .filter(|i| !i || (inverted && !nrzi))
+                {
+                    codings.push(Self {
+                        inverted,
+                        scrambled,
+                        nrzi,
+                    });
+                }
+            }
+        }
+
+        codings
+    }
+
+    /// A decoder of bits coded this way.
+    pub fn decoder(&self) -> LineDecoder {
+        LineDecoder {
+            coding: *self,
+            descrambler: Descrambler::new(),
+            nrzi: NrziDecoder::new(),
+        }
+    }
+}
+
+/// Undoes a [`LineCoding`] on demodulated bits: inverts, descrambles, then NRZI decodes them. The
+/// descrambler synchronizes by itself after 17 bits, and NRZI after 1, so decoding can start
+/// anywhere in the stream.
+#[derive(Debug, Clone)]
+pub struct LineDecoder {
+    coding: LineCoding,
+    descrambler: Descrambler,
+    nrzi: NrziDecoder,
+}
+
+impl LineDecoder {
+    pub fn decode(&mut self, mut bits: Vec<bool>) -> Vec<bool> {
+        if self.coding.inverted {
+            bits.iter_mut().for_each(|bit| *bit = !*bit);
+        }
+
+        if self.coding.scrambled {
+            bits.iter_mut()
+                .for_each(|bit| *bit = self.descrambler.descramble(*bit));
+        }
+
+        if self.coding.nrzi {
+            bits.iter_mut()
+                .for_each(|bit| *bit = self.nrzi.decode(*bit));
+        }
+
+        bits
+    }
+}
+
+/// G3RUH scrambler, multiplicative: each bit is xored with the scrambled bits 12 and 17 bits
+/// before it.
+#[derive(Debug, Clone, Default)]
+pub struct Scrambler {
+    register: u32,
+}
+
+impl Scrambler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn scramble(&mut self, bit: bool) -> bool {
+        let scrambled = bit ^ g3ruh_feedback(self.register);
+        self.register = (self.register << 1) | scrambled as u32;
+        scrambled
+    }
+}
+
+/// G3RUH descrambler, the inverse of [`Scrambler`]: each bit is xored with the received bits 12
+/// and 17 bits before it.
+#[derive(Debug, Clone, Default)]
+pub struct Descrambler {
+    register: u32,
+}
+
+impl Descrambler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn descramble(&mut self, bit: bool) -> bool {
+        let descrambled = bit ^ g3ruh_feedback(self.register);
+        self.register = (self.register << 1) | bit as u32;
+        descrambled
+    }
+}
+
+/// NRZI encoder: a 0 changes the level, a 1 keeps it.
+#[derive(Debug, Clone, Default)]
+pub struct NrziEncoder {
+    level: bool,
+}
+
+impl NrziEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn encode(&mut self, bit: bool) -> bool {
+        self.level ^= !bit;
+        self.level
+    }
+}
+
+/// NRZI decoder, the inverse of [`NrziEncoder`]: a 1 where the level stayed the same, a 0 where
+/// it changed.
+#[derive(Debug, Clone, Default)]
+pub struct NrziDecoder {
+    level: bool,
+}
+
+impl NrziDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decode(&mut self, level: bool) -> bool {
+        let bit = level == self.level;
+        self.level = level;
+        bit
+    }
+}
+
+fn g3ruh_feedback(register: u32) -> bool {
+    ((register >> G3RUH_TAPS.0) ^ (register >> G3RUH_TAPS.1)) & 1 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn descrambling_undoes_scrambling() {
+        let mut rng = StdRng::seed_from_u64(43);
+        let bits: Vec<bool> = (0..1000).map(|_| rng.random()).collect();
+
+        let mut scrambler = Scrambler::new();
+        let scrambled: Vec<bool> = bits.iter().map(|bit| scrambler.scramble(*bit)).collect();
+        assert_ne!(scrambled, bits);
+
+        let coding = LineCoding {
+            scrambled: true,
+            ..LineCoding::default()
+        };
+        assert_eq!(coding.decoder().decode(scrambled), bits);
+    }
+
+    #[test]
+    fn descrambler_synchronizes_mid_stream() {
+        let mut scrambler = Scrambler::new();
+        let scrambled: Vec<bool> = (0..200).map(|i| scrambler.scramble(i % 3 == 0)).collect();
+
+        let mut descrambler = Descrambler::new();
+        let descrambled: Vec<bool> = scrambled[50..]
+            .iter()
+            .map(|bit| descrambler.descramble(*bit))
+            .collect();
+
+        let expected: Vec<bool> = (67..200).map(|i| i % 3 == 0).collect();
+        assert_eq!(descrambled[17..], expected);
+    }
+
+    #[test]
+    fn inverts_then_descrambles() {
+        let bits = vec![true, false, false, true, true, true, false, false];
+        let mut scrambler = Scrambler::new();
+        let coded: Vec<bool> = bits.iter().map(|bit| !scrambler.scramble(*bit)).collect();
+
+        let coding = LineCoding {
+            inverted: true,
+            scrambled: true,
+            nrzi: false,
+        };
+        assert_eq!(coding.decoder().decode(coded), bits);
+    }
+
+    #[test]
+    fn descrambles_then_nrzi_decodes_either_polarity() {
+        let mut rng = StdRng::seed_from_u64(44);
+        let bits: Vec<bool> = (0..1000).map(|_| rng.random()).collect();
+        let mut nrzi = NrziEncoder::new();
+        let mut scrambler = Scrambler::new();
+        let coded: Vec<bool> = bits
+            .iter()
+            .map(|bit| scrambler.scramble(nrzi.encode(*bit)))
+            .collect();
+        let inverted: Vec<bool> = coded.iter().map(|bit| !bit).collect();
+
+        let coding = LineCoding {
+            inverted: false,
+            scrambled: true,
+            nrzi: true,
+        };
+        assert_eq!(coding.decoder().decode(coded), bits);
+        // Both polarities decode alike once the descrambler has synchronized.
+        assert_eq!(coding.decoder().decode(inverted)[18..], bits[18..]);
+    }
+
+    #[test]
+    fn combinations_leave_out_inversion_with_nrzi() {
+        let codings = LineCoding::combinations(true, true, true);
+
+        assert_eq!(codings.len(), 6);
+        assert!(
+            codings
+                .iter()
+                .all(|coding| !(coding.inverted && coding.nrzi))
+        );
+        assert_eq!(
+            LineCoding::combinations(false, false, false),
+            vec![LineCoding::default()]
+        );
+    }
+}
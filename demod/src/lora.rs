@@ -0,0 +1,1029 @@
+//! LoRa (chirp spread spectrum) demodulator and packet decoder.
+//!
+//! The physical layer follows the one reverse engineered by gr-lora_sdr: a preamble of upchirps,
+//! two sync word symbols and 2.25 downchirps, then the header and payload symbols. Each symbol is
+//! Gray mapped, diagonally interleaved and Hamming coded; the first block (the header, in explicit
+//! header mode) always uses coding rate 4/8 and two bits less per symbol. The payload is whitened
+//! and followed by an optional CRC-16.
+//!
+//! Symbols are demodulated by multiplying with a downchirp and taking the strongest FFT bin. The
+//! preamble gives the timing, and the downchirps the integer part of the carrier frequency offset.
+
+use crate::DemodulatorError;
+use rustfft::{Fft, FftPlanner};
+use sdr::block::{Complex, SampleBlock};
+use std::{collections::VecDeque, f64::consts::PI, sync::Arc};
+
+/// Upchirps in a row, at the same bin, that make a preamble.
+const MIN_PREAMBLE_SYMBOLS: usize = 4;
+/// Windows searched for the sync word after the preamble was found.
+const MAX_PREAMBLE_SYMBOLS: usize = 64;
+/// Ratio between the strongest bin and the average of the bins for a chirp to be detected.
+const DETECTION_THRESHOLD: f32 = 8.0;
+/// Symbols with more than this duration require the low data rate optimization.
+const LOW_DATA_RATE_SYMBOL_DURATION: f64 = 0.016;
+
+/// How the payload length, coding rate and CRC presence are known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoraHeader {
+    /// Sent in a header ahead of the payload.
+    Explicit,
+    /// Fixed in advance, with no header sent.
+    Implicit {
+        payload_len: usize,
+        /// `1` to `4`, for 4/5 to 4/8.
+        coding_rate: u8,
+        has_crc: bool,
+    },
+}
+
+/// Parameters of a LoRa downlink.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraParams {
+    /// `7` to `12`. Each symbol carries this many bits over `2^spreading_factor` chips.
+    pub spreading_factor: u8,
+    /// In Hz.
+    pub bandwidth: f64,
+    pub header: LoraHeader,
+    /// `0x12` for private networks, `0x34` for LoRaWAN.
+    pub sync_word: u8,
+    /// Low data rate optimization: payload symbols carry two bits less.
+    pub low_data_rate: bool,
+}
+
+impl LoraParams {
+    /// Explicit header and private sync word, with the low data rate optimization enabled when
+    /// symbols last over 16 ms, as LoRa transceivers do.
+    pub fn new(spreading_factor: u8, bandwidth: f64) -> Self {
+        let symbol_duration = (1u64 << spreading_factor.min(63)) as f64 / bandwidth;
+
+        Self {
+            spreading_factor,
+            bandwidth,
+            header: LoraHeader::Explicit,
+            sync_word: 0x12,
+            low_data_rate: symbol_duration > LOW_DATA_RATE_SYMBOL_DURATION,
+        }
+    }
+
+    /// Input samples per chip at `sample_rate` Hz, which must be a multiple of the bandwidth.
+    pub fn oversampling(&self, sample_rate: f64) -> Result<usize, LoraError> {
+        let ratio = sample_rate / self.bandwidth;
+        if (ratio - ratio.round()).abs() < 1e-6 && ratio >= 1.0 {
+            Ok(ratio.round() as usize)
+        } else {
+            Err(LoraError::SampleRate(sample_rate))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoraError {
+    /// Only spreading factors 7 to 12 are supported.
+    SpreadingFactor(u8),
+    Bandwidth(f64),
+    /// The coding rate must be `1` to `4`.
+    CodingRate(u8),
+    /// The sample rate isn't a multiple of the bandwidth.
+    SampleRate(f64),
+}
+
+/// A decoded packet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraPacket {
+    /// Dewhitened payload, without the CRC.
+    pub payload: Vec<u8>,
+    /// `1` to `4`, for 4/5 to 4/8.
+    pub coding_rate: u8,
+    /// Whether the CRC matched, `None` if the packet has no CRC.
+    pub crc_ok: Option<bool>,
+    /// Codewords with a corrected bit error (coding rates 4/7 and 4/8) or a detected one (4/5
+    /// and 4/6).
+    pub codeword_errors: usize,
+    /// Signal to noise ratio over the bandwidth, in dB.
+    pub snr_db: f64,
+    /// Carrier frequency offset, in Hz, to the nearest bin.
+    pub frequency_offset: f64,
+}
+
+impl LoraPacket {
+    /// Whether the payload can be trusted: the CRC matched, or there's no CRC and no codeword had
+    /// errors.
+    pub fn is_valid(&self) -> bool {
+        self.crc_ok.unwrap_or(self.codeword_errors == 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    payload_len: usize,
+    coding_rate: u8,
+    has_crc: bool,
+}
+
+enum State {
+    /// Looking for upchirps at the same bin.
+    Detect {
+        last_bin: Option<usize>,
+        run: usize,
+    },
+    /// Aligned to the preamble, waiting for the sync word.
+    Preamble {
+        symbols: usize,
+    },
+    SyncWord,
+    Downchirp,
+    /// Reading the first block, header included in explicit mode.
+    Header,
+    Payload {
+        header: Header,
+        nibbles: Vec<u8>,
+        symbols_left: usize,
+    },
+}
+
+/// Decodes LoRa packets from a sample stream.
+pub struct LoraDecoder {
+    params: LoraParams,
+    /// Chips per symbol.
+    n: usize,
+    fft: Arc<dyn Fft<f32>>,
+    upchirp: Vec<Complex<f32>>,
+    /// Input samples per chip.
+    oversampling: usize,
+    sample_rate: f64,
+    /// Chips not yet consumed; `pos` is the start of the next symbol.
+    buffer: Vec<Complex<f32>>,
+    pos: usize,
+    /// Absolute index of `buffer[0]`, to keep the frequency correction continuous.
+    offset: u64,
+    /// Decimation accumulator.
+    chip_sum: Complex<f32>,
+    chip_count: usize,
+    state: State,
+    /// Integer carrier frequency offset, in bins.
+    cfo: i64,
+    /// Symbols of the current block.
+    symbols: Vec<usize>,
+    codeword_errors: usize,
+    /// Sums of peak power above the noise, and of noise power per bin, over the packet.
+    signal_power: f64,
+    noise_power: f64,
+}
+
+impl LoraDecoder {
+    pub fn new(params: LoraParams) -> Result<Self, LoraError> {
+        if !(7..=12).contains(&params.spreading_factor) {
+            return Err(LoraError::SpreadingFactor(params.spreading_factor));
+        }
+
+        if params.bandwidth.is_nan() || params.bandwidth <= 0.0 {
+            return Err(LoraError::Bandwidth(params.bandwidth));
+        }
+
+        if let LoraHeader::Implicit { coding_rate, .. } = params.header
+            && !(1..=4).contains(&coding_rate)
+        {
+            return Err(LoraError::CodingRate(coding_rate));
+        }
+
+        let n = 1 << params.spreading_factor;
+
+        Ok(Self {
+            n,
+            fft: FftPlanner::new().plan_fft_forward(n),
+            upchirp: chirp(n, 0),
+            oversampling: 0,
+            sample_rate: 0.0,
+            buffer: Vec::new(),
+            pos: 0,
+            offset: 0,
+            chip_sum: Complex::new(0.0, 0.0),
+            chip_count: 0,
+            state: State::Detect {
+                last_bin: None,
+                run: 0,
+            },
+            cfo: 0,
+            symbols: Vec::new(),
+            codeword_errors: 0,
+            signal_power: 0.0,
+            noise_power: 0.0,
+            params,
+        })
+    }
+
+    /// Feeds a block of samples, returning the packets it completed.
+    ///
+    /// Fails on blocks whose sample rate isn't a multiple of the bandwidth. Lost samples or a
+    /// change of sample rate drop the packet being decoded.
+    pub fn feed(&mut self, block: &SampleBlock) -> Result<Vec<LoraPacket>, LoraError> {
+        if block.discontinuity || block.sample_rate != self.sample_rate {
+            self.sample_rate = block.sample_rate;
+            self.reset();
+            self.oversampling = self.params.oversampling(block.sample_rate).unwrap_or(0);
+        }
+
+        if self.oversampling == 0 {
+            return Err(LoraError::SampleRate(block.sample_rate));
+        }
+
+        // Decimate to one sample per chip.
+        for sample in &block.samples {
+            self.chip_sum += sample;
+            self.chip_count += 1;
+
+            if self.chip_count == self.oversampling {
+                self.buffer.push(self.chip_sum / self.oversampling as f32);
+                self.chip_sum = Complex::new(0.0, 0.0);
+                self.chip_count = 0;
+            }
+        }
+
+        let mut packets = Vec::new();
+        while self.buffer.len() >= self.pos + self.n {
+            if let Some(packet) = self.step() {
+                packets.push(packet);
+            }
+        }
+
+        // Keep a symbol before `pos`, since aligning to the preamble can step back.
+        let consumed = self.pos.saturating_sub(self.n).min(self.buffer.len());
+        self.buffer.drain(..consumed);
+        self.pos -= consumed;
+        self.offset += consumed as u64;
+
+        Ok(packets)
+    }
+
+    /// Starts looking for a preamble again, dropping any buffered samples.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.pos = 0;
+        self.offset = 0;
+        self.chip_sum = Complex::new(0.0, 0.0);
+        self.chip_count = 0;
+        self.restart();
+    }
+
+    /// Starts looking for a preamble again from the current position.
+    fn restart(&mut self) {
+        self.state = State::Detect {
+            last_bin: None,
+            run: 0,
+        };
+        self.cfo = 0;
+        self.symbols.clear();
+        self.codeword_errors = 0;
+        self.signal_power = 0.0;
+        self.noise_power = 0.0;
+    }
+
+    /// Processes the symbol at `pos`.
+    fn step(&mut self) -> Option<LoraPacket> {
+        let n = self.n;
+        let start = self.pos;
+
+        match self.state {
+            State::Detect { last_bin, run } => {
+                self.pos += n;
+                let (bin, peak, mean) = self.demodulate(start, false);
+
+                if peak < DETECTION_THRESHOLD * mean {
+                    self.state = State::Detect {
+                        last_bin: None,
+                        run: 0,
+                    };
+                    return None;
+                }
+
+                let run = match last_bin {
+                    Some(last) if circular_distance(last, bin, n) <= 1 => run + 1,
+                    _ => 1,
+                };
+                self.state = State::Detect {
+                    last_bin: Some(bin),
+                    run,
+                };
+
+                if run >= MIN_PREAMBLE_SYMBOLS {
+                    // The window started `bin` chips into an upchirp: skip to the next one.
+                    self.pos = start + (n - bin) % n;
+                    self.state = State::Preamble { symbols: 0 };
+                }
+            }
+            State::Preamble { symbols } => {
+                let (bin, _, _) = self.demodulate(start, false);
+                let offset = signed(bin, n);
+
+                if offset.abs() <= 1 {
+                    // Still in the preamble, keep tracking its timing.
+                    self.pos = (start as i64 + n as i64 - offset) as usize;
+                    self.state = State::Preamble {
+                        symbols: symbols + 1,
+                    };
+
+                    if symbols + 1 > MAX_PREAMBLE_SYMBOLS {
+                        self.restart();
+                    }
+                } else if circular_distance(bin, self.sync_symbols().0, n) <= 1 {
+                    self.pos += n;
+                    self.state = State::SyncWord;
+                } else {
+                    self.pos += n;
+                    self.restart();
+                }
+            }
+            State::SyncWord => {
+                let (bin, _, _) = self.demodulate(start, false);
+                self.pos += n;
+
+                if circular_distance(bin, self.sync_symbols().1, n) <= 1 {
+                    self.state = State::Downchirp;
+                } else {
+                    self.restart();
+                }
+            }
+            State::Downchirp => {
+                // A downchirp demodulated with an upchirp sits at twice the frequency offset,
+                // since the timing taken from the preamble absorbed the other half.
+                let (bin, _, _) = self.demodulate(start, true);
+                self.cfo = (signed(bin, n) as f64 / 2.0).round() as i64;
+
+                // 2.25 downchirps, then the timing corrected for the offset.
+                self.pos = (start as i64 + (2 * n + n / 4) as i64 + self.cfo) as usize;
+                self.state = State::Header;
+            }
+            State::Header => {
+                self.pos += n;
+                let symbol = self.data_symbol(start, true);
+                self.symbols.push(symbol);
+
+                if self.symbols.len() < 8 {
+                    return None;
+                }
+
+                let sf_app = self.params.spreading_factor as usize - 2;
+                let mut nibbles = self.decode_block(sf_app, 4);
+
+                let header = match self.params.header {
+                    LoraHeader::Explicit => {
+                        let Some(header) = parse_header(&nibbles) else {
+                            self.restart();
+                            return None;
+                        };
+                        nibbles.drain(..5);
+                        header
+                    }
+                    LoraHeader::Implicit {
+                        payload_len,
+                        coding_rate,
+                        has_crc,
+                    } => Header {
+                        payload_len,
+                        coding_rate,
+                        has_crc,
+                    },
+                };
+
+                let needed = 2 * header.payload_len + if header.has_crc { 4 } else { 0 };
+                let sf_app = self.payload_bits_per_symbol();
+                let blocks = needed.saturating_sub(nibbles.len()).div_ceil(sf_app);
+                let symbols_left = blocks * (4 + header.coding_rate as usize);
+
+                self.state = State::Payload {
+                    header,
+                    nibbles,
+                    symbols_left,
+                };
+                if symbols_left == 0 {
+                    return self.finish();
+                }
+            }
+            State::Payload { .. } => {
+                self.pos += n;
+                let symbol = self.data_symbol(start, self.params.low_data_rate);
+                self.symbols.push(symbol);
+
+                let State::Payload {
+                    header,
+                    symbols_left,
+                    ..
+                } = &mut self.state
+                else {
+                    unreachable!();
+                };
+                *symbols_left -= 1;
+                let (coding_rate, done) = (header.coding_rate as usize, *symbols_left == 0);
+
+                if self.symbols.len() == 4 + coding_rate {
+                    let block = self.decode_block(self.payload_bits_per_symbol(), coding_rate);
+                    if let State::Payload { nibbles, .. } = &mut self.state {
+                        nibbles.extend(block);
+                    }
+                }
+
+                if done {
+                    return self.finish();
+                }
+            }
+        }
+
+        None
+    }
+
+    fn finish(&mut self) -> Option<LoraPacket> {
+        let State::Payload {
+            header, nibbles, ..
+        } = std::mem::replace(
+            &mut self.state,
+            State::Detect {
+                last_bin: None,
+                run: 0,
+            },
+        )
+        else {
+            unreachable!();
+        };
+
+        let bytes: Vec<u8> = nibbles
+            .chunks_exact(2)
+            .map(|pair| pair[0] | (pair[1] << 4))
+            .collect();
+        let payload = dewhiten(&bytes[..header.payload_len]);
+        let crc_ok = header.has_crc.then(|| {
+            let received =
+                u16::from_le_bytes([bytes[header.payload_len], bytes[header.payload_len + 1]]);
+            received == payload_crc(&payload)
+        });
+
+        let packet = LoraPacket {
+            payload,
+            coding_rate: header.coding_rate,
+            crc_ok,
+            codeword_errors: self.codeword_errors,
+            snr_db: 10.0
+                * (self.signal_power / (self.noise_power * self.n as f64))
+                    .max(1e-30)
+                    .log10(),
+            frequency_offset: self.cfo as f64 * self.params.bandwidth / self.n as f64,
+        };
+        self.restart();
+
+        Some(packet)
+    }
+
+    /// Sync word symbols, as FFT bins.
+    fn sync_symbols(&self) -> (usize, usize) {
+        (
+            ((self.params.sync_word >> 4) as usize) << 3,
+            ((self.params.sync_word & 0x0F) as usize) << 3,
+        )
+    }
+
+    fn payload_bits_per_symbol(&self) -> usize {
+        self.params.spreading_factor as usize - if self.params.low_data_rate { 2 } else { 0 }
+    }
+
+    /// Demodulates the header or payload symbol at `start` into its Gray decoded value.
+    fn data_symbol(&mut self, start: usize, reduced_rate: bool) -> usize {
+        let n = self.n;
+        let (bin, peak, mean) = self.demodulate(start, false);
+
+        // The noise per bin is the average of every bin but the peak.
+        let noise = ((mean * n as f32 - peak) / (n - 1) as f32) as f64;
+        self.signal_power += (peak as f64 - noise).max(0.0);
+        self.noise_power += noise;
+
+        // Symbols are sent one bin up, and reduced rate ones with the two lowest bits unused.
+        let value = (bin + n - 1) % n;
+        let value = if reduced_rate {
+            ((value + 2) % n) >> 2
+        } else {
+            value
+        };
+
+        value ^ (value >> 1)
+    }
+
+    /// Deinterleaves and decodes the current block of `4 + coding_rate` symbols of `sf_app` bits
+    /// into `sf_app` nibbles.
+    fn decode_block(&mut self, sf_app: usize, coding_rate: usize) -> Vec<u8> {
+        let cw_len = 4 + coding_rate;
+        let mut codewords = vec![0u8; sf_app];
+
+        for (i, symbol) in self.symbols.drain(..).enumerate() {
+            for j in 0..sf_app {
+                let bit = (symbol >> (sf_app - 1 - j)) & 1;
+                let row = (i + 2 * sf_app - j - 1) % sf_app;
+                codewords[row] |= (bit as u8) << (cw_len - 1 - i);
+            }
+        }
+
+        codewords
+            .into_iter()
+            .map(|codeword| {
+                let (nibble, error) = hamming_decode(codeword, coding_rate);
+                if error {
+                    self.codeword_errors += 1;
+                }
+                nibble
+            })
+            .collect()
+    }
+
+    /// Dechirps the symbol at `start`, with an upchirp if `downchirp`, returning the strongest bin,
+    /// its power and the average power of all bins.
+    fn demodulate(&self, start: usize, downchirp: bool) -> (usize, f32, f32) {
+        let n = self.n;
+        let mut window: Vec<Complex<f32>> = self.buffer[start..start + n]
+            .iter()
+            .zip(&self.upchirp)
+            .enumerate()
+            .map(|(k, (sample, up))| {
+                let dechirped = if downchirp {
+                    sample * up
+                } else {
+                    sample * up.conj()
+                };
+
+                if self.cfo == 0 {
+                    return dechirped;
+                }
+
+                let index = (self.offset + (start + k) as u64) % n as u64;
+                let phase = -2.0 * PI * (self.cfo * index as i64) as f64 / n as f64;
+                dechirped * Complex::from_polar(1.0, phase as f32)
+            })
+            .collect();
+        self.fft.process(&mut window);
+
+        let (bin, peak) = window
+            .iter()
+            .map(|bin| bin.norm_sqr())
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("a symbol has at least one chip");
+        let mean = window.iter().map(|bin| bin.norm_sqr()).sum::<f32>() / n as f32;
+
+        (bin, peak, mean)
+    }
+}
+
+/// Chirp of symbol `symbol`, one sample per chip.
+fn chirp(n: usize, symbol: usize) -> Vec<Complex<f32>> {
+    let n_f = n as f64;
+
+    (0..n)
+        .map(|k| {
+            let k = k as f64;
+            let phase = 2.0 * PI * (k * k / (2.0 * n_f) + (symbol as f64 / n_f - 0.5) * k);
+            Complex::from_polar(1.0, phase as f32)
+        })
+        .collect()
+}
+
+fn signed(bin: usize, n: usize) -> i64 {
+    if bin > n / 2 {
+        bin as i64 - n as i64
+    } else {
+        bin as i64
+    }
+}
+
+fn circular_distance(a: usize, b: usize, n: usize) -> usize {
+    let d = a.abs_diff(b) % n;
+    d.min(n - d)
+}
+
+/// Hamming codeword of `nibble` for coding rate 4/(4 + `coding_rate`): the data bits, least
+/// significant first, followed by the parity bits.
+fn hamming_encode(nibble: u8, coding_rate: usize) -> u8 {
+    let d = |bit: u8| (nibble >> bit) & 1;
+    let data = d(0) << 3 | d(1) << 2 | d(2) << 1 | d(3);
+
+    let parity = if coding_rate == 1 {
+        d(0) ^ d(1) ^ d(2) ^ d(3)
+    } else {
+        let p0 = d(0) ^ d(1) ^ d(2);
+        let p1 = d(1) ^ d(2) ^ d(3);
+        let p2 = d(0) ^ d(1) ^ d(3);
+        let p3 = d(0) ^ d(2) ^ d(3);
+        (p0 << 3 | p1 << 2 | p2 << 1 | p3) >> (4 - coding_rate)
+    };
+
+    data << coding_rate | parity
+}
+
+/// Decodes a codeword, returning the nibble and whether there was an error. Coding rates 4/7
+/// and 4/8 correct single bit errors; 4/5 and 4/6 only detect them.
+fn hamming_decode(codeword: u8, coding_rate: usize) -> (u8, bool) {
+    let data = codeword >> coding_rate;
+    let nibble = (data >> 3 & 1) | (data >> 1 & 2) | (data << 1 & 4) | (data << 3 & 8);
+
+    if hamming_encode(nibble, coding_rate) == codeword {
+        return (nibble, false);
+    }
+
+    if coding_rate >= 3
+        && let Some(corrected) = (0..16)
+            .find(|nibble| (hamming_encode(*nibble, coding_rate) ^ codeword).count_ones() == 1)
+    {
+        return (corrected, true);
+    }
+
+    (nibble, true)
+}
+
+/// Parses the explicit header from the first 5 nibbles, `None` if its checksum doesn't match.
+fn parse_header(nibbles: &[u8]) -> Option<Header> {
+    let header = Header {
+        payload_len: (nibbles[0] << 4 | nibbles[1]) as usize,
+        coding_rate: nibbles[2] >> 1,
+        has_crc: nibbles[2] & 1 == 1,
+    };
+    let checksum = (nibbles[3] & 1) << 4 | nibbles[4];
+
+    (checksum == header_checksum(nibbles) && (1..=4).contains(&header.coding_rate))
+        .then_some(header)
+}
+
+fn header_checksum(nibbles: &[u8]) -> u8 {
+    let bit = |nibble: usize, bit: u8| (nibbles[nibble] >> bit) & 1;
+
+    let c4 = bit(0, 3) ^ bit(0, 2) ^ bit(0, 1) ^ bit(0, 0);
+    let c3 = bit(0, 3) ^ bit(1, 3) ^ bit(1, 2) ^ bit(1, 1) ^ bit(2, 0);
+    let c2 = bit(0, 2) ^ bit(1, 3) ^ bit(1, 0) ^ bit(2, 3) ^ bit(2, 1);
+    let c1 = bit(0, 1) ^ bit(1, 2) ^ bit(1, 0) ^ bit(2, 2) ^ bit(2, 1) ^ bit(2, 0);
+    let c0 = bit(0, 0) ^ bit(1, 1) ^ bit(2, 3) ^ bit(2, 2) ^ bit(2, 1) ^ bit(2, 0);
+
+    c4 << 4 | c3 << 3 | c2 << 2 | c1 << 1 | c0
+}
+
+/// XORs `bytes` with the whitening sequence, the output of the LFSR `x^8 + x^6 + x^5 + x^4 + 1`
+/// seeded with `0xFF`. Whitening and dewhitening are the same operation.
+fn dewhiten(bytes: &[u8]) -> Vec<u8> {
+    let mut lfsr: u8 = 0xFF;
+
+    bytes
+        .iter()
+        .map(|byte| {
+            let out = byte ^ lfsr;
+            let feedback = (lfsr >> 7 ^ lfsr >> 5 ^ lfsr >> 4 ^ lfsr >> 3) & 1;
+            lfsr = lfsr << 1 | feedback;
+            out
+        })
+        .collect()
+}
+
+/// CRC-16/CCITT of all but the last two bytes, XORed with those two bytes.
+fn payload_crc(payload: &[u8]) -> u16 {
+    let split = payload.len().saturating_sub(2);
+    let mut crc: u16 = 0;
+
+    for byte in &payload[..split] {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    payload[split..]
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(crc, |crc, (i, byte)| crc ^ (*byte as u16) << (8 * i))
+}
+
+/// Iterator over the packets decoded from a stream of blocks.
+pub struct LoraPackets<I>
+where
+    I: Iterator<Item = SampleBlock>,
+{
+    inner: I,
+    decoder: LoraDecoder,
+    pending: VecDeque<LoraPacket>,
+    failed: bool,
+}
+
+impl LoraDecoder {
+    pub fn packets<I>(self, input: I) -> LoraPackets<I>
+    where
+        I: Iterator<Item = SampleBlock>,
+    {
+        LoraPackets {
+            inner: input,
+            decoder: self,
+            pending: VecDeque::new(),
+            failed: false,
+        }
+    }
+}
+
+impl<I> Iterator for LoraPackets<I>
+where
+    I: Iterator<Item = SampleBlock>,
+{
+    type Item = Result<LoraPacket, DemodulatorError>;
+
+    /// Decodes blocks until a packet is complete. Ends with the input, or after failing on a
+    /// block the decoder can't decode.
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            if self.failed {
+                return None;
+            }
+
+            let block = self.inner.next()?;
+            match self.decoder.feed(&block) {
+                Ok(packets) => self.pending.extend(packets),
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(DemodulatorError::Lora(err)));
+                }
+            }
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use sdr::block::Timestamp;
+    use std::time::Duration;
+
+    const PAYLOAD: &[u8] = b"IN A HOLE IN THE GROUND";
+
+    /// Interleaves and Gray demaps `nibbles` into the symbols of one block, as FFT bins.
+    fn encode_block(
+        nibbles: &[u8],
+        sf: usize,
+        sf_app: usize,
+        coding_rate: usize,
+        reduced_rate: bool,
+    ) -> Vec<usize> {
+        let n = 1 << sf;
+        let cw_len = 4 + coding_rate;
+        let codewords: Vec<u8> = (0..sf_app)
+            .map(|i| hamming_encode(nibbles.get(i).copied().unwrap_or(0), coding_rate))
+            .collect();
+
+        (0..cw_len)
+            .map(|i| {
+                let value = (0..sf_app).fold(0, |value, j| {
+                    let row = (i + 2 * sf_app - j - 1) % sf_app;
+                    let bit = (codewords[row] >> (cw_len - 1 - i)) & 1;
+                    value | (bit as usize) << (sf_app - 1 - j)
+                });
+
+                // Inverse of the Gray code.
+                let mut binary = value;
+                let mut shift = value >> 1;
+                while shift != 0 {
+                    binary ^= shift;
+                    shift >>= 1;
+                }
+
+                let symbol = if reduced_rate { binary << 2 } else { binary };
+                (symbol + 1) % n
+            })
+            .collect()
+    }
+
+    /// Symbols (as FFT bins) of a packet carrying `payload`, after the preamble.
+    fn encode(params: &LoraParams, payload: &[u8], coding_rate: usize) -> Vec<usize> {
+        let sf = params.spreading_factor as usize;
+        let whitened = dewhiten(payload);
+        let mut nibbles: Vec<u8> = Vec::new();
+
+        if params.header == LoraHeader::Explicit {
+            let mut header = vec![
+                (payload.len() >> 4) as u8,
+                (payload.len() & 0x0F) as u8,
+                (coding_rate as u8) << 1 | 1,
+            ];
+            let checksum = header_checksum(&header);
+            header.extend([checksum >> 4, checksum & 0x0F]);
+            nibbles.extend(header);
+        }
+
+        let crc = payload_crc(payload);
+        for byte in whitened.iter().chain(&crc.to_le_bytes()) {
+            nibbles.extend([byte & 0x0F, byte >> 4]);
+        }
+
+        let mut symbols = encode_block(&nibbles, sf, sf - 2, 4, true);
+        let mut rest = &nibbles[(sf - 2).min(nibbles.len())..];
+        let sf_app = if params.low_data_rate { sf - 2 } else { sf };
+        while !rest.is_empty() {
+            symbols.extend(encode_block(
+                rest,
+                sf,
+                sf_app,
+                coding_rate,
+                params.low_data_rate,
+            ));
+            rest = &rest[sf_app.min(rest.len())..];
+        }
+
+        symbols
+    }
+
+    /// A packet with its preamble, delayed by `delay` chips and shifted by `cfo` bins, with
+    /// complex white noise of `noise_power` and silence around it.
+    fn modulate(
+        params: &LoraParams,
+        payload: &[u8],
+        coding_rate: usize,
+        delay: usize,
+        cfo: f64,
+        noise_power: f64,
+    ) -> Vec<Complex<f32>> {
+        let n = 1 << params.spreading_factor;
+        let decoder = LoraDecoder::new(params.clone()).unwrap();
+        let (sync1, sync2) = decoder.sync_symbols();
+        let downchirp: Vec<Complex<f32>> = chirp(n, 0).iter().map(|c| c.conj()).collect();
+
+        let mut samples = vec![Complex::new(0.0, 0.0); 3 * n + delay];
+        for _ in 0..8 {
+            samples.extend(chirp(n, 0));
+        }
+        samples.extend(chirp(n, sync1));
+        samples.extend(chirp(n, sync2));
+        samples.extend(&downchirp);
+        samples.extend(&downchirp);
+        samples.extend(&downchirp[..n / 4]);
+        for symbol in encode(params, payload, coding_rate) {
+            samples.extend(chirp(n, symbol));
+        }
+        samples.extend(vec![Complex::new(0.0, 0.0); 3 * n]);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let std = (noise_power / 2.0).sqrt();
+        let mut gaussian = move || {
+            let u1: f64 = 1.0 - rng.random::<f64>();
+            let u2: f64 = rng.random();
+            ((-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos() * std) as f32
+        };
+
+        samples
+            .into_iter()
+            .enumerate()
+            .map(|(k, sample)| {
+                let phase = 2.0 * PI * cfo * k as f64 / n as f64;
+                sample * Complex::from_polar(1.0, phase as f32)
+                    + Complex::new(gaussian(), gaussian())
+            })
+            .collect()
+    }
+
+    fn decode(
+        params: &LoraParams,
+        samples: Vec<Complex<f32>>,
+        sample_rate: f64,
+    ) -> Vec<LoraPacket> {
+        let blocks = samples.chunks(1000).map(|chunk| {
+            SampleBlock::new(
+                chunk.to_vec(),
+                sample_rate,
+                435e6,
+                Timestamp::Hardware(Duration::ZERO),
+            )
+        });
+
+        LoraDecoder::new(params.clone())
+            .unwrap()
+            .packets(blocks)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn decodes_explicit_header_packet() {
+        for coding_rate in 1..=4 {
+            let params = LoraParams::new(7, 125_000.0);
+            let samples = modulate(&params, PAYLOAD, coding_rate, 37, 0.0, 0.0);
+            let packets = decode(&params, samples, 125_000.0);
+
+            assert_eq!(packets.len(), 1, "CR 4/{}", 4 + coding_rate);
+            assert_eq!(packets[0].payload, PAYLOAD);
+            assert_eq!(packets[0].coding_rate, coding_rate as u8);
+            assert_eq!(packets[0].crc_ok, Some(true));
+            assert!(packets[0].is_valid());
+        }
+    }
+
+    #[test]
+    fn corrects_timing_and_frequency_offset_in_noise() {
+        let params = LoraParams::new(9, 125_000.0);
+        // -10 dB SNR over the bandwidth, 10 bins (2.4 kHz) off.
+        let samples = modulate(&params, PAYLOAD, 4, 301, 10.0, 10.0);
+        let packets = decode(&params, samples, 125_000.0);
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].payload, PAYLOAD);
+        assert_eq!(packets[0].crc_ok, Some(true));
+        assert!(
+            (packets[0].frequency_offset - 2441.4).abs() < 1.0,
+            "{}",
+            packets[0].frequency_offset
+        );
+        assert!(
+            (packets[0].snr_db + 10.0).abs() < 1.5,
+            "{}",
+            packets[0].snr_db
+        );
+    }
+
+    #[test]
+    fn decodes_implicit_header_with_low_data_rate_optimization() {
+        let mut params = LoraParams::new(12, 125_000.0);
+        assert!(params.low_data_rate);
+        params.header = LoraHeader::Implicit {
+            payload_len: PAYLOAD.len(),
+            coding_rate: 1,
+            has_crc: true,
+        };
+
+        let samples = modulate(&params, PAYLOAD, 1, 1000, 0.0, 0.0);
+        let packets = decode(&params, samples, 125_000.0);
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].payload, PAYLOAD);
+        assert_eq!(packets[0].crc_ok, Some(true));
+    }
+
+    #[test]
+    fn decimates_oversampled_input() {
+        let params = LoraParams::new(8, 125_000.0);
+        let samples: Vec<Complex<f32>> = modulate(&params, PAYLOAD, 2, 0, 0.0, 0.0)
+            .into_iter()
+            .flat_map(|sample| [sample; 4])
+            .collect();
+        let packets = decode(&params, samples, 500_000.0);
+
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].payload, PAYLOAD);
+    }
+
+    #[test]
+    fn needs_a_multiple_of_the_bandwidth() {
+        let params = LoraParams::new(8, 125_000.0);
+
+        assert_eq!(params.oversampling(500_000.0), Ok(4));
+        assert_eq!(
+            params.oversampling(48_000.0),
+            Err(LoraError::SampleRate(48_000.0))
+        );
+    }
+
+    #[test]
+    fn fails_on_samples_at_another_rate() {
+        let params = LoraParams::new(8, 125_000.0);
+        let block = SampleBlock::new(
+            vec![Complex::new(0.0, 0.0); 1000],
+            48_000.0,
+            435e6,
+            Timestamp::Hardware(Duration::ZERO),
+        );
+        let mut packets = LoraDecoder::new(params)
+            .unwrap()
+            .packets(vec![block.clone(), block].into_iter());
+
+        assert!(matches!(
+            packets.next(),
+            Some(Err(DemodulatorError::Lora(LoraError::SampleRate(rate)))) if rate == 48_000.0
+        ));
+        assert!(packets.next().is_none());
+    }
+
+    #[test]
+    fn hamming_corrects_single_bit_errors() {
+        for nibble in 0..16 {
+            for coding_rate in 3..=4 {
+                let codeword = hamming_encode(nibble, coding_rate);
+                for bit in 0..4 + coding_rate {
+                    assert_eq!(
+                        hamming_decode(codeword ^ 1 << bit, coding_rate),
+                        (nibble, true)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn whitening_sequence() {
+        assert_eq!(
+            dewhiten(&[0; 10]),
+            [0xFF, 0xFE, 0xFC, 0xF8, 0xF0, 0xE1, 0xC2, 0x85, 0x0B, 0x17]
+        );
+    }
+}
@@ -0,0 +1,861 @@
+//! SSTV (slow scan television) picture decoder, for the FM audio of ISS and CubeSat SSTV events.
+//!
+//! SSTV sends pixels as tones, from 1500 Hz for black to 2300 Hz for white, with 1200 Hz sync
+//! pulses. Each picture is announced by a VIS code: a 1900 Hz leader, then seven bits at 30 ms
+//! (1100 Hz for a `1`, 1300 Hz for a `0`), LSB first, between 1200 Hz start and stop bits.
+//!
+//! The decoder tracks the audio frequency, detects VIS codes and, once a whole picture has been
+//! received, finds its sync pulses. The line period is fitted to them, which corrects the slant
+//! caused by the difference between the transmitter's clock and ours.
+
+use crate::{filter::low_pass, fm::AUDIO_RATE};
+use sdr::block::Complex;
+use std::{collections::VecDeque, f64::consts::PI, io};
+
+/// Rate the audio frequency is tracked at, in Hz.
+const FREQUENCY_RATE: f64 = 12_000.0;
+/// Audio samples per frequency sample.
+const DECIMATION: u64 = (AUDIO_RATE / FREQUENCY_RATE) as u64;
+/// Frequency the audio is mixed down from, in the middle of the SSTV tones.
+const CENTER_FREQUENCY: f64 = 1900.0;
+/// Cutoff of the low-pass filter of the mixed down audio, in Hz.
+const FILTER_CUTOFF: f64 = 2200.0;
+/// Length of the low-pass filter, in seconds.
+const FILTER_DURATION: f64 = 0.004;
+
+const BLACK: f64 = 1500.0;
+const WHITE: f64 = 2300.0;
+const SYNC: f64 = 1200.0;
+const LEADER: f64 = 1900.0;
+/// Largest error of the mean frequency of a VIS tone, in Hz.
+const VIS_TOLERANCE: f64 = 50.0;
+/// Length of each VIS bit, in seconds.
+const VIS_BIT: f64 = 0.030;
+/// Length of each VIS leader, in seconds.
+const VIS_LEADER: f64 = 0.300;
+const VIS_BREAK: f64 = 0.010;
+/// Mean frequency under which a sync pulse is found, in Hz.
+const SYNC_LEVEL: f64 = 1350.0;
+/// How far from where it's predicted a sync pulse is searched for, in seconds.
+const SYNC_SEARCH: f64 = 0.004;
+/// Sync pulses further than this from the fitted line are ignored, in seconds.
+const SYNC_OUTLIER: f64 = 0.001;
+/// Largest relative difference between the transmitter's clock and ours.
+const MAX_CLOCK_ERROR: f64 = 0.015;
+
+/// Supported SSTV modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SstvMode {
+    Robot36,
+    Martin1,
+    Martin2,
+    Scottie1,
+    Scottie2,
+    Pd120,
+    Pd180,
+}
+
+impl SstvMode {
+    pub fn from_vis(code: u8) -> Option<Self> {
+        match code {
+            8 => Some(Self::Robot36),
+            44 => Some(Self::Martin1),
+            40 => Some(Self::Martin2),
+            60 => Some(Self::Scottie1),
+            56 => Some(Self::Scottie2),
+            95 => Some(Self::Pd120),
+            96 => Some(Self::Pd180),
+            _ => None,
+        }
+    }
+
+    pub fn vis_code(&self) -> u8 {
+        match self {
+            Self::Robot36 => 8,
+            Self::Martin1 => 44,
+            Self::Martin2 => 40,
+            Self::Scottie1 => 60,
+            Self::Scottie2 => 56,
+            Self::Pd120 => 95,
+            Self::Pd180 => 96,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Robot36 => "Robot 36",
+            Self::Martin1 => "Martin 1",
+            Self::Martin2 => "Martin 2",
+            Self::Scottie1 => "Scottie 1",
+            Self::Scottie2 => "Scottie 2",
+            Self::Pd120 => "PD120",
+            Self::Pd180 => "PD180",
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.timing().width
+    }
+
+    pub fn height(&self) -> usize {
+        self.timing().groups * self.timing().rows_per_group
+    }
+
+    fn timing(&self) -> &'static Timing {
+        match self {
+            Self::Robot36 => &ROBOT36,
+            Self::Martin1 => &MARTIN1,
+            Self::Martin2 => &MARTIN2,
+            Self::Scottie1 => &SCOTTIE1,
+            Self::Scottie2 => &SCOTTIE2,
+            Self::Pd120 => &PD120,
+            Self::Pd180 => &PD180,
+        }
+    }
+}
+
+/// What a scan carries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Channel {
+    Red,
+    Green,
+    Blue,
+    /// Luminance of a row of the group.
+    Luma(usize),
+    /// B-Y, shared by the rows of the group.
+    BlueDifference,
+    /// R-Y, shared by the rows of the group.
+    RedDifference,
+}
+
+/// A run of pixels across the whole width.
+#[derive(Debug)]
+struct Scan {
+    /// From the start of the group, in ms.
+    start: f64,
+    /// ms.
+    duration: f64,
+    channel: Channel,
+}
+
+/// Timing of a mode. Lines are sent in groups that repeat with a sync pulse each: one line for
+/// RGB modes, two for those that share the color differences between lines.
+#[derive(Debug)]
+struct Timing {
+    width: usize,
+    rows_per_group: usize,
+    groups: usize,
+    /// ms.
+    group_time: f64,
+    /// Sync pulse sent once before the first group, in ms.
+    initial_sync: f64,
+    /// Start of the sync pulse of each group, in ms.
+    sync_offset: f64,
+    /// ms.
+    sync_time: f64,
+    scans: &'static [Scan],
+}
+
+const fn scan(start: f64, duration: f64, channel: Channel) -> Scan {
+    Scan {
+        start,
+        duration,
+        channel,
+    }
+}
+
+/// Sync, porch, then green, blue and red, each followed by a separator.
+const MARTIN1: Timing = Timing {
+    width: 320,
+    rows_per_group: 1,
+    groups: 256,
+    group_time: 446.446,
+    initial_sync: 0.0,
+    sync_offset: 0.0,
+    sync_time: 4.862,
+    scans: &[
+        scan(5.434, 146.432, Channel::Green),
+        scan(152.438, 146.432, Channel::Blue),
+        scan(299.442, 146.432, Channel::Red),
+    ],
+};
+
+const MARTIN2: Timing = Timing {
+    width: 320,
+    rows_per_group: 1,
+    groups: 256,
+    group_time: 226.798,
+    initial_sync: 0.0,
+    sync_offset: 0.0,
+    sync_time: 4.862,
+    scans: &[
+        scan(5.434, 73.216, Channel::Green),
+        scan(79.222, 73.216, Channel::Blue),
+        scan(153.01, 73.216, Channel::Red),
+    ],
+};
+
+/// Separator, green, separator, blue, then the sync and porch in the middle of the line before
+/// red.
+const SCOTTIE1: Timing = Timing {
+    width: 320,
+    rows_per_group: 1,
+    groups: 256,
+    group_time: 428.22,
+    initial_sync: 9.0,
+    sync_offset: 279.48,
+    sync_time: 9.0,
+    scans: &[
+        scan(1.5, 138.24, Channel::Green),
+        scan(141.24, 138.24, Channel::Blue),
+        scan(289.98, 138.24, Channel::Red),
+    ],
+};
+
+const SCOTTIE2: Timing = Timing {
+    width: 320,
+    rows_per_group: 1,
+    groups: 256,
+    group_time: 277.692,
+    initial_sync: 9.0,
+    sync_offset: 179.128,
+    sync_time: 9.0,
+    scans: &[
+        scan(1.5, 88.064, Channel::Green),
+        scan(91.064, 88.064, Channel::Blue),
+        scan(189.628, 88.064, Channel::Red),
+    ],
+};
+
+/// Two 150 ms lines, each a sync, porch, luminance, separator, porch and a color difference at
+/// double speed: R-Y on even lines, B-Y on odd ones.
+const ROBOT36: Timing = Timing {
+    width: 320,
+    rows_per_group: 2,
+    groups: 120,
+    group_time: 300.0,
+    initial_sync: 0.0,
+    sync_offset: 0.0,
+    sync_time: 9.0,
+    scans: &[
+        scan(12.0, 88.0, Channel::Luma(0)),
+        scan(106.0, 44.0, Channel::RedDifference),
+        scan(162.0, 88.0, Channel::Luma(1)),
+        scan(256.0, 44.0, Channel::BlueDifference),
+    ],
+};
+
+/// Sync, porch, then the luminance of a line, R-Y, B-Y and the luminance of the next line.
+const PD120: Timing = Timing {
+    width: 640,
+    rows_per_group: 2,
+    groups: 248,
+    group_time: 508.48,
+    initial_sync: 0.0,
+    sync_offset: 0.0,
+    sync_time: 20.0,
+    scans: &[
+        scan(22.08, 121.6, Channel::Luma(0)),
+        scan(143.68, 121.6, Channel::RedDifference),
+        scan(265.28, 121.6, Channel::BlueDifference),
+        scan(386.88, 121.6, Channel::Luma(1)),
+    ],
+};
+
+const PD180: Timing = Timing {
+    width: 640,
+    rows_per_group: 2,
+    groups: 248,
+    group_time: 754.24,
+    initial_sync: 0.0,
+    sync_offset: 0.0,
+    sync_time: 20.0,
+    scans: &[
+        scan(22.08, 183.04, Channel::Luma(0)),
+        scan(205.12, 183.04, Channel::RedDifference),
+        scan(388.16, 183.04, Channel::BlueDifference),
+        scan(571.2, 183.04, Channel::Luma(1)),
+    ],
+};
+
+/// A decoded picture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SstvImage {
+    pub mode: SstvMode,
+    pub width: usize,
+    /// Rows received, fewer than the mode's if the picture was cut short.
+    pub height: usize,
+    /// 8-bit RGB, row by row.
+    pub pixels: Vec<u8>,
+    /// Line groups whose sync pulse was found.
+    pub synced_lines: usize,
+}
+
+impl SstvImage {
+    /// Encodes the picture as an 8-bit RGB PNG.
+    pub fn to_png(&self) -> io::Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(io::Error::other)?;
+
+        Ok(png)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Waiting for a VIS code.
+    Idle,
+    /// Receiving a picture, starting at `start` in the frequencies.
+    Receiving { mode: SstvMode, start: f64 },
+}
+
+/// Decodes SSTV pictures from FM demodulated audio at [`AUDIO_RATE`].
+pub struct SstvDecoder {
+    /// Low-pass filter of the mixed down audio, at the audio rate.
+    taps: Vec<f32>,
+    /// Latest mixed down audio samples, as many as taps.
+    mixed: VecDeque<Complex<f32>>,
+    oscillator_phase: f64,
+    /// Audio samples seen.
+    samples: u64,
+    previous: Complex<f32>,
+    /// Audio frequency, at `FREQUENCY_RATE`.
+    frequencies: Vec<f32>,
+    /// Sums of the frequencies before each index, one more than them.
+    sums: Vec<f64>,
+    state: State,
+}
+
+impl Default for SstvDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SstvDecoder {
+    pub fn new() -> Self {
+        Self {
+            taps: low_pass(FILTER_CUTOFF, AUDIO_RATE, FILTER_DURATION),
+            mixed: VecDeque::new(),
+            oscillator_phase: 0.0,
+            samples: 0,
+            previous: Complex::new(0.0, 0.0),
+            frequencies: Vec::new(),
+            sums: vec![0.0],
+            state: State::Idle,
+        }
+    }
+
+    /// Feeds audio samples, returning the pictures they completed.
+    pub fn feed(&mut self, audio: &[f32]) -> Vec<SstvImage> {
+        let mut images = Vec::new();
+
+        for sample in audio {
+            let mixed = Complex::from_polar(*sample, -self.oscillator_phase as f32);
+            self.oscillator_phase =
+                (self.oscillator_phase + 2.0 * PI * CENTER_FREQUENCY / AUDIO_RATE) % (2.0 * PI);
+
+            self.mixed.push_back(mixed);
+            if self.mixed.len() > self.taps.len() {
+                self.mixed.pop_front();
+            }
+            self.samples += 1;
+
+            if self.mixed.len() < self.taps.len() || !self.samples.is_multiple_of(DECIMATION) {
+                continue;
+            }
+
+            let filtered: Complex<f32> = self
+                .mixed
+                .iter()
+                .zip(&self.taps)
+                .map(|(sample, tap)| sample * tap)
+                .sum();
+            let frequency = CENTER_FREQUENCY
+                + (filtered * self.previous.conj()).arg() as f64 * FREQUENCY_RATE / (2.0 * PI);
+            self.previous = filtered;
+
+            self.frequencies.push(frequency as f32);
+            self.sums
+                .push(self.sums.last().expect("never empty") + frequency);
+
+            images.extend(self.step());
+        }
+
+        images
+    }
+
+    /// Mode of the picture being received, if any.
+    pub fn mode(&self) -> Option<SstvMode> {
+        match self.state {
+            State::Idle => None,
+            State::Receiving { mode, .. } => Some(mode),
+        }
+    }
+
+    /// The picture being received, as far as it got.
+    pub fn finish(self) -> Option<SstvImage> {
+        match self.state {
+            State::Idle => None,
+            State::Receiving { mode, start } => {
+                let (image, _) = self.render(mode, start);
+                (image.height > 0).then_some(image)
+            }
+        }
+    }
+
+    /// Advances the state with the latest frequency.
+    fn step(&mut self) -> Option<SstvImage> {
+        match self.state {
+            State::Idle => {
+                if let Some((mode, start)) = self.detect_vis() {
+                    self.state = State::Receiving { mode, start };
+                } else {
+                    self.trim();
+                }
+
+                None
+            }
+            State::Receiving { mode, start } => {
+                let timing = mode.timing();
+                let duration = (timing.initial_sync + timing.groups as f64 * timing.group_time)
+                    * 1e-3
+                    * (1.0 + MAX_CLOCK_ERROR)
+                    + SYNC_SEARCH;
+                if (self.frequencies.len() as f64) < start + duration * FREQUENCY_RATE {
+                    return None;
+                }
+
+                let (image, end) = self.render(mode, start);
+                self.discard(end);
+                self.state = State::Idle;
+
+                Some(image)
+            }
+        }
+    }
+
+    /// Checks whether the latest frequencies end a VIS code, returning its mode and where the
+    /// picture starts.
+    fn detect_vis(&self) -> Option<(SstvMode, f64)> {
+        let bit = VIS_BIT * FREQUENCY_RATE;
+        let leader = VIS_LEADER * FREQUENCY_RATE;
+        // Start of the start bit, if the stop bit just ended.
+        let start_bit = self.frequencies.len() as f64 - 10.0 * bit;
+        let first_leader = start_bit - 2.0 * leader - VIS_BREAK * FREQUENCY_RATE;
+        if first_leader < 0.0 {
+            return None;
+        }
+
+        let near = |from: f64, len: f64, frequency: f64| {
+            (self.inner_mean(from, len) - frequency).abs() < VIS_TOLERANCE
+        };
+        if !near(start_bit + 9.0 * bit, bit, SYNC)
+            || !near(start_bit, bit, SYNC)
+            || !near(start_bit - leader, leader, LEADER)
+            || !near(first_leader, leader, LEADER)
+        {
+            return None;
+        }
+
+        // Seven data bits and the even parity bit.
+        let mut code = 0u8;
+        let mut ones = 0;
+        for i in 0..8 {
+            let mean = self.inner_mean(start_bit + (1 + i) as f64 * bit, bit);
+            if (mean - 1200.0).abs() > 150.0 {
+                return None;
+            }
+            if mean < 1200.0 {
+                ones += 1;
+                if i < 7 {
+                    code |= 1 << i;
+                }
+            }
+        }
+        if ones % 2 != 0 {
+            return None;
+        }
+
+        let mode = SstvMode::from_vis(code)?;
+
+        // The leader to start bit edge gives the precise timing.
+        let edge_window = VIS_BREAK * FREQUENCY_RATE / 2.0;
+        let edge = (-120..=120)
+            .map(|offset| start_bit + offset as f64)
+            .max_by(|a, b| {
+                let step =
+                    |at: f64| self.mean(at - edge_window, edge_window) - self.mean(at, edge_window);
+                step(*a).total_cmp(&step(*b))
+            })
+            .expect("the search range isn't empty");
+
+        Some((mode, edge + 10.0 * bit))
+    }
+
+    /// Renders the picture starting at `start`, as far as the frequencies go, returning it and
+    /// where it ends.
+    fn render(&self, mode: SstvMode, start: f64) -> (SstvImage, f64) {
+        let timing = mode.timing();
+        let ms = FREQUENCY_RATE * 1e-3;
+        let nominal = timing.group_time * ms;
+        let available = self.frequencies.len() as f64;
+
+        // Sync of each group, tracked from the first one on.
+        let first_sync = start + (timing.initial_sync + timing.sync_offset) * ms;
+        let sync_len = timing.sync_time * ms;
+        let window = (SYNC_SEARCH * FREQUENCY_RATE).round() as i64;
+        let mut syncs = Vec::new();
+        let (mut origin, mut period) = (first_sync, nominal);
+
+        for group in 0..timing.groups {
+            let predicted = (origin + group as f64 * period).round();
+            if predicted + (window as f64) + sync_len > available {
+                break;
+            }
+
+            let (position, level) = (-window..=window)
+                .map(|offset| {
+                    let position = predicted + offset as f64;
+                    (position, self.mean(position, sync_len))
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .expect("the search range isn't empty");
+
+            if level < SYNC_LEVEL {
+                syncs.push((group as f64, position));
+                if let Some(line) = fit(&syncs, nominal) {
+                    (origin, period) = line;
+                }
+            }
+        }
+
+        // Refitted without the pulses off the line, e.g. image content mistaken for a sync.
+        let outlier = SYNC_OUTLIER * FREQUENCY_RATE;
+        let inliers: Vec<(f64, f64)> = syncs
+            .iter()
+            .copied()
+            .filter(|(group, position)| (origin + group * period - position).abs() <= outlier)
+            .collect();
+        if let Some(line) = fit(&inliers, nominal) {
+            (origin, period) = line;
+        }
+
+        // Everything in a group stretches with the transmitter's clock.
+        let scale = period / nominal;
+        let group_start =
+            |group: usize| origin + group as f64 * period - timing.sync_offset * ms * scale;
+
+        let width = timing.width;
+        let mut pixels = Vec::new();
+        let mut groups = 0;
+        while groups < timing.groups && group_start(groups + 1) <= available {
+            let start = group_start(groups);
+            let scans: Vec<(Channel, Vec<f64>)> = timing
+                .scans
+                .iter()
+                .map(|scan| {
+                    let pixel_len = scan.duration * ms * scale / width as f64;
+                    let scan_start = start + scan.start * ms * scale;
+                    let levels = (0..width)
+                        .map(|x| {
+                            let frequency = self.mean(scan_start + x as f64 * pixel_len, pixel_len);
+                            ((frequency - BLACK) / (WHITE - BLACK) * 255.0).clamp(0.0, 255.0)
+                        })
+                        .collect();
+                    (scan.channel, levels)
+                })
+                .collect();
+            let channel = |channel: Channel| {
+                scans
+                    .iter()
+                    .find(|(scan_channel, _)| *scan_channel == channel)
+                    .map(|(_, levels)| levels)
+            };
+
+            for row in 0..timing.rows_per_group {
+                for x in 0..width {
+                    let rgb = match channel(Channel::Luma(row)) {
+                        Some(luma) => {
+                            let y = luma[x];
+                            let cr = channel(Channel::RedDifference).expect("YUV mode")[x] - 128.0;
+                            let cb = channel(Channel::BlueDifference).expect("YUV mode")[x] - 128.0;
+                            [
+                                y + 1.402 * cr,
+                                y - 0.344136 * cb - 0.714136 * cr,
+                                y + 1.772 * cb,
+                            ]
+                        }
+                        None => [Channel::Red, Channel::Green, Channel::Blue]
+                            .map(|color| channel(color).expect("RGB mode")[x]),
+                    };
+                    pixels.extend(rgb.map(|level| level.clamp(0.0, 255.0).round() as u8));
+                }
+            }
+            groups += 1;
+        }
+
+        let image = SstvImage {
+            mode,
+            width,
+            height: groups * timing.rows_per_group,
+            pixels,
+            synced_lines: inliers.len(),
+        };
+
+        (image, group_start(groups).min(available))
+    }
+
+    /// Mean frequency from `from`, over `len` frequency samples, clamped to those received.
+    fn mean(&self, from: f64, len: f64) -> f64 {
+        let last = self.frequencies.len();
+        let start = (from.round().max(0.0) as usize).min(last);
+        let end = ((from + len).round().max(0.0) as usize).clamp(start, last);
+        if end == start {
+            return self
+                .frequencies
+                .get(start)
+                .map_or(0.0, |frequency| *frequency as f64);
+        }
+
+        (self.sums[end] - self.sums[start]) / (end - start) as f64
+    }
+
+    /// Mean frequency of the middle 80% of the `len` samples from `from`, so that tones are
+    /// measured even if slightly misaligned.
+    fn inner_mean(&self, from: f64, len: f64) -> f64 {
+        self.mean(from + 0.1 * len, 0.8 * len)
+    }
+
+    /// Drops the frequencies older than a VIS code while waiting for one.
+    fn trim(&mut self) {
+        let keep = ((2.0 * VIS_LEADER + VIS_BREAK + 10.0 * VIS_BIT) * FREQUENCY_RATE) as usize;
+        if self.frequencies.len() > 2 * keep {
+            self.discard((self.frequencies.len() - keep) as f64);
+        }
+    }
+
+    /// Drops the frequencies before `end`.
+    fn discard(&mut self, end: f64) {
+        let end = (end.max(0.0) as usize).min(self.frequencies.len());
+        self.frequencies.drain(..end);
+        self.sums.drain(..end);
+        let base = self.sums[0];
+        for sum in &mut self.sums {
+            *sum -= base;
+        }
+    }
+}
+
+/// Least squares line through the sync pulses, as the position of the first and the period, if
+/// there are enough pulses and the period is plausible.
+fn fit(syncs: &[(f64, f64)], nominal: f64) -> Option<(f64, f64)> {
+    if syncs.len() < 2 {
+        return None;
+    }
+
+    let n = syncs.len() as f64;
+    let mean_x = syncs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = syncs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut sxx, mut sxy) = (0.0, 0.0);
+    for (x, y) in syncs {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if sxx == 0.0 {
+        return None;
+    }
+
+    let period = sxy / sxx;
+    if (period / nominal - 1.0).abs() > MAX_CLOCK_ERROR {
+        return None;
+    }
+
+    Some((mean_y - period * mean_x, period))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frequency of the test picture at `(x, y)`, for `channel`, from RGB bands: red grows to the
+    /// right, green downwards, and blue is a vertical bar in the middle.
+    fn level(mode: SstvMode, channel: Channel, x: usize, y: usize) -> f64 {
+        let [r, g, b] = color(mode, x, y);
+        let ycbcr = |y: usize| {
+            let [r, g, b] = color(mode, x, y);
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+            [luma, 128.0 + (b - luma) / 1.772, 128.0 + (r - luma) / 1.402]
+        };
+        let level = match channel {
+            Channel::Red => r,
+            Channel::Green => g,
+            Channel::Blue => b,
+            Channel::Luma(row) => ycbcr(y + row)[0],
+            Channel::BlueDifference => ycbcr(y)[1],
+            Channel::RedDifference => ycbcr(y)[2],
+        };
+        BLACK + level / 255.0 * (WHITE - BLACK)
+    }
+
+    fn color(mode: SstvMode, x: usize, y: usize) -> [f64; 3] {
+        let (width, height) = (mode.width(), mode.height());
+        let bar = (x * 8 / width == 4) as u8 as f64 * 200.0;
+        [(x * 255 / width) as f64, (y * 255 / height) as f64, bar]
+    }
+
+    /// The FM audio of the VIS code of `mode` and the first `groups` line groups of its picture,
+    /// sent by a transmitter whose clock is `clock_error` fast.
+    fn transmit(mode: SstvMode, groups: usize, clock_error: f64) -> Vec<f32> {
+        // Tones as (frequency, ms).
+        let mut tones = vec![
+            (1500.0, 500.0),
+            (LEADER, 300.0),
+            (SYNC, 10.0),
+            (LEADER, 300.0),
+            (SYNC, 30.0),
+        ];
+        let code = mode.vis_code();
+        for bit in 0..7 {
+            tones.push((if code >> bit & 1 == 1 { 1100.0 } else { 1300.0 }, 30.0));
+        }
+        let parity = code.count_ones() % 2 == 1;
+        tones.push((if parity { 1100.0 } else { 1300.0 }, 30.0));
+        tones.push((SYNC, 30.0));
+
+        let timing = mode.timing();
+        if timing.initial_sync > 0.0 {
+            tones.push((SYNC, timing.initial_sync));
+        }
+        for group in 0..groups {
+            // Whatever isn't a scan or the sync is a porch or a separator.
+            let mut spans: Vec<(f64, f64, Option<&Scan>)> = timing
+                .scans
+                .iter()
+                .map(|scan| (scan.start, scan.duration, Some(scan)))
+                .collect();
+            spans.push((timing.sync_offset, timing.sync_time, None));
+            spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut time = 0.0;
+            for (start, duration, scan) in spans {
+                if start > time {
+                    tones.push((BLACK, start - time));
+                }
+                match scan {
+                    Some(scan) => {
+                        let pixel = duration / timing.width as f64;
+                        for x in 0..timing.width {
+                            let y = group * timing.rows_per_group;
+                            tones.push((level(mode, scan.channel, x, y), pixel));
+                        }
+                    }
+                    None => tones.push((SYNC, duration)),
+                }
+                time = start + duration;
+            }
+            if timing.group_time > time {
+                tones.push((BLACK, timing.group_time - time));
+            }
+        }
+        tones.push((BLACK, 2000.0));
+
+        let mut audio = Vec::new();
+        let (mut phase, mut time, mut end) = (0.0, 0.0, 0.0);
+        for (frequency, duration) in tones {
+            end += duration * 1e-3 / (1.0 + clock_error);
+            while time < end {
+                phase = (phase + 2.0 * PI * frequency / AUDIO_RATE) % (2.0 * PI);
+                audio.push(0.5 * phase.sin() as f32);
+                time += 1.0 / AUDIO_RATE;
+            }
+        }
+
+        audio
+    }
+
+    fn decode(audio: &[f32]) -> Vec<SstvImage> {
+        let mut decoder = SstvDecoder::new();
+        audio
+            .chunks(4800)
+            .flat_map(|chunk| decoder.feed(chunk))
+            .collect()
+    }
+
+    /// Mean absolute difference with the test picture, away from the color edges.
+    fn error(image: &SstvImage) -> f64 {
+        let mut total = 0.0;
+        let mut count = 0;
+        for y in 0..image.height {
+            for x in 4..image.width - 4 {
+                if (x * 8 / image.width) != ((x + 4) * 8 / image.width)
+                    || (x * 8 / image.width) != ((x - 4) * 8 / image.width)
+                {
+                    continue;
+                }
+                let expected = color(image.mode, x, y);
+                let pixel = &image.pixels[(y * image.width + x) * 3..][..3];
+                for (expected, actual) in expected.iter().zip(pixel) {
+                    total += (expected - *actual as f64).abs();
+                    count += 1;
+                }
+            }
+        }
+
+        total / count as f64
+    }
+
+    #[test]
+    fn decodes_rgb_modes() {
+        let images = decode(&transmit(SstvMode::Martin2, 256, 0.0));
+
+        assert_eq!(images.len(), 1);
+        let image = &images[0];
+        assert_eq!(image.mode, SstvMode::Martin2);
+        assert_eq!((image.width, image.height), (320, 256));
+        assert!(image.synced_lines > 250, "{}", image.synced_lines);
+        assert!(error(image) < 8.0, "{}", error(image));
+    }
+
+    #[test]
+    fn corrects_the_slant_of_yuv_modes() {
+        let images = decode(&transmit(SstvMode::Robot36, 120, 0.005));
+
+        assert_eq!(images.len(), 1);
+        let image = &images[0];
+        assert_eq!(image.mode, SstvMode::Robot36);
+        assert_eq!((image.width, image.height), (320, 240));
+        assert!(error(image) < 8.0, "{}", error(image));
+    }
+
+    #[test]
+    fn detects_vis_codes() {
+        for mode in [
+            SstvMode::Robot36,
+            SstvMode::Martin1,
+            SstvMode::Martin2,
+            SstvMode::Scottie1,
+            SstvMode::Scottie2,
+            SstvMode::Pd120,
+            SstvMode::Pd180,
+        ] {
+            assert_eq!(SstvMode::from_vis(mode.vis_code()), Some(mode));
+
+            let mut decoder = SstvDecoder::new();
+            decoder.feed(&transmit(mode, 4, 0.0));
+            assert_eq!(decoder.mode(), Some(mode));
+
+            // The part of the picture received so far, up to the end of the audio.
+            let image = decoder.finish().unwrap();
+            assert_eq!(image.width, mode.width());
+            assert!(image.height >= 4 * mode.timing().rows_per_group);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Async counterpart of [`Demodulator`], on streams of samples.
+//!
+//! The sync demodulators run on a blocking thread behind [`Blocking`], reading the input stream as
+//! a [`BlockingStream`]. Dropping the output stream cancels them: their input ends, and so does
+//! the thread.
+
+use crate::{Demodulator, DemodulatorError};
+use blocking::{BlockingStream, run_blocking};
+use sdr::block::SampleBlock;
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+
+/// Bits demodulated ahead of the consumer of the output stream.
+const BITS_QUEUE_LEN: usize = 64;
+
+/// Demodulates a stream of samples into a stream of bits.
+///
+/// The output ends when the input does, or after a fatal error. Transient errors are output in
+/// between the bits.
+pub trait AsyncDemodulator<S>
+where
+    S: Stream<Item = SampleBlock>,
+{
+    type Output: Stream<Item = Result<Vec<bool>, DemodulatorError>>;
+
+    fn bits(&self, input: S) -> Self::Output;
+}
+
+/// Runs a sync [`Demodulator`] as an [`AsyncDemodulator`], on a blocking thread.
+pub struct Blocking<D>(pub D);
+
+impl<D, S> AsyncDemodulator<S> for Blocking<D>
+where
+    S: Stream<Item = SampleBlock> + Send + 'static,
+    D: Demodulator<BlockingStream<S>>,
+    D::Output: Send + 'static,
+{
+    type Output = ReceiverStream<Result<Vec<bool>, DemodulatorError>>;
+
+    /// Must be called within a Tokio runtime.
+    fn bits(&self, input: S) -> Self::Output {
+        run_blocking(input, BITS_QUEUE_LEN, |input| self.0.bits(input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::example::ExampleDemod;
+    use sdr::block::{Complex, Timestamp};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio_stream::StreamExt;
+
+    fn block() -> SampleBlock {
+        SampleBlock::new(
+            vec![Complex::new(0.0, 0.0)],
+            48_000.0,
+            0.0,
+            Timestamp::Hardware(Duration::ZERO),
+        )
+    }
+
+    #[tokio::test]
+    async fn outputs_the_bits_of_the_sync_demodulator() {
+        let demodulator = Blocking(ExampleDemod::new());
+        let input = tokio_stream::iter((0..35).map(|_| block()));
+
+        let bits: Vec<_> = demodulator.bits(input).map(Result::unwrap).collect().await;
+
+        assert_eq!(bits, vec![vec![true]; 3]);
+    }
+
+    #[tokio::test]
+    async fn dropping_the_output_stops_reading_the_input() {
+        let demodulator = Blocking(ExampleDemod::new());
+        let (tx, rx) = mpsc::channel(1);
+        let mut bits = demodulator.bits(ReceiverStream::new(rx));
+
+        for _ in 0..10 {
+            tx.send(block()).await.unwrap();
+        }
+        assert!(bits.next().await.unwrap().is_ok());
+        drop(bits);
+
+        // The input is dropped along with the demodulator once its thread ends.
+        tokio::time::timeout(Duration::from_secs(5), tx.closed())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+//! Throughput of the HDLC deframer, against the 9600 baud it has to keep up with in real time.
+//!
+//! ```text
+//! cargo bench -p framing --bench hdlc_deframer
+//! ```
+
+use framing::{deframer::Deframer, frame::Frame, hdlc_deframer::HdlcDeframer};
+use std::{hint::black_box, time::Instant};
+
+/// Fastest downlink the deframer is expected to handle.
+const BAUD: f64 = 9600.0;
+/// Bits per chunk, about what a demodulator hands over at a time.
+const CHUNK_LEN: usize = 512;
+/// Bits deframed in each case.
+const STREAM_LEN: usize = 20_000_000;
+
+/// Pseudo-random bits, the same on every run.
+struct Bits(u64);
+
+impl Bits {
+    fn next(&mut self) -> bool {
+        // xorshift64
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 & 1 == 1
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| (0..8).fold(0, |byte, i| byte | (self.next() as u8) << i))
+            .collect()
+    }
+}
+
+/// Frames of `payload_len` random bytes, with `garbage_len` random bits between them.
+fn stream(payload_len: usize, garbage_len: usize) -> Vec<bool> {
+    let mut rng = Bits(0x2545_f491_4f6c_dd1d);
+    let mut bits = Vec::with_capacity(STREAM_LEN);
+
+    while bits.len() < STREAM_LEN {
+        bits.extend(Frame::new(Some(rng.bytes(payload_len))).to_bits());
+        bits.extend((0..garbage_len).map(|_| rng.next()));
+    }
+
+    bits
+}
+
+fn bench(name: &str, bits: &[bool]) {
+    let chunks: Vec<Vec<bool>> = bits.chunks(CHUNK_LEN).map(<[bool]>::to_vec).collect();
+    let deframer = HdlcDeframer::new();
+
+    let start = Instant::now();
+    let frames = deframer.frames(black_box(chunks).into_iter()).count();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let rate = bits.len() as f64 / elapsed;
+    println!(
+        "{:<24} {:>8} frames {:>10.2} Mbit/s {:>8.0}x real time at {} Bd",
+        name,
+        frames,
+        rate / 1e6,
+        rate / BAUD,
+        BAUD
+    );
+}
+
+fn main() {
+    bench("back-to-back frames", &stream(32, 0));
+    bench("garbage between frames", &stream(32, 256));
+    bench("long frames", &stream(255, 0));
+
+    let mut rng = Bits(0x9e37_79b9_7f4a_7c15);
+    let garbage: Vec<bool> = (0..STREAM_LEN).map(|_| rng.next()).collect();
+    bench("garbage", &garbage);
+}
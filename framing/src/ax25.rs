@@ -0,0 +1,66 @@
+//! AX.25 frames, as deframed from HDLC: addresses, control and, for I and UI frames, a PID before
+//! the information field.
+
+/// Bytes of each address.
+const ADDRESS_LEN: usize = 7;
+/// Destination, source and up to 8 digipeaters.
+const MAX_ADDRESSES: usize = 10;
+/// Control byte of UI frames, P/F bit clear.
+const UI: u8 = 0x03;
+
+/// The information field of an AX.25 I or UI frame (without FCS, modulo 8), after its addresses,
+/// control and PID. `None` for other frames, which have none, and for bytes that aren't a frame.
+pub fn info(frame: &[u8]) -> Option<&[u8]> {
+    // The last address has the extension bit set.
+    let addresses = (1..=MAX_ADDRESSES)
+        .find(|n| {
+            frame
+                .get(n * ADDRESS_LEN - 1)
+                .is_some_and(|byte| byte & 1 == 1)
+        })
+        .filter(|n| *n >= 2)?;
+
+    let control = *frame.get(addresses * ADDRESS_LEN)?;
+    if control & 1 != 0 && control & !0x10 != UI {
+        return None;
+    }
+
+    // After the PID.
+    frame.get(addresses * ADDRESS_LEN + 2..)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` addresses, the last one with the extension bit set.
+    fn addresses(count: usize) -> Vec<u8> {
+        let mut bytes: Vec<u8> = (0..count)
+            .flat_map(|_| b"N0CALL\x60".map(|b| b << 1))
+            .collect();
+        *bytes.last_mut().unwrap() |= 1;
+        bytes
+    }
+
+    #[test]
+    fn info_of_ui_and_i_frames() {
+        let ui = [&addresses(2)[..], &[0x03, 0xF0], b"csp"].concat();
+        let digipeated = [&addresses(4)[..], &[0x13, 0xF0], b"csp"].concat();
+        let i_frame = [&addresses(2)[..], &[0x22, 0xCC], b"csp"].concat();
+
+        for frame in [ui, digipeated, i_frame] {
+            assert_eq!(info(&frame), Some(&b"csp"[..]));
+        }
+    }
+
+    #[test]
+    fn no_info_in_other_frames() {
+        let s_frame = [&addresses(2)[..], &[0x41]].concat();
+        let sabm = [&addresses(2)[..], &[0x2F]].concat();
+        let one_address = [&addresses(1)[..], &[0x03, 0xF0], b"csp"].concat();
+
+        for frame in [&s_frame[..], &sabm, &one_address, &[0x12, 0x34]] {
+            assert_eq!(info(frame), None);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! CSP (CubeSat Space Protocol) headers, at the start of the deframed payloads of spacecraft that
+//! speak it, e.g. over AX100 radios, KISS or CAN-to-RF bridges.
+//!
+//! Version 1 headers are 4 bytes: priority (2 bits), source and destination addresses (5 bits
+//! each), destination and source ports (6 bits each), and 8 bits of flags. Version 2 headers are
+//! 6 bytes: priority, destination and source addresses (14 bits each), destination and source
+//! ports, and 6 bits of flags. Both are big endian.
+
+/// Flag bits, in the low bits of both versions.
+const FLAG_FRAGMENTATION: u8 = 0x10;
+const FLAG_HMAC: u8 = 0x08;
+const FLAG_XTEA: u8 = 0x04;
+const FLAG_RDP: u8 = 0x02;
+const FLAG_CRC32: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CspVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+impl CspVersion {
+    /// Bytes of a header.
+    pub fn header_len(&self) -> usize {
+        match self {
+            CspVersion::V1 => 4,
+            CspVersion::V2 => 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CspError {
+    /// The packet is shorter than a header.
+    TooShort,
+}
+
+/// Options the packet was sent with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CspFlags {
+    pub fragmentation: bool,
+    /// Authenticated with an HMAC.
+    pub hmac: bool,
+    /// Encrypted with XTEA. Version 1 only.
+    pub xtea: bool,
+    /// Sent over RDP, the reliable datagram protocol.
+    pub rdp: bool,
+    /// Ends with a CRC32.
+    pub crc32: bool,
+}
+
+impl CspFlags {
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            fragmentation: bits & FLAG_FRAGMENTATION != 0,
+            hmac: bits & FLAG_HMAC != 0,
+            xtea: bits & FLAG_XTEA != 0,
+            rdp: bits & FLAG_RDP != 0,
+            crc32: bits & FLAG_CRC32 != 0,
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        [
+            (self.fragmentation, FLAG_FRAGMENTATION),
+            (self.hmac, FLAG_HMAC),
+            (self.xtea, FLAG_XTEA),
+            (self.rdp, FLAG_RDP),
+            (self.crc32, FLAG_CRC32),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |bits, (_, flag)| bits | flag)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CspHeader {
+    /// 0 (critical) to 3 (low).
+    pub priority: u8,
+    pub source: u16,
+    pub destination: u16,
+    pub destination_port: u8,
+    pub source_port: u8,
+    pub flags: CspFlags,
+}
+
+impl CspHeader {
+    /// Parses the header at the start of `packet`, returning it and the rest of the packet.
+    pub fn parse(packet: &[u8], version: CspVersion) -> Result<(Self, &[u8]), CspError> {
+        if packet.len() < version.header_len() {
+            return Err(CspError::TooShort);
+        }
+        let (header, rest) = packet.split_at(version.header_len());
+        let id = header.iter().fold(0u64, |id, &byte| id << 8 | byte as u64);
+        let field = |shift: u32, width: u32| (id >> shift & ((1 << width) - 1)) as u16;
+
+        let header = match version {
+            CspVersion::V1 => Self {
+                priority: field(30, 2) as u8,
+                source: field(25, 5),
+                destination: field(20, 5),
+                destination_port: field(14, 6) as u8,
+                source_port: field(8, 6) as u8,
+                flags: CspFlags::from_bits(field(0, 8) as u8),
+            },
+            CspVersion::V2 => Self {
+                priority: field(46, 2) as u8,
+                destination: field(32, 14),
+                source: field(18, 14),
+                destination_port: field(12, 6) as u8,
+                source_port: field(6, 6) as u8,
+                flags: CspFlags::from_bits(field(0, 6) as u8),
+            },
+        };
+
+        Ok((header, rest))
+    }
+
+    /// The header as sent, fields truncated to their width in `version`.
+    pub fn to_bytes(&self, version: CspVersion) -> Vec<u8> {
+        let field =
+            |value: u16, shift: u32, width: u32| (value as u64 & ((1 << width) - 1)) << shift;
+
+        let id = match version {
+            CspVersion::V1 => {
+                field(self.priority as u16, 30, 2)
+                    | field(self.source, 25, 5)
+                    | field(self.destination, 20, 5)
+                    | field(self.destination_port as u16, 14, 6)
+                    | field(self.source_port as u16, 8, 6)
+                    | field(self.flags.bits() as u16, 0, 8)
+            }
+            CspVersion::V2 => {
+                field(self.priority as u16, 46, 2)
+                    | field(self.destination, 32, 14)
+                    | field(self.source, 18, 14)
+                    | field(self.destination_port as u16, 12, 6)
+                    | field(self.source_port as u16, 6, 6)
+                    | field(self.flags.bits() as u16, 0, 6)
+            }
+        };
+
+        id.to_be_bytes()[8 - version.header_len()..].to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_v1_headers() {
+        // Priority 2, from 1:10 to 28:15, HMAC and CRC32.
+        let packet = [0x83, 0xC3, 0xCA, 0x09, 0xAB];
+
+        let (header, rest) = CspHeader::parse(&packet, CspVersion::V1).unwrap();
+
+        assert_eq!(
+            header,
+            CspHeader {
+                priority: 2,
+                source: 1,
+                destination: 28,
+                destination_port: 15,
+                source_port: 10,
+                flags: CspFlags {
+                    hmac: true,
+                    crc32: true,
+                    ..CspFlags::default()
+                },
+            }
+        );
+        assert_eq!(rest, &[0xAB]);
+        assert_eq!(header.to_bytes(CspVersion::V1), &packet[..4]);
+    }
+
+    #[test]
+    fn parses_v2_headers() {
+        let header = CspHeader {
+            priority: 1,
+            source: 0x2ABC,
+            destination: 0x1234,
+            destination_port: 63,
+            source_port: 7,
+            flags: CspFlags {
+                fragmentation: true,
+                rdp: true,
+                ..CspFlags::default()
+            },
+        };
+        let mut packet = header.to_bytes(CspVersion::V2);
+        packet.extend(b"data");
+
+        assert_eq!(packet[..6], [0x52, 0x34, 0xAA, 0xF3, 0xF1, 0xD2]);
+        assert_eq!(
+            CspHeader::parse(&packet, CspVersion::V2),
+            Ok((header, &b"data"[..]))
+        );
+    }
+
+    #[test]
+    fn rejects_packets_shorter_than_a_header() {
+        assert_eq!(
+            CspHeader::parse(&[0; 5], CspVersion::V2),
+            Err(CspError::TooShort)
+        );
+        assert!(CspHeader::parse(&[0; 4], CspVersion::V1).is_ok());
+    }
+}
@@ -1,4 +1,4 @@
-use crc_any::CRCu16;
+use crc_any::{CRCu16, CRCu32};
 
 pub(crate) type Bit = bool;
 pub(crate) type Byte = u8;
@@ -14,19 +14,134 @@ pub enum DeframingError {
 const FLAG: Byte = 0b0111_1110;
 const MIN_FRAME_SIZE: usize = 32; // Start flag (8) + empty Info(0) + FCS(16) + End flag (8)
 
+/// Frame checking sequence algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fcs {
+    /// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xFFFF, not reflected.
+    Crc16Ccitt,
+    /// CRC-16/X.25 (a.k.a. CRC-16/IBM-SDLC), the one of ISO HDLC and AX.25.
+    #[default]
+    Crc16X25,
+    /// CRC-32 (ISO-HDLC), the 32-bit FCS of ISO HDLC.
+    Crc32,
+    /// No FCS: every frame between flags is taken as valid.
+    None,
+}
+
+impl Fcs {
+    /// Bytes of the FCS in a frame.
+    pub fn len(&self) -> usize {
+        match self {
+            Fcs::Crc16Ccitt | Fcs::Crc16X25 => 2,
+            Fcs::Crc32 => 4,
+            Fcs::None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn calculate(&self, data: &[Byte]) -> u32 {
+        match self {
+            Fcs::Crc16Ccitt => {
+                let mut crc = CRCu16::crc16ccitt_false();
+                crc.digest(data);
+                crc.get_crc() as u32
+            }
+            Fcs::Crc16X25 => {
+                let mut crc = CRCu16::crc16_x25();
+                crc.digest(data);
+                crc.get_crc() as u32
+            }
+            Fcs::Crc32 => {
+                let mut crc = CRCu32::crc32();
+                crc.digest(data);
+                crc.get_crc()
+            }
+            Fcs::None => 0,
+        }
+    }
+}
+
+/// Order of the bytes of a multi-byte field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+/// Order in which the bits of each byte are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+    #[default]
+    LsbFirst,
+    MsbFirst,
+}
+
+/// How frames are laid out on the air. The default is what GNU Radio's HDLC framer does:
+/// CRC-16/X.25 appended little endian, every byte LSB first.
+/// https://github.com/gnuradio/gnuradio/blob/721e477cdb4ed22214ed886d6063cff2dac7d0b5/gr-digital/lib/hdlc_framer_pb_impl.cc#L133
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameFormat {
+    pub fcs: Fcs,
+    pub fcs_byte_order: ByteOrder,
+    pub bit_order: BitOrder,
+}
+
+impl FrameFormat {
+    /// The bytes of `fcs`, in the order they're sent.
+    fn fcs_bytes(&self, fcs: u32) -> Vec<Byte> {
+        let len = self.fcs.len();
+        match self.fcs_byte_order {
+            ByteOrder::LittleEndian => fcs.to_le_bytes()[..len].to_vec(),
+            ByteOrder::BigEndian => fcs.to_be_bytes()[4 - len..].to_vec(),
+        }
+    }
+
+    fn parse_fcs(&self, bytes: &[Byte]) -> u32 {
+        match self.fcs_byte_order {
+            ByteOrder::LittleEndian => bytes
+                .iter()
+                .rev()
+                .fold(0, |acc, &byte| (acc << 8) | byte as u32),
+            ByteOrder::BigEndian => bytes.iter().fold(0, |acc, &byte| (acc << 8) | byte as u32),
+        }
+    }
+
+    /// The bits of `byte`, in the order they're sent.
+    pub(crate) fn unpack(&self, byte: Byte) -> Vec<Bit> {
+        match self.bit_order {
+            BitOrder::LsbFirst => unpack_lsb(byte),
+            BitOrder::MsbFirst => unpack_lsb(byte.reverse_bits()),
+        }
+    }
+
+    /// `byte` with bit `i`, counting in the order they're sent, set to `bit`.
+    pub(crate) fn pack(&self, byte: Byte, i: usize, bit: Bit) -> Byte {
+        match self.bit_order {
+            BitOrder::LsbFirst => byte | (bit as Byte) << i,
+            BitOrder::MsbFirst => byte | (bit as Byte) << (7 - i),
+        }
+    }
+}
+
 /// Represents an HDLC frame.
 #[derive(Debug, PartialEq, Eq)]
 pub struct Frame {
     pub info: Option<Vec<Byte>>,
     fcs: FrameCheckingSequence,
+    format: FrameFormat,
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct FrameCheckingSequence(u16);
+struct FrameCheckingSequence(u32);
 
 impl TryFrom<Vec<Bit>> for Frame {
     type Error = DeframingError;
 
+    /// Parses a whole frame, flags included, in the default [`FrameFormat`].
     fn try_from(bits: Vec<Bit>) -> Result<Self, Self::Error> {
         if bits.len() < MIN_FRAME_SIZE {
             return Err(DeframingError::InvalidFrameSize);
@@ -46,60 +161,55 @@ impl TryFrom<Vec<Bit>> for Frame {
 
         let content_bits = bit_destuff(content_bits);
 
-        if content_bits.len() < 16 {
+        if !content_bits.len().is_multiple_of(8) {
             return Err(DeframingError::InvalidFrameSize);
         }
 
-        // Extract info bits (everything between the first flag and FCS)
-        let info_bits = &content_bits[..content_bits.len() - 16];
-
-        // Extract FCS (last 16 bits)
-        let fcs_bits = &content_bits[content_bits.len() - 16..];
-        let received_fcs = bits_to_u16(fcs_bits);
-        let fcs = FrameCheckingSequence(received_fcs);
-
-        // Convert info_bits to bytes
-        let info_bytes = if info_bits.is_empty() {
-            None
-        } else {
-            let mut bytes = Vec::new();
-            for chunk in info_bits.chunks(8) {
-                let mut byte = 0u8;
-                for (i, &b) in chunk.iter().enumerate() {
-                    byte |= (b as u8) << i;
-                }
-                bytes.push(byte);
-            }
-            Some(bytes)
-        };
-
-        let calc_fcs = calculate_fcs(&info_bytes);
-        if calc_fcs != fcs.0 {
-            return Err(DeframingError::FcsMismatch);
-        }
-
-        Ok(Frame {
-            info: info_bytes,
-            fcs,
-        })
+        let format = FrameFormat::default();
+        let content = content_bits
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (i, &bit)| format.pack(byte, i, bit))
+            })
+            .collect();
+
+        Frame::from_content(content, format)
     }
 }
 
 impl Frame {
+    /// A frame in the default [`FrameFormat`].
     pub fn new(info: Option<Vec<Byte>>) -> Self {
-        // 1. Preparar bytes para el CRC
-        let mut data: Vec<u8> = Vec::new();
+        Self::with_format(info, FrameFormat::default())
+    }
+
+    pub fn with_format(info: Option<Vec<Byte>>, format: FrameFormat) -> Self {
+        let fcs = FrameCheckingSequence(calculate_fcs(&info, format.fcs));
+
+        Frame { info, fcs, format }
+    }
 
-        if let Some(ref payload) = info {
-            data.extend(payload);
+    /// Parses the destuffed bytes between two flags: the info, then the FCS.
+    pub(crate) fn from_content(
+        mut content: Vec<Byte>,
+        format: FrameFormat,
+    ) -> Result<Self, DeframingError> {
+        if content.len() < format.fcs.len() {
+            return Err(DeframingError::InvalidFrameSize);
         }
 
-        // 2. Calcular el FCS
-        let crc = calculate_fcs(&info);
-        let fcs = FrameCheckingSequence(crc);
+        let fcs_bytes = content.split_off(content.len() - format.fcs.len());
+        let fcs = FrameCheckingSequence(format.parse_fcs(&fcs_bytes));
+        let info = (!content.is_empty()).then_some(content);
+
+        if calculate_fcs(&info, format.fcs) != fcs.0 {
+            return Err(DeframingError::FcsMismatch);
+        }
 
-        // 3. Crear el frame
-        Frame { info, fcs }
+        Ok(Frame { info, fcs, format })
     }
 
     /// Converts a Frame into a vector of bits.
@@ -108,15 +218,13 @@ impl Frame {
 
         if let Some(info) = &self.info {
             for byte in info {
-                raw_bits.extend(unpack_lsb(*byte));
+                raw_bits.extend(self.format.unpack(*byte));
             }
         }
 
-        // Append CRC, in little endian. Not ISO compliant, but it's what GNU radio's HDLC framer does.
-        // https://github.com/gnuradio/gnuradio/blob/721e477cdb4ed22214ed886d6063cff2dac7d0b5/gr-digital/lib/hdlc_framer_pb_impl.cc#L133
-        let crc_bytes = self.fcs.0.to_le_bytes();
-        raw_bits.extend(unpack_lsb(crc_bytes[0]));
-        raw_bits.extend(unpack_lsb(crc_bytes[1]));
+        for byte in self.format.fcs_bytes(self.fcs.0) {
+            raw_bits.extend(self.format.unpack(byte));
+        }
 
         // Apply bit stuffing to the entire content between flags
         let stuffed_bits = bit_stuff(&raw_bits);
@@ -184,23 +292,8 @@ pub fn bit_destuff(bits_in: &[Bit]) -> Vec<Bit> {
 }
 
 /// Calculates the FCS for the given info bytes.
-fn calculate_fcs(info_bytes: &Option<Vec<Byte>>) -> u16 {
-    let mut data = Vec::new();
-
-    if let Some(payload) = info_bytes {
-        data.extend(payload);
-    }
-
-    let mut crc = CRCu16::crc16_x25();
-    crc.digest(&data);
-    crc.get_crc()
-}
-
-/// Converts a slice of bits (LSB first) to a u16
-fn bits_to_u16(bits: &[Bit]) -> u16 {
-    bits.iter()
-        .enumerate()
-        .fold(0u16, |acc, (i, &b)| acc | ((b as u16) << i))
+fn calculate_fcs(info_bytes: &Option<Vec<Byte>>, fcs: Fcs) -> u32 {
+    fcs.calculate(info_bytes.as_deref().unwrap_or_default())
 }
 
 /// Specialized function for packing boolean slices into u8s (MSB first).
@@ -263,4 +356,36 @@ mod tests {
         let expected = input.clone();
         assert_eq!(bit_stuff(&input), expected);
     }
+
+    #[test]
+    fn fcs_in_byte_order() {
+        let crc32_big_endian = FrameFormat {
+            fcs: Fcs::Crc32,
+            fcs_byte_order: ByteOrder::BigEndian,
+            bit_order: BitOrder::LsbFirst,
+        };
+        // https://crccalc.com/?crc=123456789&method=CRC-32&datatype=ascii&outtype=hex
+        let fcs = Fcs::Crc32.calculate(b"123456789");
+
+        assert_eq!(fcs, 0xCBF43926);
+        assert_eq!(
+            crc32_big_endian.fcs_bytes(fcs),
+            vec![0xCB, 0xF4, 0x39, 0x26]
+        );
+        assert_eq!(FrameFormat::default().fcs_bytes(0xA55E), vec![0x5E, 0xA5]);
+        assert_eq!(crc32_big_endian.parse_fcs(&[0xCB, 0xF4, 0x39, 0x26]), fcs);
+    }
+
+    #[test]
+    fn frame_msb_first() {
+        let format = FrameFormat {
+            fcs: Fcs::None,
+            bit_order: BitOrder::MsbFirst,
+            ..FrameFormat::default()
+        };
+
+        let bits = Frame::with_format(Some(vec![0x12]), format).to_bits();
+
+        assert_eq!(pack_bools_to_bytes_msb(&bits), vec![0x7e_u8, 0x12, 0x7e]);
+    }
 }
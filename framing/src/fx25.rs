@@ -0,0 +1,356 @@
+//! FX.25: AX.25 frames with Reed-Solomon forward error correction, which plain AX.25 receivers
+//! still decode when there are no errors.
+//!
+//! A 64-bit correlation tag, which also tells the code, is sent before a Reed-Solomon block. The
+//! data of the block is the HDLC frame, flags and stuffing included, padded with flags; the check
+//! bytes follow. Everything is sent LSB first.
+//!
+//! The receiver looks for tags in the bit stream, allowing for a few wrong bits. After one, it
+//! corrects the block and deframes its data as HDLC. Frames sent without FX.25, or whose block
+//! can't be corrected, are deframed as plain HDLC.
+//!
+//! http://www.stensat.org/docs/FX-25_01_06.pdf
+
+use crate::{
+    deframer::Deframer,
+    frame::{Frame, FrameFormat},
+    hdlc_deframer::Receiver,
+    reed_solomon::ReedSolomon,
+};
+use std::collections::VecDeque;
+
+/// Exponent of the first root of the Reed-Solomon generator polynomial.
+const FIRST_ROOT: usize = 1;
+/// Bits of a correlation tag that may be wrong for it to be taken.
+const MAX_TAG_ERRORS: u32 = 8;
+/// An HDLC flag, LSB first.
+const FLAG_BITS: [bool; 8] = [false, true, true, true, true, true, true, false];
+
+/// A correlation tag, and the code of the block it announces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub tag: u64,
+    /// Bytes in the block, data and check.
+    pub block_len: usize,
+    /// Data bytes in the block.
+    pub data_len: usize,
+}
+
+impl Mode {
+    pub fn check_len(&self) -> usize {
+        self.block_len - self.data_len
+    }
+}
+
+/// Tags 0x01 to 0x0B; the others are reserved.
+pub const MODES: [Mode; 11] = [
+    Mode::new(0xB74D_B7DF_8A53_2F3E, 255, 239),
+    Mode::new(0x26FF_60A6_00CC_8FDE, 144, 128),
+    Mode::new(0xC7DC_0508_F3D9_B09E, 80, 64),
+    Mode::new(0x8F05_6EB4_3696_60EE, 48, 32),
+    Mode::new(0x6E26_0B1A_C583_5FAE, 255, 223),
+    Mode::new(0xFF94_DC63_4F1C_FF4E, 160, 128),
+    Mode::new(0x1EB7_B9CD_BC09_C00E, 96, 64),
+    Mode::new(0xDBF8_69BD_2DBB_1776, 64, 32),
+    Mode::new(0x3ADB_0C13_DEAE_2836, 255, 191),
+    Mode::new(0xAB69_DB6A_5431_88D6, 192, 128),
+    Mode::new(0x4A4A_BEC4_A724_B796, 128, 64),
+];
+
+impl Mode {
+    const fn new(tag: u64, block_len: usize, data_len: usize) -> Self {
+        Self {
+            tag,
+            block_len,
+            data_len,
+        }
+    }
+
+    /// The mode whose tag is the closest to `register`, if close enough.
+    fn correlate(register: u64) -> Option<&'static Mode> {
+        MODES
+            .iter()
+            .map(|mode| (mode, (mode.tag ^ register).count_ones()))
+            .filter(|(_, errors)| *errors <= MAX_TAG_ERRORS)
+            .min_by_key(|(_, errors)| *errors)
+            .map(|(mode, _)| mode)
+    }
+}
+
+/// The bits to send `frame` with FX.25, tag and block, in the smallest mode with `check_len`
+/// check bytes (16, 32 or 64) its bits fit in. `None` if there's no such mode.
+pub fn encode(frame: &Frame, check_len: usize) -> Option<Vec<bool>> {
+    let mut bits = frame.to_bits();
+    let mode = MODES
+        .iter()
+        .filter(|mode| mode.check_len() == check_len && mode.data_len * 8 >= bits.len())
+        .min_by_key(|mode| mode.data_len)?;
+
+    // Padded with flags up to the data length.
+    let padding = mode.data_len * 8 - bits.len();
+    bits.extend(FLAG_BITS.iter().cycle().take(padding));
+
+    let mut block = pack(&bits);
+    block.extend(ReedSolomon::new(mode.check_len(), FIRST_ROOT).encode(&block));
+
+    let mut out: Vec<bool> = (0..64).map(|i| mode.tag >> i & 1 == 1).collect();
+    out.extend(unpack(&block));
+    Some(out)
+}
+
+/// Bytes of `bits`, LSB first.
+fn pack(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, &bit)| byte | (bit as u8) << i)
+        })
+        .collect()
+}
+
+/// Bits of `bytes`, LSB first.
+fn unpack(bytes: &[u8]) -> impl Iterator<Item = bool> + '_ {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| byte >> i & 1 == 1))
+}
+
+enum State {
+    /// Looking for a tag in the last 64 bits, the latest one in the MSB.
+    Hunting { register: u64, bits: usize },
+    /// Receiving the block announced by a tag: its bits so far, LSB first.
+    Block {
+        mode: &'static Mode,
+        bits: Vec<bool>,
+    },
+}
+
+pub struct Fx25DeframingIterator<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    input: I,
+    format: FrameFormat,
+    state: State,
+    /// Deframes whatever isn't in a block.
+    plain: Receiver,
+    /// Frames deframed from a block, not yet returned.
+    pending: VecDeque<Frame>,
+    /// Input bits being received, and the next one.
+    bits: Vec<bool>,
+    position: usize,
+}
+
+impl<I> Fx25DeframingIterator<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    /// Receives a bit, returning the frame it closed outside of blocks, if any.
+    fn push(&mut self, bit: bool) -> Option<Frame> {
+        match &mut self.state {
+            State::Hunting { register, bits } => {
+                *register = *register >> 1 | (bit as u64) << 63;
+                *bits += 1;
+
+                if *bits >= 64
+                    && let Some(mode) = Mode::correlate(*register)
+                {
+                    self.state = State::Block {
+                        mode,
+                        bits: Vec::with_capacity(mode.block_len * 8),
+                    };
+                }
+
+                self.plain.push(bit)
+            }
+            State::Block { mode, bits } => {
+                bits.push(bit);
+                if bits.len() == mode.block_len * 8 {
+                    let (mode, bits) = (*mode, std::mem::take(bits));
+                    self.state = State::Hunting {
+                        register: 0,
+                        bits: 0,
+                    };
+                    self.receive_block(mode, bits);
+                }
+                None
+            }
+        }
+    }
+
+    /// Corrects and deframes a block, leaving its frames pending. If it can't be corrected,
+    /// its bits are deframed as they are.
+    fn receive_block(&mut self, mode: &Mode, bits: Vec<bool>) {
+        let mut block = pack(&bits);
+
+        match ReedSolomon::new(mode.check_len(), FIRST_ROOT).decode(&mut block) {
+            Ok(_) => {
+                let mut receiver = Receiver::new(self.format);
+                let data = unpack(&block[..mode.data_len]);
+                self.pending
+                    .extend(data.filter_map(|bit| receiver.push(bit)));
+            }
+            Err(_) => {
+                let plain = &mut self.plain;
+                self.pending
+                    .extend(bits.into_iter().filter_map(|bit| plain.push(bit)));
+            }
+        }
+    }
+}
+
+impl<I> Iterator for Fx25DeframingIterator<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Some(frame);
+            }
+
+            while let Some(&bit) = self.bits.get(self.position) {
+                self.position += 1;
+                if let Some(frame) = self.push(bit) {
+                    return Some(frame);
+                }
+                if let Some(frame) = self.pending.pop_front() {
+                    return Some(frame);
+                }
+            }
+
+            self.bits = self.input.next()?;
+            self.position = 0;
+        }
+    }
+}
+
+/// Deframes FX.25, and plain HDLC frames.
+pub struct Fx25Deframer<I> {
+    format: FrameFormat,
+    _phantom: std::marker::PhantomData<I>,
+}
+
+impl<I> Fx25Deframer<I> {
+    pub fn new() -> Self {
+        Self::with_format(FrameFormat::default())
+    }
+
+    /// A deframer of HDLC frames in `format`, with or without FX.25.
+    pub fn with_format(format: FrameFormat) -> Self {
+        Self {
+            format,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<I> Default for Fx25Deframer<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> Deframer<Vec<bool>, Frame> for Fx25Deframer<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    type Input = I;
+    type Output = Fx25DeframingIterator<I>;
+
+    fn frames(&self, input: Self::Input) -> Self::Output {
+        Fx25DeframingIterator {
+            input,
+            format: self.format,
+            state: State::Hunting {
+                register: 0,
+                bits: 0,
+            },
+            plain: Receiver::new(self.format),
+            pending: VecDeque::new(),
+            bits: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deframe(bits: Vec<bool>) -> Vec<Frame> {
+        Fx25Deframer::new()
+            .frames(bits.chunks(100).map(<[bool]>::to_vec))
+            .collect()
+    }
+
+    fn preamble() -> Vec<bool> {
+        FLAG_BITS.repeat(4)
+    }
+
+    #[test]
+    fn picks_the_smallest_mode() {
+        let frame = Frame::new(Some(vec![0x55; 20]));
+
+        let bits = encode(&frame, 16).unwrap();
+
+        assert_eq!(bits.len(), 64 + 48 * 8);
+        assert!(encode(&Frame::new(Some(vec![0; 300])), 16).is_none());
+    }
+
+    #[test]
+    fn corrects_a_frame_in_every_mode() {
+        for mode in &MODES {
+            // About as long a frame as fits.
+            let frame = Frame::new(Some(vec![0xA5; mode.data_len.saturating_sub(8) * 4 / 5]));
+            let mut bits = preamble();
+            bits.extend(encode(&frame, mode.check_len()).unwrap());
+            bits.extend(preamble());
+
+            // Wrong tag bits, and as many wrong bytes as can be corrected, spread out.
+            for i in 0..MAX_TAG_ERRORS as usize {
+                bits[32 + 8 * i] ^= true;
+            }
+            let block_start = 32 + 64;
+            for i in 0..mode.check_len() / 2 {
+                let byte = i * mode.block_len / (mode.check_len() / 2);
+                bits[block_start + 8 * byte + i % 8] ^= true;
+            }
+
+            assert_eq!(deframe(bits), vec![frame], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn deframes_plain_frames() {
+        let first = Frame::new(Some(b"plain".to_vec()));
+        let second = Frame::new(Some(b"fx.25".to_vec()));
+        let third = Frame::new(Some(b"plain again".to_vec()));
+        let mut bits = preamble();
+        bits.extend(first.to_bits());
+        bits.extend(preamble());
+        bits.extend(encode(&second, 32).unwrap());
+        bits.extend(third.to_bits());
+
+        assert_eq!(deframe(bits), vec![first, second, third]);
+    }
+
+    #[test]
+    fn falls_back_to_plain_hdlc_when_the_block_is_uncorrectable() {
+        let frame = Frame::new(Some(b"check bytes lost".to_vec()));
+        let mut bits = preamble();
+        bits.extend(encode(&frame, 16).unwrap());
+        bits.extend(preamble());
+        // Wipe out the check bytes, past the frame and its padding.
+        let end = bits.len() - 32;
+        for bit in &mut bits[end - 16 * 8..end] {
+            *bit = !*bit;
+        }
+
+        assert_eq!(deframe(bits), vec![frame]);
+    }
+}
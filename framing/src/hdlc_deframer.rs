@@ -1,26 +1,128 @@
-use crate::bitvecdeque::BitVecDeque;
-use crate::deframer::Deframer;
-use crate::frame::Frame;
+//! Streaming HDLC receiver. Each bit goes through a counter of the ones in a row, which is all it
+//! takes to tell flags (`01111110`), aborts (seven ones) and stuffed zeros (a zero after five
+//! ones) apart, so frames are destuffed and packed into bytes as the bits arrive.
 
-/// Local parser state duplicated here so the iterator can run the same state machine.
-enum ParserState {
-    SearchingStartSync,
-    SearchingEndSync,
-}
+use crate::deframer::Deframer;
+use crate::frame::{Frame, FrameFormat};
 
 // Typical HDLC frames are up to 260 bytes (2080 bits)
 // 4096 bits (512 bytes) is a safe upper bound for most use cases
 const MAX_BUFFER_LEN: usize = 4096;
-const FLAG_ARRAY: [bool; 8] = [false, true, true, true, true, true, true, false];
+/// Bits of a flag.
+const FLAG_LEN: usize = 8;
+
+/// State of the receiver between bits.
+pub(crate) struct Receiver {
+    format: FrameFormat,
+    /// Ones in a row, up to 7.
+    ones: u8,
+    /// After a flag, and not aborted since.
+    in_frame: bool,
+    /// Bits received since the flag, stuffed zeros included.
+    raw: usize,
+    /// Destuffed bits since the flag, packed in the bit order of the format, and how many there
+    /// are. They end with the first 7 bits of the closing flag once it's received.
+    content: Vec<u8>,
+    len: usize,
+}
+
+impl Receiver {
+    pub(crate) fn new(format: FrameFormat) -> Self {
+        Self {
+            format,
+            ones: 0,
+            in_frame: false,
+            raw: 0,
+            content: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Receives a bit, returning the frame it closed, if any and valid.
+    pub(crate) fn push(&mut self, bit: bool) -> Option<Frame> {
+        if self.in_frame {
+            self.raw += 1;
+            // Drop frames that grow too large (prevents DoS via never-ending garbage)
+            if FLAG_LEN + self.raw > MAX_BUFFER_LEN {
+                self.in_frame = false;
+            }
+        }
+
+        if bit {
+            self.ones = (self.ones + 1).min(7);
+            if self.ones == 7 {
+                // Abort: hunt for the next flag.
+                self.in_frame = false;
+            } else {
+                self.push_content(true);
+            }
+            return None;
+        }
+
+        match std::mem::replace(&mut self.ones, 0) {
+            6 => {
+                let frame = if self.in_frame {
+                    self.take_frame()
+                } else {
+                    None
+                };
+                self.start_frame();
+                frame
+            }
+            // Stuffed.
+            5 => None,
+            // Idle after an abort.
+            7 => None,
+            _ => {
+                self.push_content(false);
+                None
+            }
+        }
+    }
+
+    fn push_content(&mut self, bit: bool) {
+        if !self.in_frame {
+            return;
+        }
+
+        if self.len.is_multiple_of(8) {
+            self.content.push(0);
+        }
+        let byte = &mut self.content[self.len / 8];
+        *byte = self.format.pack(*byte, self.len % 8, bit);
+        self.len += 1;
+    }
+
+    fn start_frame(&mut self) {
+        self.in_frame = true;
+        self.raw = 0;
+        self.content.clear();
+        self.len = 0;
+    }
+
+    /// The frame just closed by a flag, if it's a whole number of bytes with a valid FCS.
+    fn take_frame(&mut self) -> Option<Frame> {
+        // Everything but the flag, which all but its last bit was taken for content.
+        let len = self.len.checked_sub(FLAG_LEN - 1)?;
+        if len == 0 || !len.is_multiple_of(8) {
+            return None;
+        }
+
+        let mut content = std::mem::take(&mut self.content);
+        content.truncate(len / 8);
+        Frame::from_content(content, self.format).ok()
+    }
+}
 
 pub struct HdlcDeframingIterator<I>
 where
     I: Iterator<Item = Vec<bool>>,
 {
     input: I,
-    buffer: BitVecDeque,
-    idx: usize,
-    state: ParserState,
+    receiver: Receiver,
+    /// Input bits being received, and the next one.
+    bits: Vec<bool>,
+    position: usize,
 }
 
 impl<I> Iterator for HdlcDeframingIterator<I>
@@ -30,93 +132,35 @@ where
     type Item = Frame;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // Keep attempting to find a frame. As soon as one is found, return it.
         loop {
-            // Try to find a frame using currently buffered bits
-            while self.buffer.len() >= 8 && self.buffer.len().saturating_sub(self.idx) >= 8 {
-                // Drop buffer if it grows too large (prevents DoS via never-ending garbage)
-                if self.buffer.len() > MAX_BUFFER_LEN {
-                    self.buffer.clear();
-                    self.idx = 0;
-                    self.state = ParserState::SearchingStartSync;
-                    break;
-                }
-
-                // Get an 8-bit slice at current idx
-                let bitvec_slice = self.buffer.slice_to_bitvec(self.idx, self.idx + 8);
-                let slice: Vec<bool> = bitvec_slice.iter().map(|b| *b).collect();
-
-                if slice == FLAG_ARRAY {
-                    match self.state {
-                        ParserState::SearchingStartSync => {
-                            self.state = ParserState::SearchingEndSync;
-                            // Move past this flag and continue looking for end flag
-                            self.idx = self.idx.saturating_add(8);
-                        }
-                        ParserState::SearchingEndSync => {
-                            // Drain the frame including both flags (from 0 to idx+8)
-                            let frame_bits = self.buffer.drain_range(0, self.idx + 8);
-
-                            // Flag sharing: keep the closing flag as the opening flag of the next frame
-                            // Push the flag back to the front of the buffer
-                            for bit in FLAG_ARRAY.iter().rev() {
-                                self.buffer.push_front(*bit);
-                            }
-                            // Reset index to start scanning after the shared flag
-                            self.idx = 8;
-
-                            if let Ok(frame) = Frame::try_from(frame_bits) {
-                                return Some(frame);
-                            }
-
-                            // If frame parsing failed, reset to search for a new start
-                            self.idx = 0;
-                            self.state = ParserState::SearchingStartSync;
-                        }
-                    }
-                } else {
-                    match self.state {
-                        ParserState::SearchingStartSync => {
-                            // drop the first element; garbage before a start flag
-                            self.buffer.pop_front();
-                        }
-                        ParserState::SearchingEndSync => {
-                            // increment the index to continue looking for the closing flag
-                            self.idx = self.idx.saturating_add(1);
-                        }
-                    }
+            while let Some(bit) = self.bits.get(self.position) {
+                self.position += 1;
+                if let Some(frame) = self.receiver.push(*bit) {
+                    return Some(frame);
                 }
             }
 
-            // If we cannot find a frame with current data, try to read more from input
-            match self.input.next() {
-                Some(new_bits) => {
-                    if new_bits.is_empty() {
-                        // nothing to add, continue loop to try to find a frame (or read more)
-                        continue;
-                    }
-                    for bit in new_bits {
-                        self.buffer.push_back(bit);
-                    }
-                    // continue the loop to attempt a new scan with added bits
-                    continue;
-                }
-                None => {
-                    // No more input; if a frame hasn't been produced, return None
-                    return None;
-                }
-            }
+            // No more input; if a frame hasn't been produced, return None
+            self.bits = self.input.next()?;
+            self.position = 0;
         }
     }
 }
 
 pub struct HdlcDeframer<I> {
+    format: FrameFormat,
     _phantom: std::marker::PhantomData<I>,
 }
 
 impl<I> HdlcDeframer<I> {
+    /// A deframer of frames in the default [`FrameFormat`], GNU Radio's.
     pub fn new() -> Self {
+        Self::with_format(FrameFormat::default())
+    }
+
+    pub fn with_format(format: FrameFormat) -> Self {
         Self {
+            format,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -138,9 +182,9 @@ where
     fn frames(&self, input: Self::Input) -> Self::Output {
         HdlcDeframingIterator {
             input,
-            buffer: BitVecDeque::new(),
-            idx: 0,
-            state: ParserState::SearchingStartSync,
+            receiver: Receiver::new(self.format),
+            bits: Vec::new(),
+            position: 0,
         }
     }
 }
@@ -149,6 +193,8 @@ where
 mod tests {
     use super::*;
 
+    const FLAG_ARRAY: [bool; 8] = [false, true, true, true, true, true, true, false];
+
     // Helpers for tests -------------------------------------------------
     fn frame_bits_with_info(info: Option<Vec<u8>>) -> Vec<bool> {
         let frame = Frame::new(info);
@@ -530,4 +576,62 @@ mod tests {
         assert_eq!(frames[0].to_bits(), frame1_bits);
         assert_eq!(frames[1].to_bits(), frame2_bits);
     }
+
+    #[test]
+    fn test_aborted_frame_is_dropped() {
+        let deframer = HdlcDeframer::new();
+        let aborted_bits = frame_bits_with_info(Some(vec![0x33, 0x44]));
+        let frame_bits = frame_bits_with_info(Some(vec![0x55]));
+
+        // Seven ones in the middle of the first frame abort it, and the idle ones after the
+        // abort don't make a flag.
+        let mut input = aborted_bits[..24].to_vec();
+        input.extend([true; 12]);
+        input.extend(&aborted_bits[24..]);
+        input.extend(&frame_bits);
+
+        let frames: Vec<Frame> = deframer.frames(vec![input].into_iter()).collect();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].to_bits(), frame_bits);
+    }
+
+    #[test]
+    fn test_frames_in_every_format() {
+        use crate::frame::{BitOrder, ByteOrder, Fcs};
+
+        for fcs in [Fcs::Crc16Ccitt, Fcs::Crc16X25, Fcs::Crc32, Fcs::None] {
+            for fcs_byte_order in [ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+                for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+                    let format = FrameFormat {
+                        fcs,
+                        fcs_byte_order,
+                        bit_order,
+                    };
+                    let frame = Frame::with_format(Some(vec![0x01, 0x7E, 0xFF]), format);
+
+                    let frames: Vec<Frame> = HdlcDeframer::with_format(format)
+                        .frames(vec![frame.to_bits()].into_iter())
+                        .collect();
+
+                    assert_eq!(frames, vec![frame], "{:?}", format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_in_another_format_is_dropped() {
+        let big_endian = FrameFormat {
+            fcs_byte_order: crate::frame::ByteOrder::BigEndian,
+            ..FrameFormat::default()
+        };
+        let bits = frame_bits_with_info(Some(vec![0x12, 0x34]));
+
+        let frames: Vec<Frame> = HdlcDeframer::with_format(big_endian)
+            .frames(vec![bits].into_iter())
+            .collect();
+
+        assert!(frames.is_empty());
+    }
 }
@@ -0,0 +1,685 @@
+//! IL2P (Improved Layer 2 Protocol): AX.25 packets behind a sync word, with a compressed header
+//! and Reed-Solomon forward error correction instead of HDLC flags, stuffing and FCS.
+//!
+//! After the 24-bit sync word, a 13-byte header carries the payload length and, for headers of
+//! type 1, the addresses, control and PID of the AX.25 frame; type 0 headers leave the whole frame
+//! in the payload. The header is protected by 2 check bytes, and the payload is split into blocks
+//! of up to 239 or 247 bytes, protected by 16 check bytes each (max FEC) or 2 to 8 depending on
+//! their size (baseline FEC). The header and each block are scrambled before their check bytes
+//! are computed. Every byte is sent MSB first.
+//!
+//! The deframer yields the AX.25 frame of each packet, as the HDLC deframers do.
+//!
+//! https://tarpn.net/t/il2p/il2p-specification0-4.pdf
+
+use crate::{deframer::Deframer, frame::Frame, reed_solomon::ReedSolomon};
+
+const SYNC_WORD: u32 = 0xF1_5E48;
+/// Bits of the sync word that may be wrong for it to be taken.
+const MAX_SYNC_ERRORS: u32 = 1;
+/// Exponent of the first root of the Reed-Solomon generator polynomials.
+const FIRST_ROOT: usize = 0;
+const HEADER_LEN: usize = 13;
+const HEADER_CHECK_LEN: usize = 2;
+/// Largest payload the 10-bit byte count of the header allows.
+pub const MAX_PAYLOAD_LEN: usize = 1023;
+
+/// Control byte of each unnumbered frame type a type 1 header can carry, P/F bit clear: SABM,
+/// DISC, DM, UA, FRMR, UI, XID and TEST.
+const U_CONTROLS: [u8; 8] = [0x2F, 0x43, 0x0F, 0x63, 0x87, 0x03, 0xAF, 0xE3];
+const UI: usize = 5;
+/// AX.25 PID of each PID code of a type 1 header. Codes 0 and 1 are for S and U frames, which
+/// have no PID; the others without one are reserved.
+const PIDS: [Option<u8>; 16] = [
+    None,
+    None,
+    Some(0x20),
+    Some(0x01),
+    Some(0x06),
+    Some(0x07),
+    Some(0x08),
+    None,
+    None,
+    None,
+    None,
+    Some(0xCC),
+    Some(0xCD),
+    Some(0xCE),
+    Some(0xCF),
+    Some(0xF0),
+];
+
+/// Sets a field spread over bit `bit` of consecutive header bytes, its LSB in `hdr[lsb_index]`
+/// and its MSB `width - 1` bytes before.
+fn set_field(hdr: &mut [u8; HEADER_LEN], bit: u8, lsb_index: usize, width: usize, value: u16) {
+    for i in 0..width {
+        hdr[lsb_index - i] |= ((value >> i & 1) as u8) << bit;
+    }
+}
+
+fn get_field(hdr: &[u8; HEADER_LEN], bit: u8, lsb_index: usize, width: usize) -> u16 {
+    (0..width).fold(0, |value, i| {
+        value | ((hdr[lsb_index - i] >> bit & 1) as u16) << i
+    })
+}
+
+/// Multiplicative scrambler on x^9 + x^4 + 1, restarted for the header and each block.
+struct Scrambler(u16);
+
+impl Scrambler {
+    fn new() -> Self {
+        Self(0x1F0)
+    }
+
+    fn shift(&mut self, scrambled: bool) {
+        self.0 = ((self.0 >> 1) | (scrambled as u16) << 8) ^ (scrambled as u16) << 3;
+    }
+
+    fn bytes(bytes: &[u8], scramble: bool) -> Vec<u8> {
+        let mut lfsr = Self::new();
+        bytes
+            .iter()
+            .map(|byte| {
+                (0..8).rev().fold(0, |out, i| {
+                    let bit = byte >> i & 1 == 1;
+                    let flipped = bit ^ (lfsr.0 & 1 == 1);
+                    lfsr.shift(if scramble { flipped } else { bit });
+                    out | (flipped as u8) << i
+                })
+            })
+            .collect()
+    }
+
+    fn scramble(bytes: &[u8]) -> Vec<u8> {
+        Self::bytes(bytes, true)
+    }
+
+    fn descramble(bytes: &[u8]) -> Vec<u8> {
+        Self::bytes(bytes, false)
+    }
+}
+
+/// Sizes of the blocks a payload of `len` bytes is split into, larger ones first, and the check
+/// bytes of each.
+fn blocks(len: usize, max_fec: bool) -> (Vec<usize>, usize) {
+    if len == 0 {
+        return (Vec::new(), 0);
+    }
+
+    let count = len.div_ceil(if max_fec { 239 } else { 247 });
+    let small = len / count;
+    let large_count = len - count * small;
+    let sizes = (0..count)
+        .map(|i| if i < large_count { small + 1 } else { small })
+        .collect();
+    let check_len = match (max_fec, small) {
+        (true, _) => 16,
+        (false, ..=61) => 2,
+        (false, ..=123) => 4,
+        (false, ..=185) => 6,
+        (false, _) => 8,
+    };
+
+    (sizes, check_len)
+}
+
+/// A type 1 header for an AX.25 frame (without FCS), and its payload, if the header can carry
+/// its addresses, control and PID: two addresses, callsigns in upper case, modulo 8 control.
+fn translate(packet: &[u8]) -> Option<([u8; HEADER_LEN], &[u8])> {
+    let (dest, src) = (packet.get(..7)?, packet.get(7..14)?);
+    let control = *packet.get(14)?;
+
+    // No digipeaters, and reserved bits set.
+    if dest[6] & 0x61 != 0x60 || src[6] & 0x61 != 0x61 {
+        return None;
+    }
+    let command = match (dest[6] >> 7, src[6] >> 7) {
+        (1, 0) => true,
+        (0, 1) => false,
+        _ => return None,
+    };
+
+    let mut hdr = [0; HEADER_LEN];
+    for (i, &byte) in dest[..6].iter().chain(&src[..6]).enumerate() {
+        if byte & 1 != 0 || !(0x20..0x60).contains(&(byte >> 1)) {
+            return None;
+        }
+        hdr[i] = (byte >> 1) - 0x20;
+    }
+    hdr[12] = (dest[6] >> 1 & 0x0F) << 4 | (src[6] >> 1 & 0x0F);
+
+    let pid_code = |pid: u8| PIDS.iter().position(|&p| p == Some(pid));
+    let pf = (control >> 4 & 1) as u16;
+    let (ui, pid, fields, payload) = if control & 1 == 0 {
+        // I frames are always commands.
+        if !command {
+            return None;
+        }
+        let pid = pid_code(*packet.get(15)?)?;
+        let fields = pf << 6 | (control as u16 >> 5) << 3 | (control as u16 >> 1 & 7);
+        (0, pid, fields, &packet[16..])
+    } else if control & 3 == 1 {
+        let fields = pf << 6
+            | (control as u16 >> 5) << 3
+            | (command as u16) << 2
+            | (control as u16 >> 2 & 3);
+        (0, 0, fields, &packet[15..])
+    } else {
+        let opcode = U_CONTROLS.iter().position(|&c| c == control & !0x10)?;
+        let fields = pf << 6 | (opcode as u16) << 3 | (command as u16) << 2;
+        if opcode == UI {
+            (1, pid_code(*packet.get(15)?)?, fields, &packet[16..])
+        } else {
+            (0, 1, fields, &packet[15..])
+        }
+    };
+
+    set_field(&mut hdr, 6, 0, 1, ui);
+    set_field(&mut hdr, 6, 4, 4, pid as u16);
+    set_field(&mut hdr, 6, 11, 7, fields);
+    Some((hdr, payload))
+}
+
+/// The AX.25 frame (without FCS) of a type 1 header and its payload.
+fn untranslate(hdr: &[u8; HEADER_LEN], payload: &[u8]) -> Option<Vec<u8>> {
+    let ui = get_field(hdr, 6, 0, 1) == 1;
+    let pid = get_field(hdr, 6, 4, 4) as usize;
+    let fields = get_field(hdr, 6, 11, 7) as u8;
+    let pf = fields >> 6 & 1;
+    let command_bit = fields >> 2 & 1 == 1;
+
+    let (command, control, pid) = match pid {
+        0 => (
+            command_bit,
+            (fields >> 3 & 7) << 5 | pf << 4 | (fields & 3) << 2 | 1,
+            None,
+        ),
+        1 => (
+            command_bit,
+            U_CONTROLS[(fields >> 3 & 7) as usize] | pf << 4,
+            None,
+        ),
+        _ if ui => (command_bit, U_CONTROLS[UI] | pf << 4, Some(PIDS[pid]?)),
+        _ => (
+            true,
+            (fields >> 3 & 7) << 5 | pf << 4 | (fields & 7) << 1,
+            Some(PIDS[pid]?),
+        ),
+    };
+
+    let mut packet: Vec<u8> = hdr[..12].iter().map(|c| ((c & 0x3F) + 0x20) << 1).collect();
+    packet.insert(6, 0x60 | (hdr[12] >> 4) << 1 | (command as u8) << 7);
+    packet.push(0x61 | (hdr[12] & 0x0F) << 1 | (!command as u8) << 7);
+    packet.push(control);
+    packet.extend(pid);
+    packet.extend(payload);
+    Some(packet)
+}
+
+/// The bits to send an AX.25 frame (without FCS, e.g. the info of a [`Frame`]) with IL2P, sync
+/// word included, with max FEC or baseline FEC. Type 1 headers are used whenever they can carry
+/// the frame. `None` if the payload is longer than [`MAX_PAYLOAD_LEN`].
+pub fn encode(packet: &[u8], max_fec: bool) -> Option<Vec<bool>> {
+    let (mut hdr, payload, header_type) = match translate(packet) {
+        Some((hdr, payload)) => (hdr, payload, 1),
+        None => ([0; HEADER_LEN], packet, 0),
+    };
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return None;
+    }
+    set_field(&mut hdr, 7, 0, 1, max_fec as u16);
+    set_field(&mut hdr, 7, 1, 1, header_type);
+    set_field(&mut hdr, 7, 11, 10, payload.len() as u16);
+
+    let mut bytes = Scrambler::scramble(&hdr);
+    bytes.extend(ReedSolomon::new(HEADER_CHECK_LEN, FIRST_ROOT).encode(&bytes));
+
+    let (sizes, check_len) = blocks(payload.len(), max_fec);
+    let rs = ReedSolomon::new(check_len, FIRST_ROOT);
+    let mut rest = payload;
+    for size in sizes {
+        let (block, tail) = rest.split_at(size);
+        let scrambled = Scrambler::scramble(block);
+        bytes.extend(&scrambled);
+        bytes.extend(rs.encode(&scrambled));
+        rest = tail;
+    }
+
+    let mut bits: Vec<bool> = (0..24).rev().map(|i| SYNC_WORD >> i & 1 == 1).collect();
+    bits.extend(
+        bytes
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1)),
+    );
+    Some(bits)
+}
+
+/// A header, checked and descrambled.
+struct Header {
+    hdr: [u8; HEADER_LEN],
+    translated: bool,
+    max_fec: bool,
+    payload_len: usize,
+    /// Bytes of the encoded payload that follows the header.
+    encoded_len: usize,
+}
+
+impl Header {
+    fn decode(mut bytes: Vec<u8>) -> Option<Self> {
+        ReedSolomon::new(HEADER_CHECK_LEN, FIRST_ROOT)
+            .decode(&mut bytes)
+            .ok()?;
+        let hdr: [u8; HEADER_LEN] = Scrambler::descramble(&bytes[..HEADER_LEN])
+            .try_into()
+            .ok()?;
+
+        let max_fec = get_field(&hdr, 7, 0, 1) == 1;
+        let payload_len = get_field(&hdr, 7, 11, 10) as usize;
+        let (sizes, check_len) = blocks(payload_len, max_fec);
+
+        Some(Self {
+            hdr,
+            translated: get_field(&hdr, 7, 1, 1) == 1,
+            max_fec,
+            payload_len,
+            encoded_len: sizes.iter().map(|size| size + check_len).sum(),
+        })
+    }
+
+    /// The frame of the packet, from its encoded payload.
+    fn frame(&self, mut encoded: Vec<u8>) -> Option<Frame> {
+        let (sizes, check_len) = blocks(self.payload_len, self.max_fec);
+        let rs = ReedSolomon::new(check_len, FIRST_ROOT);
+
+        let mut payload = Vec::with_capacity(self.payload_len);
+        for size in sizes {
+            let rest = encoded.split_off(size + check_len);
+            rs.decode(&mut encoded).ok()?;
+            payload.extend(Scrambler::descramble(&encoded[..size]));
+            encoded = rest;
+        }
+
+        let packet = if self.translated {
+            untranslate(&self.hdr, &payload)?
+        } else {
+            payload
+        };
+        Some(Frame::new(Some(packet)))
+    }
+}
+
+enum State {
+    /// Looking for the sync word in the last 24 bits.
+    Hunting { register: u32 },
+    /// Receiving the header, bytes so far and bits of the next one, MSB first.
+    Header { bytes: Vec<u8>, bits: u16 },
+    /// Receiving the payload announced by a header.
+    Payload {
+        header: Header,
+        bytes: Vec<u8>,
+        bits: u16,
+    },
+}
+
+pub struct Il2pDeframingIterator<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    input: I,
+    state: State,
+    /// Input bits being received, and the next one.
+    bits: Vec<bool>,
+    position: usize,
+}
+
+impl<I> Il2pDeframingIterator<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    /// Receives a bit, returning the frame of the packet it completed, if any and correctable.
+    fn push(&mut self, bit: bool) -> Option<Frame> {
+        let hunting = State::Hunting { register: 0 };
+
+        match &mut self.state {
+            State::Hunting { register } => {
+                *register = (*register << 1 | bit as u32) & 0xFF_FFFF;
+                if (*register ^ SYNC_WORD).count_ones() <= MAX_SYNC_ERRORS {
+                    self.state = State::Header {
+                        bytes: Vec::with_capacity(HEADER_LEN + HEADER_CHECK_LEN),
+                        bits: 1,
+                    };
+                }
+                None
+            }
+            State::Header { bytes, bits } => {
+                if !push_bit(bytes, bits, bit) || bytes.len() < HEADER_LEN + HEADER_CHECK_LEN {
+                    return None;
+                }
+
+                let header = Header::decode(std::mem::take(bytes));
+                self.state = hunting;
+                let header = header?;
+                if header.encoded_len == 0 {
+                    return header.frame(Vec::new());
+                }
+                self.state = State::Payload {
+                    bytes: Vec::with_capacity(header.encoded_len),
+                    header,
+                    bits: 1,
+                };
+                None
+            }
+            State::Payload {
+                header,
+                bytes,
+                bits,
+            } => {
+                if !push_bit(bytes, bits, bit) || bytes.len() < header.encoded_len {
+                    return None;
+                }
+
+                let bytes = std::mem::take(bytes);
+                let State::Payload { header, .. } = std::mem::replace(&mut self.state, hunting)
+                else {
+                    unreachable!()
+                };
+                header.frame(bytes)
+            }
+        }
+    }
+}
+
+/// Shifts `bit` into `bits`, which starts at 1 as a marker, pushing the byte to `bytes` once
+/// whole. True if it did.
+fn push_bit(bytes: &mut Vec<u8>, bits: &mut u16, bit: bool) -> bool {
+    *bits = *bits << 1 | bit as u16;
+    if *bits < 0x100 {
+        return false;
+    }
+    bytes.push(*bits as u8);
+    *bits = 1;
+    true
+}
+
+impl<I> Iterator for Il2pDeframingIterator<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some(&bit) = self.bits.get(self.position) {
+                self.position += 1;
+                if let Some(frame) = self.push(bit) {
+                    return Some(frame);
+                }
+            }
+
+            self.bits = self.input.next()?;
+            self.position = 0;
+        }
+    }
+}
+
+/// Deframes IL2P packets into frames of their AX.25 frame.
+pub struct Il2pDeframer<I> {
+    _phantom: std::marker::PhantomData<I>,
+}
+
+impl<I> Il2pDeframer<I> {
+    pub fn new() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<I> Default for Il2pDeframer<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I> Deframer<Vec<bool>, Frame> for Il2pDeframer<I>
+where
+    I: Iterator<Item = Vec<bool>>,
+{
+    type Input = I;
+    type Output = Il2pDeframingIterator<I>;
+
+    fn frames(&self, input: Self::Input) -> Self::Output {
+        Il2pDeframingIterator {
+            input,
+            state: State::Hunting { register: 0 },
+            bits: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An AX.25 address field, `dest` then `src`, as a command or a response.
+    fn addresses(dest: (&str, u8), src: (&str, u8), command: bool) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for ((call, ssid), c, last) in [(dest, command, false), (src, !command, true)] {
+            bytes.extend(format!("{:<6}", call).bytes().map(|b| b << 1));
+            bytes.push((c as u8) << 7 | 0x60 | ssid << 1 | last as u8);
+        }
+        bytes
+    }
+
+    fn deframe(bits: Vec<bool>) -> Vec<Frame> {
+        Il2pDeframer::new()
+            .frames(bits.chunks(77).map(<[bool]>::to_vec))
+            .collect()
+    }
+
+    fn preamble() -> Vec<bool> {
+        (0..64).map(|i| i % 2 == 0).collect()
+    }
+
+    /// Sync word and `bytes`, as sent.
+    fn bits(bytes: &[u8]) -> Vec<bool> {
+        let mut bits: Vec<bool> = (0..24).rev().map(|i| SYNC_WORD >> i & 1 == 1).collect();
+        bits.extend(
+            bytes
+                .iter()
+                .flat_map(|byte| (0..8).rev().map(move |i| byte >> i & 1 == 1)),
+        );
+        bits
+    }
+
+    // Worked examples of the specification, as in Dire Wolf's il2p_test.c: a header before
+    // scrambling, and after scrambling and Reed-Solomon encoding.
+
+    /// An RR S frame from KK4HEJ-7 to KA2DEW-2, as a command, with N(R) 5 and P/F set.
+    const EXAMPLE_S_FRAME: [u8; 15] = [
+        0x96, 0x82, 0x64, 0x88, 0x8A, 0xAE, 0xE4, 0x96, 0x96, 0x68, 0x90, 0x8A, 0x94, 0x6F, 0xB1,
+    ];
+    const EXAMPLE_S_FRAME_HEADER: [u8; HEADER_LEN] = [
+        0x2B, 0xA1, 0x12, 0x24, 0x25, 0x77, 0x6B, 0x2B, 0x54, 0x68, 0x25, 0x2A, 0x27,
+    ];
+    const EXAMPLE_S_FRAME_ENCODED: [u8; HEADER_LEN + HEADER_CHECK_LEN] = [
+        0x26, 0x57, 0x4D, 0x57, 0xF1, 0x96, 0xCC, 0x85, 0x42, 0xE7, 0x24, 0xF7, 0x2E, 0x8A, 0x97,
+    ];
+    /// An empty UI frame from KK4HEJ-15 to CQ, as a response, with PID 0xF0.
+    const EXAMPLE_UI_FRAME_HEADER: [u8; HEADER_LEN] = [
+        0x63, 0xF1, 0x40, 0x40, 0x40, 0x00, 0x6B, 0x2B, 0x54, 0x28, 0x25, 0x2A, 0x0F,
+    ];
+    const EXAMPLE_UI_FRAME_ENCODED: [u8; HEADER_LEN + HEADER_CHECK_LEN] = [
+        0x6A, 0xEA, 0x9C, 0xC2, 0x01, 0x11, 0xFC, 0x14, 0x1F, 0xDA, 0x6E, 0xF2, 0x53, 0x91, 0xBD,
+    ];
+
+    #[test]
+    fn encodes_the_examples_of_the_specification() {
+        assert_eq!(
+            encode(&EXAMPLE_S_FRAME, false).unwrap(),
+            bits(&EXAMPLE_S_FRAME_ENCODED)
+        );
+        // The header type is set by the encoder, not the translation.
+        let (mut hdr, payload) = translate(&EXAMPLE_S_FRAME).unwrap();
+        set_field(&mut hdr, 7, 1, 1, 1);
+        assert_eq!(hdr, EXAMPLE_S_FRAME_HEADER);
+        assert!(payload.is_empty());
+
+        for (hdr, encoded) in [
+            (EXAMPLE_S_FRAME_HEADER, EXAMPLE_S_FRAME_ENCODED),
+            (EXAMPLE_UI_FRAME_HEADER, EXAMPLE_UI_FRAME_ENCODED),
+        ] {
+            let mut bytes = Scrambler::scramble(&hdr);
+            bytes.extend(ReedSolomon::new(HEADER_CHECK_LEN, FIRST_ROOT).encode(&bytes));
+
+            assert_eq!(bytes, encoded);
+        }
+    }
+
+    #[test]
+    fn decodes_the_examples_of_the_specification() {
+        let mut bits = preamble();
+        bits.extend(bits_with_error(&EXAMPLE_S_FRAME_ENCODED));
+        bits.extend(bits_with_error(&EXAMPLE_UI_FRAME_ENCODED));
+        bits.extend(preamble());
+
+        let frames = deframe(bits);
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], Frame::new(Some(EXAMPLE_S_FRAME.to_vec())));
+        let ui = frames[1].info.as_ref().unwrap();
+        assert_eq!(
+            ui[..13],
+            [
+                0x86, 0xA2, 0x40, 0x40, 0x40, 0x40, 0x60, 0x96, 0x96, 0x68, 0x90, 0x8A, 0x94
+            ]
+        );
+        // SSID 15, as a response.
+        assert_eq!(ui[13] & 0x1E, 0x1E);
+        assert_eq!(ui[6] >> 7, 0);
+        assert_eq!(ui[14..], [0x03, 0xF0]);
+    }
+
+    /// Like [`bits`], with a wrong byte for the check bytes to correct.
+    fn bits_with_error(bytes: &[u8]) -> Vec<bool> {
+        let mut bytes = bytes.to_vec();
+        bytes[6] ^= 0x5A;
+        bits(&bytes)
+    }
+
+    #[test]
+    fn scrambler_round_trip() {
+        let bytes: Vec<u8> = (0..=255).collect();
+
+        let scrambled = Scrambler::scramble(&bytes);
+
+        assert_ne!(scrambled, bytes);
+        assert_eq!(Scrambler::descramble(&scrambled), bytes);
+    }
+
+    #[test]
+    fn splits_payloads_into_blocks() {
+        assert_eq!(blocks(0, true), (vec![], 0));
+        assert_eq!(blocks(100, true), (vec![100], 16));
+        assert_eq!(blocks(240, true), (vec![120, 120], 16));
+        assert_eq!(blocks(481, true), (vec![161, 160, 160], 16));
+        assert_eq!(blocks(61, false), (vec![61], 2));
+        assert_eq!(blocks(62, false), (vec![62], 4));
+        assert_eq!(blocks(1023, false), (vec![205, 205, 205, 204, 204], 8));
+    }
+
+    #[test]
+    fn translates_every_kind_of_frame() {
+        let ui = addresses(("CQ", 0), ("LU1ABC", 7), true);
+        let i_frame = addresses(("N0CALL", 15), ("LU1ABC", 1), true);
+        let s_frame = addresses(("N0CALL", 0), ("LU1ABC", 0), false);
+        let packets = [
+            [&ui[..], &[0x13, 0xF0], b"telemetry"].concat(),
+            [&i_frame[..], &[0xB6, 0xCC], b"ip"].concat(),
+            [&s_frame[..], &[0xB9]].concat(),
+            [&s_frame[..], &[0x73]].concat(),
+            [&i_frame[..], &[0xE3], b"test"].concat(),
+        ];
+
+        for packet in packets {
+            let (hdr, payload) = translate(&packet).expect("translatable");
+
+            assert_eq!(untranslate(&hdr, payload).as_deref(), Some(&packet[..]));
+        }
+    }
+
+    #[test]
+    fn leaves_untranslatable_frames_to_type_0() {
+        let mut digipeated = addresses(("CQ", 0), ("LU1ABC", 0), true);
+        digipeated[13] &= !1;
+        digipeated.extend(addresses(("RELAY", 0), ("RELAY", 0), true)[7..].iter());
+        let lower_case = addresses(("cq", 0), ("LU1ABC", 0), true);
+        let sabme = addresses(("CQ", 0), ("LU1ABC", 0), true);
+
+        for packet in [
+            [&digipeated[..], &[0x03, 0xF0]].concat(),
+            [&lower_case[..], &[0x03, 0xF0]].concat(),
+            [&sabme[..], &[0x6F]].concat(),
+            vec![0x12, 0x34],
+        ] {
+            assert!(translate(&packet).is_none());
+
+            let frames = deframe(encode(&packet, true).unwrap());
+
+            assert_eq!(frames, vec![Frame::new(Some(packet))]);
+        }
+    }
+
+    #[test]
+    fn corrects_packets_with_both_fec_levels() {
+        let header = addresses(("CQ", 0), ("LU1ABC", 7), true);
+        for max_fec in [true, false] {
+            for len in [0, 10, 300, MAX_PAYLOAD_LEN] {
+                let payload: Vec<u8> = (0..len).map(|i| (i * 13) as u8).collect();
+                let packet = [&header[..], &[0x03, 0xF0], &payload[..]].concat();
+                let mut bits = preamble();
+                bits.extend(encode(&packet, max_fec).unwrap());
+                bits.extend(preamble());
+
+                // A wrong sync bit, a wrong header byte, and a wrong byte in every block.
+                bits[64] ^= true;
+                bits[64 + 24 + 8 * 3] ^= true;
+                let mut start = 64 + 24 + 8 * (HEADER_LEN + HEADER_CHECK_LEN);
+                let (sizes, check_len) = blocks(len, max_fec);
+                for size in sizes {
+                    bits[start + 5] ^= true;
+                    start += 8 * (size + check_len);
+                }
+
+                assert_eq!(
+                    deframe(bits),
+                    vec![Frame::new(Some(packet))],
+                    "{} bytes, max FEC {}",
+                    len,
+                    max_fec
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn deframes_back_to_back_packets() {
+        let header = addresses(("CQ", 0), ("LU1ABC", 0), true);
+        let first = [&header[..], &[0x03, 0xF0], b"first"].concat();
+        let second = [&header[..], &[0x03, 0xF0], b"second"].concat();
+        let mut bits = preamble();
+        bits.extend(encode(&first, false).unwrap());
+        bits.extend(encode(&second, false).unwrap());
+
+        assert_eq!(
+            deframe(bits),
+            vec![Frame::new(Some(first)), Frame::new(Some(second))]
+        );
+    }
+
+    #[test]
+    fn rejects_payloads_too_long() {
+        assert!(encode(&[0; MAX_PAYLOAD_LEN + 1], true).is_none());
+    }
+}
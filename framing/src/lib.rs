@@ -1,5 +1,10 @@
-pub mod bitvecdeque;
+pub mod ax25;
+pub mod csp;
 pub mod deframer;
 pub mod frame;
+pub mod fx25;
 pub mod hdlc_deframer;
+pub mod il2p;
 pub mod mock_deframer;
+pub mod reed_solomon;
+pub mod stream;
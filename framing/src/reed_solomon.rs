@@ -0,0 +1,296 @@
+//! Reed-Solomon codes over GF(2^8), shortened to any block length up to 255 bytes.
+//!
+//! The field is generated by x^8 + x^4 + x^3 + x^2 + 1 (0x11D), and the roots of the generator
+//! polynomial are consecutive powers of α: α^1 to α^nroots for FX.25, α^0 to α^(nroots - 1) for
+//! IL2P. Blocks are the data followed by the check bytes, first byte the coefficient of highest
+//! degree; shortened blocks are full ones with their leading zeros left out.
+
+/// Primitive polynomial of the field.
+const PRIMITIVE_POLY: u16 = 0x11D;
+/// Bytes in a full block.
+pub const BLOCK_LEN: usize = 255;
+
+/// Exponentials and logarithms of the field, base α = 2. Exponentials are repeated so that
+/// the sum of two logarithms can index them.
+struct Field {
+    exp: [u8; 2 * BLOCK_LEN],
+    log: [u8; 256],
+}
+
+static FIELD: Field = Field::new();
+
+impl Field {
+    const fn new() -> Self {
+        let mut exp = [0; 2 * BLOCK_LEN];
+        let mut log = [0; 256];
+        let mut x: u16 = 1;
+        let mut i = 0;
+        while i < BLOCK_LEN {
+            exp[i] = x as u8;
+            exp[i + BLOCK_LEN] = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= PRIMITIVE_POLY;
+            }
+            i += 1;
+        }
+
+        Self { exp, log }
+    }
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    FIELD.exp[FIELD.log[a as usize] as usize + FIELD.log[b as usize] as usize]
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    FIELD.exp[FIELD.log[a as usize] as usize + BLOCK_LEN - FIELD.log[b as usize] as usize]
+}
+
+/// α^power, for any power, negative ones included.
+fn alpha(power: isize) -> u8 {
+    FIELD.exp[power.rem_euclid(BLOCK_LEN as isize) as usize]
+}
+
+/// Value at `x` of a polynomial, coefficients lowest degree first.
+fn eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &c| mul(acc, x) ^ c)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReedSolomonError {
+    /// The block is longer than 255 bytes, or not longer than its check bytes.
+    InvalidBlockLength,
+    /// More errors than the code can correct.
+    Uncorrectable,
+}
+
+/// A code with `nroots` check bytes, which corrects up to `nroots / 2` bytes in error.
+#[derive(Debug, Clone)]
+pub struct ReedSolomon {
+    /// Exponent of the first root of the generator polynomial.
+    first_root: usize,
+    /// Generator polynomial, highest degree first, monic.
+    generator: Vec<u8>,
+}
+
+impl ReedSolomon {
+    pub fn new(nroots: usize, first_root: usize) -> Self {
+        let mut generator = vec![1];
+        for i in 0..nroots {
+            // Times (x - α^(first_root + i)).
+            let root = alpha((first_root + i) as isize);
+            let mut next = generator.clone();
+            next.push(0);
+            for (j, &c) in generator.iter().enumerate() {
+                next[j + 1] ^= mul(c, root);
+            }
+            generator = next;
+        }
+
+        Self {
+            first_root,
+            generator,
+        }
+    }
+
+    /// Check bytes per block.
+    pub fn nroots(&self) -> usize {
+        self.generator.len() - 1
+    }
+
+    /// The check bytes of `data`, to be sent after it.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let nroots = self.nroots();
+        let mut parity = vec![0; nroots];
+
+        for &byte in data {
+            let feedback = byte ^ parity[0];
+            parity.rotate_left(1);
+            parity[nroots - 1] = 0;
+            for (p, &g) in parity.iter_mut().zip(&self.generator[1..]) {
+                *p ^= mul(feedback, g);
+            }
+        }
+
+        parity
+    }
+
+    /// Corrects `block`, data and check bytes, in place, returning how many bytes were wrong.
+    pub fn decode(&self, block: &mut [u8]) -> Result<usize, ReedSolomonError> {
+        let nroots = self.nroots();
+        let n = block.len();
+        if n > BLOCK_LEN || n <= nroots {
+            return Err(ReedSolomonError::InvalidBlockLength);
+        }
+
+        let syndromes: Vec<u8> = (0..nroots)
+            .map(|j| {
+                let root = alpha((self.first_root + j) as isize);
+                block.iter().fold(0, |acc, &b| mul(acc, root) ^ b)
+            })
+            .collect();
+        if syndromes.iter().all(|&s| s == 0) {
+            return Ok(0);
+        }
+
+        let locator = berlekamp_massey(&syndromes);
+        let errors = locator.len() - 1;
+        if errors > nroots / 2 {
+            return Err(ReedSolomonError::Uncorrectable);
+        }
+
+        // Chien search: an error at degree p makes α^-p a root of the locator.
+        let positions: Vec<usize> = (0..n)
+            .filter(|&p| eval(&locator, alpha(-(p as isize))) == 0)
+            .collect();
+        if positions.len() != errors {
+            return Err(ReedSolomonError::Uncorrectable);
+        }
+
+        // Forney: the error evaluator, and the formal derivative of the locator, which in
+        // characteristic 2 only keeps the odd powers.
+        let mut evaluator = vec![0; nroots];
+        for (i, &s) in syndromes.iter().enumerate() {
+            for (j, &l) in locator.iter().enumerate().take(nroots - i) {
+                evaluator[i + j] ^= mul(s, l);
+            }
+        }
+        let derivative: Vec<u8> = locator
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, &l)| if i % 2 == 1 { l } else { 0 })
+            .collect();
+
+        for p in positions {
+            let x_inv = alpha(-(p as isize));
+            let denominator = eval(&derivative, x_inv);
+            if denominator == 0 {
+                return Err(ReedSolomonError::Uncorrectable);
+            }
+            // X^(1 - first_root) Ω(X^-1) / Λ'(X^-1)
+            let magnitude = mul(
+                alpha(p as isize * (1 - self.first_root as isize)),
+                div(eval(&evaluator, x_inv), denominator),
+            );
+            block[n - 1 - p] ^= magnitude;
+        }
+
+        Ok(errors)
+    }
+}
+
+/// The error locator polynomial, lowest degree first, of `syndromes`.
+fn berlekamp_massey(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator = vec![1];
+    let mut previous = vec![1];
+    let mut errors = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1;
+
+    for k in 0..syndromes.len() {
+        let discrepancy = (1..=errors).fold(syndromes[k], |d, i| {
+            d ^ mul(locator.get(i).copied().unwrap_or(0), syndromes[k - i])
+        });
+
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        // locator - discrepancy / previous_discrepancy * x^shift * previous
+        let scale = div(discrepancy, previous_discrepancy);
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len() + shift), 0);
+        for (i, &c) in previous.iter().enumerate() {
+            next[i + shift] ^= mul(scale, c);
+        }
+
+        if 2 * errors <= k {
+            previous = std::mem::replace(&mut locator, next);
+            errors = k + 1 - errors;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            locator = next;
+            shift += 1;
+        }
+    }
+
+    locator.truncate(errors + 1);
+    locator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(rs: &ReedSolomon, data: &[u8]) -> Vec<u8> {
+        let mut block = data.to_vec();
+        block.extend(rs.encode(data));
+        block
+    }
+
+    #[test]
+    fn generator_polynomial() {
+        // (x - α)(x - α^2) = x^2 + (2 ^ 4) x + 8
+        assert_eq!(ReedSolomon::new(2, 1).generator, vec![1, 6, 8]);
+        // (x - 1)(x - α) = x^2 + (1 ^ 2) x + 2
+        assert_eq!(ReedSolomon::new(2, 0).generator, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn corrects_up_to_half_the_check_bytes() {
+        for first_root in [0, 1] {
+            let rs = ReedSolomon::new(16, first_root);
+            let data: Vec<u8> = (0..64).map(|i| (i * 37 + 11) as u8).collect();
+            let sent = block(&rs, &data);
+
+            for errors in 0..=8 {
+                let mut received = sent.clone();
+                for i in 0..errors {
+                    received[i * 9 + 3] ^= 0x5A + i as u8;
+                }
+
+                assert_eq!(rs.decode(&mut received), Ok(errors));
+                assert_eq!(received, sent);
+            }
+        }
+    }
+
+    #[test]
+    fn corrects_full_blocks_and_check_bytes() {
+        let rs = ReedSolomon::new(32, 1);
+        let data: Vec<u8> = (0..223).map(|i| (i * 7) as u8).collect();
+        let sent = block(&rs, &data);
+        let mut received = sent.clone();
+        received[0] = !received[0];
+        received[254] ^= 1;
+        received[230] ^= 0xFF;
+
+        assert_eq!(rs.decode(&mut received), Ok(3));
+        assert_eq!(received, sent);
+    }
+
+    #[test]
+    fn rejects_too_many_errors() {
+        let rs = ReedSolomon::new(16, 1);
+        let mut received = block(&rs, &[0; 32]);
+        for byte in &mut received[..9] {
+            *byte ^= 0x33;
+        }
+
+        assert_eq!(
+            rs.decode(&mut received),
+            Err(ReedSolomonError::Uncorrectable)
+        );
+    }
+}
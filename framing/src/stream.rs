@@ -0,0 +1,81 @@
+//! Async counterpart of [`Deframer`], on streams of bits.
+//!
+//! The sync deframers run on a blocking thread behind [`Blocking`], reading the input stream as
+//! a [`BlockingStream`]. Dropping the output stream cancels them: their input ends, and so does
+//! the thread.
+
+use crate::deframer::Deframer;
+use blocking::{BlockingStream, run_blocking};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
+
+/// Frames deframed ahead of the consumer of the output stream.
+const FRAME_QUEUE_LEN: usize = 16;
+
+/// Deframes a stream of bits into a stream of frames.
+///
+/// The output yields each frame as soon as its last bits are in, and ends once the input has and
+/// the frames deframed from it are out. Bits that don't make up a valid frame are dropped.
+/// Dropping the output stops deframing.
+pub trait AsyncDeframer<B, F> {
+    type Input: Stream<Item = B>;
+    type Output: Stream<Item = F>;
+
+    fn frames(&self, input: Self::Input) -> Self::Output;
+}
+
+/// Runs a sync [`Deframer`] as an [`AsyncDeframer`], on a blocking thread.
+pub struct Blocking<D>(pub D);
+
+impl<D, S, B, F> AsyncDeframer<B, F> for Blocking<D>
+where
+    S: Stream<Item = B> + Send + 'static,
+    D: Deframer<B, F, Input = BlockingStream<S>>,
+    D::Output: Send + 'static,
+    F: Send + 'static,
+{
+    type Input = S;
+    type Output = ReceiverStream<F>;
+
+    /// Must be called within a Tokio runtime.
+    fn frames(&self, input: Self::Input) -> Self::Output {
+        run_blocking(input, FRAME_QUEUE_LEN, |input| self.0.frames(input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::Frame, hdlc_deframer::HdlcDeframer};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn outputs_the_frames_of_the_sync_deframer() {
+        let deframer = Blocking(HdlcDeframer::new());
+        let first = Frame::new(Some(vec![0x0F])).to_bits();
+        let second = Frame::new(Some(vec![0x12, 0x34])).to_bits();
+        let input = tokio_stream::iter(vec![vec![false, true], first, second]);
+
+        let frames: Vec<Frame> = deframer.frames(input).collect().await;
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].info, Some(vec![0x0F]));
+        assert_eq!(frames[1].info, Some(vec![0x12, 0x34]));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_output_stops_reading_the_input() {
+        let deframer = Blocking(HdlcDeframer::new());
+        let (tx, rx) = mpsc::channel(1);
+        let frames = deframer.frames(ReceiverStream::new(rx));
+
+        tx.send(vec![false, true]).await.unwrap();
+        drop(frames);
+
+        // The input is dropped along with the deframer once its thread ends.
+        tokio::time::timeout(Duration::from_secs(5), tx.closed())
+            .await
+            .unwrap();
+    }
+}
@@ -1,7 +1,21 @@
-use axum::{Json, response::IntoResponse};
+use crate::diagnostics::DiagnosticsTx;
+use axum::{
+    Json,
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
 use rustar_types::jobs::{Job, TleData};
+use sdr::{SdrStats, spectrum::Spectrum};
+use serde::Serialize;
 use serde_json::json;
-use utoipa::OpenApi;
+use std::sync::Arc;
+use tokio::sync::{broadcast, watch};
+use utoipa::{OpenApi, ToSchema};
 
 /// # API Documentation
 ///
@@ -11,10 +25,15 @@ use utoipa::OpenApi;
 #[openapi(
     paths(
         add_job,
-        root
+        root,
+        health,
+        spectrum_snapshot,
+        spectrum_stream,
+        sstv_picture,
+        diagnostics_stream
     ),
     components(
-        schemas(Job, TleData)
+        schemas(Job, TleData, SpectrumFrame, StationHealth)
     ),
     tags(
         (name = "Ground Station API", description = "API for interacting with a running ground station instance")
@@ -50,3 +69,207 @@ pub async fn add_job(
 pub async fn root() -> impl IntoResponse {
     Json(json!({ "status": "ok", "message": "Ground Station API is running 🚀" }))
 }
+
+/// State of the SDR in use.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct StationHealth {
+    /// `ok`, `overflowing`, `stalled` or `disconnected`, or `idle` if no SDR is in use.
+    pub sdr: String,
+    /// Times samples stopped arriving.
+    pub stalls: u64,
+    /// Successful reconnections of the SDR.
+    pub reconnects: u64,
+    /// Blocks that followed lost samples.
+    pub gaps: u64,
+}
+
+impl StationHealth {
+    pub fn new(stats: Option<&SdrStats>) -> Self {
+        match stats {
+            Some(stats) => Self {
+                sdr: stats.health().name().to_string(),
+                stalls: stats.stalls(),
+                reconnects: stats.reconnects(),
+                gaps: stats.gaps(),
+            },
+            None => Self {
+                sdr: "idle".to_string(),
+                stalls: 0,
+                reconnects: 0,
+                gaps: 0,
+            },
+        }
+    }
+}
+
+/// Counters of the SDR in use, `None` while there's none.
+pub type SdrStatsRx = watch::Receiver<Option<Arc<SdrStats>>>;
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "Ground Station",
+    responses(
+        (status = 200, description = "State of the SDR in use", body = StationHealth)
+    )
+)]
+pub async fn health(State(sdr_stats_rx): State<SdrStatsRx>) -> impl IntoResponse {
+    Json(StationHealth::new(sdr_stats_rx.borrow().as_deref()))
+}
+
+/// Averaged power spectrum of the SDR samples.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SpectrumFrame {
+    /// Time of the first sample that went into the spectrum, in RFC 3339 format. When the SDR
+    /// only gives its own hardware time, when the spectrum was computed instead.
+    pub timestamp: String,
+    /// Frequency of the middle bin, in Hz.
+    pub center_frequency: f64,
+    /// Width of each bin, in Hz.
+    pub bin_width: f64,
+    /// Power of each bin in dB relative to full scale, from the lowest frequency to the highest.
+    pub power_db: Vec<f32>,
+}
+
+impl From<Spectrum> for SpectrumFrame {
+    fn from(spectrum: Spectrum) -> Self {
+        Self {
+            timestamp: spectrum
+                .timestamp
+                .to_system_time()
+                .map_or_else(Utc::now, DateTime::from)
+                .to_rfc3339(),
+            center_frequency: spectrum.center_frequency,
+            bin_width: spectrum.bin_width,
+            power_db: spectrum.power_db,
+        }
+    }
+}
+
+/// Latest spectrum, `None` until the first one is computed.
+pub type SpectrumRx = watch::Receiver<Option<Arc<SpectrumFrame>>>;
+
+#[utoipa::path(
+    get,
+    path = "/spectrum",
+    tag = "Spectrum",
+    responses(
+        (status = 200, description = "Latest spectrum", body = SpectrumFrame),
+        (status = 404, description = "No spectrum computed yet")
+    )
+)]
+pub async fn spectrum_snapshot(State(spectrum_rx): State<SpectrumRx>) -> Response {
+    let spectrum = spectrum_rx.borrow().clone();
+
+    match spectrum {
+        Some(spectrum) => Json(spectrum).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"status": "error", "message": "No spectrum computed yet"})),
+        )
+            .into_response(),
+    }
+}
+
+/// WebSocket streaming every new spectrum as a JSON text message.
+#[utoipa::path(
+    get,
+    path = "/spectrum/stream",
+    tag = "Spectrum",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol")
+    )
+)]
+pub async fn spectrum_stream(
+    ws: WebSocketUpgrade,
+    State(spectrum_rx): State<SpectrumRx>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| send_spectra(socket, spectrum_rx))
+}
+
+async fn send_spectra(mut socket: WebSocket, mut spectrum_rx: SpectrumRx) {
+    println!("[API] Spectrum client connected");
+
+    // Only the latest spectrum is kept, so slow clients skip spectra instead of falling behind.
+    while spectrum_rx.changed().await.is_ok() {
+        let Some(spectrum) = spectrum_rx.borrow_and_update().clone() else {
+            continue;
+        };
+        let text = serde_json::to_string(&*spectrum).unwrap();
+
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+
+    println!("[API] Spectrum client disconnected");
+}
+
+/// Latest SSTV picture decoded, as a PNG, `None` until the first one.
+pub type SstvRx = watch::Receiver<Option<Arc<Vec<u8>>>>;
+
+#[utoipa::path(
+    get,
+    path = "/sstv",
+    tag = "SSTV",
+    responses(
+        (status = 200, description = "Latest SSTV picture decoded", content_type = "image/png"),
+        (status = 404, description = "No SSTV picture decoded yet")
+    )
+)]
+pub async fn sstv_picture(State(sstv_rx): State<SstvRx>) -> Response {
+    let picture = sstv_rx.borrow().clone();
+
+    match picture {
+        Some(png) => ([(header::CONTENT_TYPE, "image/png")], png.to_vec()).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({"status": "error", "message": "No SSTV picture decoded yet"})),
+        )
+            .into_response(),
+    }
+}
+
+/// WebSocket streaming the diagnostics of the demodulator of the current pass, as JSON arrays of
+/// the symbols demodulated since the previous message.
+#[utoipa::path(
+    get,
+    path = "/diagnostics/stream",
+    tag = "Diagnostics",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol")
+    )
+)]
+pub async fn diagnostics_stream(
+    ws: WebSocketUpgrade,
+    State(diagnostics_tx): State<DiagnosticsTx>,
+) -> impl IntoResponse {
+    let diagnostics_rx = diagnostics_tx.subscribe();
+    ws.on_upgrade(move |socket| send_diagnostics(socket, diagnostics_rx))
+}
+
+async fn send_diagnostics(
+    mut socket: WebSocket,
+    mut diagnostics_rx: broadcast::Receiver<Arc<String>>,
+) {
+    println!("[API] Diagnostics client connected");
+
+    loop {
+        let text = match diagnostics_rx.recv().await {
+            Ok(text) => text,
+            // Slow clients skip batches instead of falling behind.
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        if socket
+            .send(Message::Text(text.as_str().into()))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+
+    println!("[API] Diagnostics client disconnected");
+}
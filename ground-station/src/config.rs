@@ -6,6 +6,34 @@ pub struct Config {
     pub ground_station: GroundStationConfig,
     pub api: ApiConfig,
     pub sdr: SdrConfig,
+    /// Share one wideband SDR between passes. Without it, each pass opens the SDR on its own.
+    #[serde(default)]
+    pub channelizer: Option<ChannelizerConfig>,
+    /// Live spectrum of the SDR samples. Not computed if not set.
+    #[serde(default)]
+    pub spectrum: Option<SpectrumConfig>,
+    /// Signal quality measurements during passes. Not measured if not set.
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    /// Demodulator of the downlink. The example demodulator is used if not set.
+    #[serde(default)]
+    pub demodulator: Option<DemodulatorConfig>,
+    /// Deframer of the bits of the demodulator, for those that output bits. The mock deframer
+    /// is used if not set.
+    #[serde(default)]
+    pub deframer: Option<DeframerConfig>,
+    /// NOAA APT image decoding. No pass is decoded as APT if not set.
+    #[serde(default)]
+    pub apt: Option<AptConfig>,
+    /// FM audio recording of voice passes. No pass is recorded if not set.
+    #[serde(default)]
+    pub audio: Option<AudioConfig>,
+    /// Diagnostics of the FSK demodulator. Not kept if not set.
+    #[serde(default)]
+    pub diagnostics: Option<DiagnosticsConfig>,
+    /// CSP headers of the telemetry frames. Not parsed if not set.
+    #[serde(default)]
+    pub csp: Option<CspConfig>,
 }
 
 /// MQTT Transport Type
@@ -49,12 +77,416 @@ pub struct Location {
 }
 
 /// SDR Type
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum SdrConfig {
-    Mock,
-    ZmqMock { zmq_endpoint: String },
-    Soapy { soapy_string: String },
+    Mock {
+        /// Simulated satellite downlink. Without it, the mock SDR generates a plain tone.
+        #[serde(default)]
+        signal: Option<MockSignalConfig>,
+        #[serde(default)]
+        corrections: CorrectionsConfig,
+    },
+    ZmqMock {
+        zmq_endpoint: String,
+        sample_rate: f64,
+        #[serde(default)]
+        corrections: CorrectionsConfig,
+    },
+    Network {
+        transport: NetworkTransport,
+        /// Address to connect to, or to listen on.
+        address: String,
+        format: SampleFormat,
+        sample_rate: f64,
+        /// UDP datagrams start with a little endian `u32` sequence number.
+        #[serde(default)]
+        sequence_numbers: bool,
+        #[serde(default)]
+        corrections: CorrectionsConfig,
+    },
+    Soapy {
+        soapy_string: String,
+        #[serde(default)]
+        corrections: CorrectionsConfig,
+    },
+}
+
+impl SdrConfig {
+    pub fn corrections(&self) -> &CorrectionsConfig {
+        match self {
+            SdrConfig::Mock { corrections, .. }
+            | SdrConfig::ZmqMock { corrections, .. }
+            | SdrConfig::Network { corrections, .. }
+            | SdrConfig::Soapy { corrections, .. } => corrections,
+        }
+    }
+}
+
+/// Front-end corrections applied to the SDR samples. All disabled by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorrectionsConfig {
+    /// Remove the DC offset (the spike at the center of the spectrum).
+    pub dc_block: bool,
+    /// Estimate and correct the IQ amplitude and phase imbalance.
+    pub iq_balance: bool,
+    /// Frequency error of the local oscillator, in parts per million.
+    pub ppm: f64,
+    /// Additional static frequency error, in Hz.
+    pub frequency_offset: f64,
+}
+
+/// How IQ samples reach the network SDR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkTransport {
+    TcpConnect,
+    TcpListen,
+    Udp,
+}
+
+/// Encoding of the IQ samples received by the network SDR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleFormat {
+    Cf32,
+    Cs16,
+    Cs8,
+    Cu8,
+}
+
+/// Simulated downlink for the mock SDR
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MockSignalConfig {
+    /// Sample rate, in Hz.
+    pub sample_rate: f64,
+    /// Frequency the simulated satellite transmits at, in Hz.
+    pub carrier_frequency: f64,
+    pub modulation: ModulationConfig,
+    /// Signal to noise ratio over the sampled bandwidth, in dB. No noise if not set.
+    pub snr_db: Option<f64>,
+    /// Apply the Doppler shift of the job's satellite, as seen from the ground station.
+    pub doppler: bool,
+    /// Payload of the frames sent by the satellite.
+    pub payload: String,
+    /// Silence between frames, in milliseconds.
+    pub gap_ms: u64,
+}
+
+/// Modulation of the simulated downlink
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ModulationConfig {
+    Afsk {
+        baud: f64,
+        mark: f64,
+        space: f64,
+        deviation: f64,
+    },
+    Gfsk {
+        baud: f64,
+        deviation: f64,
+        bt: f64,
+    },
+    Bpsk {
+        baud: f64,
+    },
+}
+
+/// Wideband receiver split into one channel per pass
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChannelizerConfig {
+    /// Frequency the SDR is tuned to, in Hz. Passes must fall within its bandwidth.
+    pub center_frequency: f64,
+    /// Ratio between the SDR sample rate and each channel's sample rate.
+    pub decimation: usize,
+}
+
+/// Live spectrum of the SDR samples
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpectrumConfig {
+    /// Bins per spectrum.
+    pub fft_size: usize,
+    /// FFTs averaged into each spectrum.
+    pub averages: usize,
+    /// Spectra per second.
+    pub rate: f64,
+    /// Also publish the spectra over MQTT, on `gs/{id}/spectrum`.
+    #[serde(default)]
+    pub mqtt: bool,
+}
+
+/// Signal quality measurements
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    /// Bandwidth of the downlink around the tuned frequency, in Hz.
+    pub bandwidth: f64,
+    /// Bit rate of the downlink, in bits per second.
+    pub bit_rate: f64,
+    /// Measurements per second.
+    pub rate: f64,
+}
+
+/// Demodulator of the downlink
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum DemodulatorConfig {
+    Fsk {
+        /// Tone frequencies relative to the tuned frequency, in Hz, from the lowest to the
+        /// highest. 2 or 4 of them, or any power of two.
+        tones: Vec<f64>,
+        baud: f64,
+        /// Symbol sent on each tone. Natural binary order (`0, 1, 2, ...`) if not set.
+        #[serde(default)]
+        mapping: Option<Vec<u8>>,
+        #[serde(default)]
+        detector: FskDetector,
+    },
+    /// 2-FSK at every combination of the given baud rates and shifts, each with and without
+    /// inversion, G3RUH scrambling and NRZI, decoded in parallel into HDLC frames. For downlinks
+    /// that aren't known exactly; the pass report tells which combination decoded the frames.
+    /// At most [`crate::hypotheses::MAX_HYPOTHESES`] combinations.
+    Hypotheses {
+        bauds: Vec<f64>,
+        /// Shifts between the two tones, in Hz.
+        shifts: Vec<f64>,
+        /// Also try with the bits inverted.
+        #[serde(default = "default_true")]
+        inverted: bool,
+        /// Also try descrambling the bits.
+        #[serde(default = "default_true")]
+        scrambled: bool,
+        /// Also try NRZI decoding the bits, after descrambling them.
+        #[serde(default = "default_true")]
+        nrzi: bool,
+        #[serde(default)]
+        detector: FskDetector,
+    },
+    Lora {
+        /// 7 to 12.
+        spreading_factor: u8,
+        /// In Hz.
+        bandwidth: f64,
+        #[serde(default = "default_sync_word")]
+        sync_word: u8,
+        /// Low data rate optimization. Enabled for symbols over 16 ms if not set.
+        #[serde(default)]
+        low_data_rate: Option<bool>,
+        /// Fixed packet format, with no header sent. Explicit header if not set.
+        #[serde(default)]
+        implicit_header: Option<ImplicitHeaderConfig>,
+    },
+}
+
+/// How the demodulated bits are framed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum DeframerConfig {
+    Hdlc {
+        #[serde(default)]
+        fcs: FcsConfig,
+        /// Order of the bytes of the FCS.
+        #[serde(default)]
+        fcs_byte_order: ByteOrderConfig,
+        /// Order of the bits of every byte.
+        #[serde(default)]
+        bit_order: BitOrderConfig,
+    },
+    /// HDLC, correcting the frames sent with FX.25.
+    Fx25 {
+        #[serde(default)]
+        fcs: FcsConfig,
+        #[serde(default)]
+        fcs_byte_order: ByteOrderConfig,
+        #[serde(default)]
+        bit_order: BitOrderConfig,
+    },
+    Il2p,
+}
+
+/// Frame check sequence of HDLC frames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FcsConfig {
+    Crc16Ccitt,
+    #[default]
+    Crc16X25,
+    Crc32,
+    None,
+}
+
+/// Order of the bytes of a field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ByteOrderConfig {
+    #[default]
+    LittleEndian,
+    BigEndian,
+}
+
+/// Order in which the bits of each byte are sent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BitOrderConfig {
+    #[default]
+    LsbFirst,
+    MsbFirst,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_sync_word() -> u8 {
+    0x12
+}
+
+/// Packet format of LoRa downlinks without a header
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImplicitHeaderConfig {
+    pub payload_len: usize,
+    /// 1 to 4, for 4/5 to 4/8.
+    pub coding_rate: u8,
+    pub crc: bool,
+}
+
+/// How the FSK demodulator detects the tone of each symbol
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FskDetector {
+    Discriminator,
+    #[default]
+    MatchedFilter,
+}
+
+/// NOAA APT image decoding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AptConfig {
+    /// Directory the images are saved to. Only published if not set.
+    #[serde(default)]
+    pub directory: Option<String>,
+    /// Satellites whose passes are decoded as APT.
+    pub satellites: Vec<AptSatelliteConfig>,
+}
+
+impl AptConfig {
+    pub fn satellite(&self, satellite_id: &str) -> Option<&AptSatelliteConfig> {
+        self.satellites
+            .iter()
+            .find(|satellite| satellite.satellite_id == satellite_id)
+    }
+}
+
+/// Satellite transmitting APT
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AptSatelliteConfig {
+    /// As in the jobs.
+    pub satellite_id: String,
+    /// APT downlink frequency, in Hz.
+    pub frequency: f64,
+}
+
+/// CSP (CubeSat Space Protocol) headers at the start of the telemetry frames
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CspConfig {
+    #[serde(default)]
+    pub version: CspVersionConfig,
+    /// Publish each frame on a topic of its source address and port.
+    #[serde(default)]
+    pub split_topics: bool,
+    /// The frames are AX.25 frames, and the CSP header starts their information field, after the
+    /// addresses, control and PID.
+    #[serde(default)]
+    pub ax25: bool,
+}
+
+/// Version of the CSP headers, 1 or 2
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum CspVersionConfig {
+    #[default]
+    V1,
+    V2,
+}
+
+impl TryFrom<u8> for CspVersionConfig {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(CspVersionConfig::V1),
+            2 => Ok(CspVersionConfig::V2),
+            version => Err(format!("invalid CSP version {}, expected 1 or 2", version)),
+        }
+    }
+}
+
+impl From<CspVersionConfig> for u8 {
+    fn from(version: CspVersionConfig) -> Self {
+        match version {
+            CspVersionConfig::V1 => 1,
+            CspVersionConfig::V2 => 2,
+        }
+    }
+}
+
+/// Diagnostics of the FSK demodulator: the constellation, eye diagram, timing and frequency errors
+/// of every symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsConfig {
+    /// Directory each pass's diagnostics are saved to. Only streamed if not set.
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+/// FM audio recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioConfig {
+    /// Directory the recordings are saved to.
+    pub directory: String,
+    /// Satellites whose passes are recorded instead of decoded. APT takes precedence.
+    pub satellites: Vec<AudioSatelliteConfig>,
+}
+
+impl AudioConfig {
+    pub fn satellite(&self, satellite_id: &str) -> Option<&AudioSatelliteConfig> {
+        self.satellites
+            .iter()
+            .find(|satellite| satellite.satellite_id == satellite_id)
+    }
+}
+
+/// Satellite transmitting FM audio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioSatelliteConfig {
+    /// As in the jobs.
+    pub satellite_id: String,
+    /// FM downlink frequency, in Hz.
+    pub frequency: f64,
+    /// Peak deviation, in Hz.
+    #[serde(default = "default_deviation")]
+    pub deviation: f64,
+    /// Highest audio frequency kept, in Hz.
+    #[serde(default = "default_audio_bandwidth")]
+    pub audio_bandwidth: f64,
+    /// De-emphasis time constant, in µs. Not de-emphasized if not set.
+    #[serde(default)]
+    pub deemphasis: Option<f64>,
+    /// Signal power under which the audio is muted, in dBFS. Never muted if not set.
+    #[serde(default)]
+    pub squelch: Option<f32>,
+    /// Decode the SSTV pictures in the audio.
+    #[serde(default)]
+    pub sstv: bool,
+}
+
+fn default_deviation() -> f64 {
+    5_000.0
+}
+
+fn default_audio_bandwidth() -> f64 {
+    4_000.0
 }
 
 /// API Server Configuration
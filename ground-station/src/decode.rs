@@ -0,0 +1,516 @@
+//! Telemetry frames of the downlink, from the configured demodulator and deframer.
+
+use crate::{
+    config,
+    hypotheses::{self, Hypothesis},
+    report::DemodulatorStats,
+};
+use blocking::run_blocking;
+use demod::{
+    DemodulatorError,
+    diagnostics::DiagnosticsTap,
+    example::ExampleDemod,
+    fsk::{Detector, Fsk, FskParams},
+    line_coding::LineCoding,
+    lora::{LoraDecoder, LoraHeader, LoraParams},
+    stream::{AsyncDemodulator, Blocking as BlockingDemodulator},
+};
+use framing::{
+    ax25,
+    csp::{CspHeader, CspVersion},
+    frame::{BitOrder, ByteOrder, Fcs, Frame, FrameFormat},
+    fx25::Fx25Deframer,
+    hdlc_deframer::HdlcDeframer,
+    il2p::Il2pDeframer,
+    mock_deframer::MockDeframer,
+    stream::{AsyncDeframer, Blocking as BlockingDeframer},
+};
+use sdr::block::SampleBlock;
+use std::{pin::Pin, sync::Arc};
+use tokio_stream::{Stream, StreamExt};
+
+/// Frames decoded ahead of their publisher.
+const FRAME_QUEUE_LEN: usize = 16;
+
+/// Demodulator of the downlink, as configured.
+#[derive(Clone)]
+pub enum DownlinkDemodulator {
+    /// Bits, framed by the deframer.
+    Fsk(Fsk),
+    /// HDLC frames, from whichever of several guesses at the downlink decodes them.
+    Hypotheses(Vec<Hypothesis>),
+    /// Whole packets. The decoder is created for each pass, since it keeps state.
+    Lora(LoraParams),
+}
+/// `sample_rate` is that of the samples of passes, if known.
+pub fn create_demodulator(
+    demodulator_config: &config::DemodulatorConfig,
+    sample_rate: Option<f64>,
+) -> DownlinkDemodulator {
+    match demodulator_config {
+        config::DemodulatorConfig::Fsk {
+            tones,
+            baud,
+            mapping,
+            detector,
+        } => {
+            let params = FskParams {
+                tones: tones.clone(),
+                baud: *baud,
+                mapping: mapping
+                    .clone()
+                    .unwrap_or_else(|| (0..tones.len()).map(|symbol| symbol as u8).collect()),
+                detector: fsk_detector(*detector),
+            };
+
+            let fsk = Fsk::new(params)
+                .unwrap_or_else(|err| panic!("Invalid FSK demodulator configuration: {:?}", err));
+
+            DownlinkDemodulator::Fsk(fsk)
+        }
+        config::DemodulatorConfig::Hypotheses {
+            bauds,
+            shifts,
+            inverted,
+            scrambled,
+            nrzi,
+            detector,
+        } => {
+            let codings = LineCoding::combinations(*inverted, *scrambled, *nrzi);
+            let detector = fsk_detector(*detector);
+
+            let hypotheses = Hypothesis::combinations(bauds, shifts, &codings, |baud, shift| {
+                FskParams::binary(baud, shift, detector)
+            })
+            .unwrap_or_else(|err| panic!("Invalid FSK demodulator configuration: {:?}", err));
+            if hypotheses.len() > hypotheses::MAX_HYPOTHESES {
+                panic!(
+                    "Too many hypotheses: {}, at most {} can be decoded at once",
+                    hypotheses.len(),
+                    hypotheses::MAX_HYPOTHESES
+                );
+            }
+            println!(
+                "[DECODE] Trying {} hypotheses in parallel",
+                hypotheses.len()
+            );
+
+            DownlinkDemodulator::Hypotheses(hypotheses)
+        }
+        config::DemodulatorConfig::Lora {
+            spreading_factor,
+            bandwidth,
+            sync_word,
+            low_data_rate,
+            implicit_header,
+        } => {
+            let mut params = LoraParams::new(*spreading_factor, *bandwidth);
+            params.sync_word = *sync_word;
+            if let Some(low_data_rate) = low_data_rate {
+                params.low_data_rate = *low_data_rate;
+            }
+            if let Some(header) = implicit_header {
+                params.header = LoraHeader::Implicit {
+                    payload_len: header.payload_len,
+                    coding_rate: header.coding_rate,
+                    has_crc: header.crc,
+                };
+            }
+
+            if let Err(err) = LoraDecoder::new(params.clone()) {
+                panic!("Invalid LoRa demodulator configuration: {:?}", err);
+            }
+            if let Some(sample_rate) = sample_rate
+                && let Err(err) = params.oversampling(sample_rate)
+            {
+                panic!(
+                    "The SDR can't feed the LoRa demodulator, its sample rate must be a multiple of the {} Hz bandwidth: {:?}",
+                    params.bandwidth, err
+                );
+            }
+
+            DownlinkDemodulator::Lora(params)
+        }
+    }
+}
+
+/// How the CSP headers of the telemetry frames are parsed and published.
+#[derive(Debug, Clone, Copy)]
+pub struct CspSettings {
+    version: CspVersion,
+    pub split_topics: bool,
+    /// The header follows that of an AX.25 frame.
+    ax25: bool,
+}
+
+impl CspSettings {
+    pub fn new(config: &config::CspConfig) -> Self {
+        Self {
+            version: match config.version {
+                config::CspVersionConfig::V1 => CspVersion::V1,
+                config::CspVersionConfig::V2 => CspVersion::V2,
+            },
+            split_topics: config.split_topics,
+            ax25: config.ax25,
+        }
+    }
+
+    /// The CSP header of a telemetry frame, if it has one.
+    pub fn header(&self, frame: &[u8]) -> Option<CspHeader> {
+        let packet = if self.ax25 { ax25::info(frame)? } else { frame };
+
+        CspHeader::parse(packet, self.version)
+            .ok()
+            .map(|(header, _)| header)
+    }
+}
+
+/// Frames of the `samples` of a pass, by `demodulator` and `deframer`, or the example
+/// demodulator and the mock deframer if they aren't configured. `stats` counts the demodulator's
+/// errors, which are skipped so that a quiet spell doesn't end the pass, and `tap`, if any, gets
+/// the diagnostics of an FSK demodulator.
+pub fn frames(
+    samples: impl Stream<Item = SampleBlock> + Send + 'static,
+    demodulator: Option<DownlinkDemodulator>,
+    deframer: Option<config::DeframerConfig>,
+    stats: Arc<DemodulatorStats>,
+    tap: Option<DiagnosticsTap>,
+) -> Pin<Box<dyn Stream<Item = Frame> + Send>> {
+    let bits: Pin<Box<dyn Stream<Item = Result<Vec<bool>, DemodulatorError>> + Send>> =
+        match demodulator {
+            // LoRa packets carry their own framing.
+            Some(DownlinkDemodulator::Lora(params)) => {
+                return Box::pin(run_blocking(samples, FRAME_QUEUE_LEN, |samples| {
+                    LoraDecoder::new(params)
+                        .expect("validated at startup")
+                        .packets(samples)
+                        .filter_map(move |packet| stats.record(packet))
+                        .filter_map(|packet| {
+                            println!(
+                                "[LORA] {} byte packet, CRC {:?}, SNR {:.1} dB",
+                                packet.payload.len(),
+                                packet.crc_ok,
+                                packet.snr_db
+                            );
+                            packet.is_valid().then(|| Frame::new(Some(packet.payload)))
+                        })
+                }));
+            }
+            Some(DownlinkDemodulator::Hypotheses(guesses)) => {
+                return Box::pin(run_blocking(samples, FRAME_QUEUE_LEN, |samples| {
+                    hypotheses::decode(samples, guesses, stats)
+                }));
+            }
+            Some(DownlinkDemodulator::Fsk(fsk)) => {
+                let fsk = match tap {
+                    Some(tap) => fsk.with_diagnostics(tap),
+                    None => fsk,
+                };
+                Box::pin(BlockingDemodulator(fsk).bits(samples))
+            }
+            None => Box::pin(BlockingDemodulator(ExampleDemod::new()).bits(samples)),
+        };
+
+    let bits = bits.filter_map(move |bits| stats.record(bits));
+    deframe(deframer, bits)
+}
+
+/// Frames of `bits`, by the configured deframer, or the mock one if none is.
+pub fn deframe(
+    deframer: Option<config::DeframerConfig>,
+    bits: impl Stream<Item = Vec<bool>> + Send + 'static,
+) -> Pin<Box<dyn Stream<Item = Frame> + Send>> {
+    match deframer {
+        Some(config::DeframerConfig::Hdlc {
+            fcs,
+            fcs_byte_order,
+            bit_order,
+        }) => Box::pin(
+            BlockingDeframer(HdlcDeframer::with_format(frame_format(
+                fcs,
+                fcs_byte_order,
+                bit_order,
+            )))
+            .frames(bits),
+        ),
+        Some(config::DeframerConfig::Fx25 {
+            fcs,
+            fcs_byte_order,
+            bit_order,
+        }) => Box::pin(
+            BlockingDeframer(Fx25Deframer::with_format(frame_format(
+                fcs,
+                fcs_byte_order,
+                bit_order,
+            )))
+            .frames(bits),
+        ),
+        Some(config::DeframerConfig::Il2p) => {
+            Box::pin(BlockingDeframer(Il2pDeframer::new()).frames(bits))
+        }
+        None => Box::pin(
+            BlockingDeframer(MockDeframer::new(
+                "IN A HOLE IN THE GROUND".as_bytes().to_vec(),
+            ))
+            .frames(bits),
+        ),
+    }
+}
+
+fn frame_format(
+    fcs: config::FcsConfig,
+    fcs_byte_order: config::ByteOrderConfig,
+    bit_order: config::BitOrderConfig,
+) -> FrameFormat {
+    FrameFormat {
+        fcs: match fcs {
+            config::FcsConfig::Crc16Ccitt => Fcs::Crc16Ccitt,
+            config::FcsConfig::Crc16X25 => Fcs::Crc16X25,
+            config::FcsConfig::Crc32 => Fcs::Crc32,
+            config::FcsConfig::None => Fcs::None,
+        },
+        fcs_byte_order: match fcs_byte_order {
+            config::ByteOrderConfig::LittleEndian => ByteOrder::LittleEndian,
+            config::ByteOrderConfig::BigEndian => ByteOrder::BigEndian,
+        },
+        bit_order: match bit_order {
+            config::BitOrderConfig::LsbFirst => BitOrder::LsbFirst,
+            config::BitOrderConfig::MsbFirst => BitOrder::MsbFirst,
+        },
+    }
+}
+
+fn fsk_detector(detector: config::FskDetector) -> Detector {
+    match detector {
+        config::FskDetector::Discriminator => Detector::Discriminator,
+        config::FskDetector::MatchedFilter => Detector::MatchedFilter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdr::{block::Timestamp, modulation::Modulation};
+    use std::time::Duration;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    fn hdlc(fcs: config::FcsConfig, bit_order: config::BitOrderConfig) -> config::DeframerConfig {
+        config::DeframerConfig::Hdlc {
+            fcs,
+            fcs_byte_order: config::ByteOrderConfig::default(),
+            bit_order,
+        }
+    }
+
+    /// `bits` between idle flags, at 1200 baud with a 1 kHz shift.
+    fn transmit(bits: Vec<bool>) -> Vec<SampleBlock> {
+        let modulation = Modulation::Gfsk {
+            baud: 1200.0,
+            deviation: 500.0,
+            bt: 1.0,
+        };
+        let mut padded = vec![false; 64];
+        padded.extend(bits);
+        padded.extend(vec![false; 64]);
+
+        modulation
+            .modulate(&padded, SAMPLE_RATE)
+            .chunks(1024)
+            .map(|chunk| {
+                SampleBlock::new(
+                    chunk.to_vec(),
+                    SAMPLE_RATE,
+                    0.0,
+                    Timestamp::Hardware(Duration::ZERO),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fx25_deframer_corrects_frames() {
+        let frame = Frame::new(Some(b"fx.25 downlink".to_vec()));
+        let mut bits = vec![false; 32];
+        bits.extend(framing::fx25::encode(&frame, 16).unwrap());
+        bits.extend(vec![false; 32]);
+        // A wrong byte in the block, past the 64 bits of the tag.
+        for bit in &mut bits[32 + 64 + 40..32 + 64 + 48] {
+            *bit = !*bit;
+        }
+
+        let deframer = config::DeframerConfig::Fx25 {
+            fcs: config::FcsConfig::default(),
+            fcs_byte_order: config::ByteOrderConfig::default(),
+            bit_order: config::BitOrderConfig::default(),
+        };
+        let frames: Vec<Frame> = deframe(Some(deframer), tokio_stream::iter(vec![bits]))
+            .collect()
+            .await;
+
+        assert_eq!(frames, vec![frame]);
+    }
+
+    #[tokio::test]
+    async fn hdlc_deframer_follows_the_configured_format() {
+        let format = FrameFormat {
+            fcs: Fcs::Crc32,
+            fcs_byte_order: ByteOrder::LittleEndian,
+            bit_order: BitOrder::MsbFirst,
+        };
+        let frame = Frame::with_format(Some(b"crc-32, msb first".to_vec()), format);
+        let bits = frame.to_bits();
+
+        let configured = hdlc(config::FcsConfig::Crc32, config::BitOrderConfig::MsbFirst);
+        let frames: Vec<Frame> = deframe(Some(configured), tokio_stream::iter(vec![bits.clone()]))
+            .collect()
+            .await;
+        assert_eq!(frames, vec![frame]);
+
+        // The default CRC doesn't check.
+        let default = hdlc(
+            config::FcsConfig::default(),
+            config::BitOrderConfig::MsbFirst,
+        );
+        let frames: Vec<Frame> = deframe(Some(default), tokio_stream::iter(vec![bits]))
+            .collect()
+            .await;
+        assert!(frames.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fsk_frames_are_deframed_and_counted() {
+        let frame = Frame::new(Some(b"telemetry".to_vec()));
+        let blocks = transmit(frame.to_bits());
+
+        let demodulator = create_demodulator(
+            &config::DemodulatorConfig::Fsk {
+                tones: vec![-500.0, 500.0],
+                baud: 1200.0,
+                mapping: None,
+                detector: config::FskDetector::default(),
+            },
+            Some(SAMPLE_RATE),
+        );
+        let deframer = hdlc(
+            config::FcsConfig::default(),
+            config::BitOrderConfig::default(),
+        );
+        let stats = Arc::new(DemodulatorStats::default());
+
+        let decoded: Vec<Frame> = frames(
+            tokio_stream::iter(blocks),
+            Some(demodulator),
+            Some(deframer),
+            stats,
+            None,
+        )
+        .collect()
+        .await;
+
+        assert_eq!(decoded, vec![frame]);
+    }
+
+    #[tokio::test]
+    async fn hypotheses_decode_frames_without_a_deframer() {
+        let frame = Frame::new(Some(b"unknown downlink".to_vec()));
+        let blocks = transmit(frame.to_bits());
+
+        let demodulator = create_demodulator(
+            &config::DemodulatorConfig::Hypotheses {
+                bauds: vec![1200.0],
+                shifts: vec![1000.0],
+                inverted: true,
+                scrambled: false,
+                nrzi: false,
+                detector: config::FskDetector::default(),
+            },
+            Some(SAMPLE_RATE),
+        );
+        let stats = Arc::new(DemodulatorStats::default());
+
+        let decoded: Vec<Frame> = frames(
+            tokio_stream::iter(blocks),
+            Some(demodulator),
+            None,
+            stats.clone(),
+            None,
+        )
+        .collect()
+        .await;
+
+        assert_eq!(decoded, vec![frame]);
+        assert_eq!(
+            stats.hypotheses(),
+            vec![("1200 Bd, 1000 Hz shift".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many hypotheses")]
+    fn hypotheses_are_bounded() {
+        // 6 baud rates and 2 shifts, with 6 line codings each.
+        let bauds = (1..=6).map(|n| n as f64 * 1200.0).collect();
+
+        create_demodulator(
+            &config::DemodulatorConfig::Hypotheses {
+                bauds,
+                shifts: vec![1000.0, 4800.0],
+                inverted: true,
+                scrambled: true,
+                nrzi: true,
+                detector: config::FskDetector::default(),
+            },
+            Some(SAMPLE_RATE),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The SDR can't feed the LoRa demodulator")]
+    fn lora_needs_a_multiple_of_its_bandwidth() {
+        create_demodulator(
+            &config::DemodulatorConfig::Lora {
+                spreading_factor: 7,
+                bandwidth: 125_000.0,
+                sync_word: 0x12,
+                low_data_rate: None,
+                implicit_header: None,
+            },
+            Some(300_000.0),
+        );
+    }
+
+    #[test]
+    fn csp_headers_are_found_after_ax25_addresses() {
+        let header = CspHeader {
+            priority: 2,
+            source: 1,
+            destination: 10,
+            destination_port: 7,
+            source_port: 42,
+            flags: Default::default(),
+        };
+        let packet = header.to_bytes(CspVersion::V1);
+
+        // Destination and source addresses, the last one with the extension bit set, then the
+        // control byte of a UI frame and the PID.
+        let mut frame: Vec<u8> = b"N0CALL\x60N1CALL\x61".to_vec();
+        frame.extend([0x03, 0xf0]);
+        frame.extend(&packet);
+
+        let plain = CspSettings::new(&config::CspConfig {
+            version: config::CspVersionConfig::V1,
+            split_topics: false,
+            ax25: false,
+        });
+        let ax25 = CspSettings::new(&config::CspConfig {
+            version: config::CspVersionConfig::V1,
+            split_topics: false,
+            ax25: true,
+        });
+
+        assert_eq!(plain.header(&packet), Some(header));
+        assert_eq!(ax25.header(&frame), Some(header));
+        assert_eq!(ax25.header(&packet), None);
+    }
+}
@@ -0,0 +1,101 @@
+use demod::diagnostics::SymbolDiagnostics;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    iter,
+    path::Path,
+    sync::{Arc, mpsc},
+    thread::{self, JoinHandle},
+};
+use tokio::sync::broadcast;
+
+/// Batches of [`SymbolReport`]s, as JSON arrays, for `/diagnostics/stream`.
+pub type DiagnosticsTx = broadcast::Sender<Arc<String>>;
+
+/// Demodulator state at a symbol. Complex values are `[re, im]`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolReport {
+    pub index: u64,
+    pub symbol: [f32; 2],
+    pub trace: Vec<[f32; 2]>,
+    /// Symbols.
+    pub timing_error: f64,
+    /// Radians.
+    pub phase_error: Option<f64>,
+    /// Hz.
+    pub frequency_error: Option<f64>,
+}
+
+impl SymbolReport {
+    pub fn new(diagnostics: SymbolDiagnostics) -> Self {
+        Self {
+            index: diagnostics.index,
+            symbol: [diagnostics.symbol.re, diagnostics.symbol.im],
+            trace: diagnostics.trace.iter().map(|s| [s.re, s.im]).collect(),
+            timing_error: diagnostics.timing_error,
+            phase_error: diagnostics.phase_error,
+            frequency_error: diagnostics.frequency_error,
+        }
+    }
+}
+
+/// Saves the diagnostics of a pass as JSON lines, one symbol per line, to `{name}-diagnostics.jsonl`
+/// in `directory`, if set, and streams them in batches to the clients of `/diagnostics/stream`.
+/// Runs on its own thread, until the demodulator drops its tap.
+pub fn spawn(
+    diagnostics: mpsc::Receiver<SymbolDiagnostics>,
+    directory: Option<String>,
+    name: String,
+    stream_tx: DiagnosticsTx,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut file = None;
+
+        while let Ok(first) = diagnostics.recv() {
+            // Whatever else is pending goes in the same batch.
+            let batch: Vec<SymbolReport> = iter::once(first)
+                .chain(diagnostics.try_iter())
+                .map(SymbolReport::new)
+                .collect();
+
+            if let Some(directory) = &directory
+                && let Err(err) = save(&mut file, directory, &name, &batch)
+            {
+                eprintln!("[DIAGNOSTICS] Failed to save: {}", err);
+            }
+
+            if stream_tx.receiver_count() > 0 {
+                let _ = stream_tx.send(Arc::new(serde_json::to_string(&batch).unwrap()));
+            }
+        }
+
+        if let Some(mut file) = file {
+            let _ = file.flush();
+        }
+    })
+}
+
+/// Appends `batch` to the diagnostics file, creating it first if it wasn't.
+fn save(
+    file: &mut Option<BufWriter<File>>,
+    directory: &str,
+    name: &str,
+    batch: &[SymbolReport],
+) -> std::io::Result<()> {
+    let file = match file {
+        Some(file) => file,
+        None => {
+            let path = Path::new(directory).join(format!("{}-diagnostics.jsonl", name));
+            println!("[DIAGNOSTICS] Saving to {}", path.display());
+            file.insert(BufWriter::new(File::create(path)?))
+        }
+    };
+
+    for symbol in batch {
+        serde_json::to_writer(&mut *file, symbol)?;
+        writeln!(file)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+//! Doppler of the downlinks, from the range rate the tracker reports.
+
+use chrono::{DateTime, Utc};
+use rustar_types::jobs::Job;
+use sdr::doppler::DopplerProfile;
+use std::sync::Arc;
+use tracking::{Elements, Observer, Tracker};
+
+/// Speed of light, in km/s, the unit of the tracker's ranges over seconds.
+const SPEED_OF_LIGHT: f64 = 299_792.458;
+
+/// Doppler shift, in Hz, of a downlink at `frequency` Hz whose range changes at `range_rate`
+/// km/s, as observed by the tracker.
+pub fn doppler_shift(frequency: f64, range_rate: f64) -> f64 {
+    -frequency * range_rate / SPEED_OF_LIGHT
+}
+
+/// Doppler of the downlink the mock SDR simulates for `job`, as seen from `observer`, following
+/// the same tracker passes are tuned from.
+pub fn simulated_profile(job: &Job, observer: &Observer) -> DopplerProfile {
+    let elements = match Elements::from_tle(
+        Some(job.tle.tle0.clone()),
+        job.tle.tle1.as_bytes(),
+        job.tle.tle2.as_bytes(),
+    ) {
+        Ok(elements) => elements,
+        Err(err) => {
+            eprintln!("[SDR] Invalid TLE, not simulating Doppler: {:?}", err);
+            return DopplerProfile::None;
+        }
+    };
+    let tracker = match Tracker::new(observer, elements) {
+        Ok(tracker) => tracker,
+        Err(err) => {
+            eprintln!(
+                "[SDR] Can't track the satellite, not simulating Doppler: {:?}",
+                err
+            );
+            return DopplerProfile::None;
+        }
+    };
+
+    DopplerProfile::Tracked(Arc::new(move |time| {
+        let obs = tracker.track(DateTime::<Utc>::from(time)).ok()?;
+
+        // The simulator takes m/s.
+        (obs.elevation > 0.0).then_some(obs.range_rate * 1000.0)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn receding_satellites_lower_the_downlink_frequency() {
+        // 7 km/s, about the fastest a LEO satellite's range changes, is about 10.2 kHz at 435 MHz.
+        let receding = doppler_shift(435e6, 7.0);
+        let approaching = doppler_shift(435e6, -7.0);
+
+        assert!((receding + 10_157.0).abs() < 1.0, "{}", receding);
+        assert_eq!(approaching, -receding);
+    }
+
+    #[test]
+    fn tracker_and_simulator_agree_on_the_shift() {
+        // The simulator's profile converts the tracker's km/s to m/s.
+        let profile = DopplerProfile::Tracked(Arc::new(|_| Some(7.0 * 1000.0)));
+        let simulated = profile.offset(435e6, std::time::SystemTime::now()).unwrap();
+
+        assert!((simulated - doppler_shift(435e6, 7.0)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::report::DemodulatorStats;
+use demod::{
+    Demodulator,
+    fsk::{Fsk, FskParams},
+    line_coding::LineCoding,
+};
+use framing::{deframer::Deframer, frame::Frame, fx25::Fx25Deframer};
+use sdr::block::SampleBlock;
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Blocks each branch may fall behind the others before the input waits for it.
+const BRANCH_QUEUE_LEN: usize = 64;
+/// Hypotheses decoded at once. Each one takes a thread for the whole pass.
+pub const MAX_HYPOTHESES: usize = 64;
+/// Frames with the same payload decoded within this long of each other are the same frame.
+const DUPLICATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// A guess at how the downlink is modulated and coded.
+#[derive(Debug, Clone)]
+pub struct Hypothesis {
+    pub fsk: Fsk,
+    pub coding: LineCoding,
+}
+
+impl Hypothesis {
+    /// Every combination of the given baud rates and shifts of 2-FSK, with each line coding.
+    pub fn combinations(
+        bauds: &[f64],
+        shifts: &[f64],
+        codings: &[LineCoding],
+        fsk: impl Fn(f64, f64) -> FskParams,
+    ) -> Result<Vec<Self>, demod::fsk::FskError> {
+        let mut hypotheses = Vec::new();
+        for baud in bauds {
+            for shift in shifts {
+                let fsk = Fsk::new(fsk(*baud, *shift))?;
+                for coding in codings {
+                    hypotheses.push(Self {
+                        fsk: fsk.clone(),
+                        coding: *coding,
+                    });
+                }
+            }
+        }
+
+        Ok(hypotheses)
+    }
+}
+
+impl fmt::Display for Hypothesis {
+    /// e.g. `9600 Bd, 4800 Hz shift, scrambled, NRZI`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self.fsk.params();
+        let tones = &params.tones;
+        write!(
+            f,
+            "{} Bd, {} Hz shift",
+            params.baud,
+            tones[tones.len() - 1] - tones[0]
+        )?;
+        if self.coding.inverted {
+            write!(f, ", inverted")?;
+        }
+        if self.coding.scrambled {
+            write!(f, ", scrambled")?;
+        }
+        if self.coding.nrzi {
+            write!(f, ", NRZI")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes `samples` into HDLC frames, with or without FX.25, with every hypothesis in parallel,
+/// each on its own thread, so there should be at most [`MAX_HYPOTHESES`] of them.
+/// Frames decoded by several of them are only output once, and `stats` counts the frames each
+/// one decoded, duplicates included, to tell which of them is right.
+pub fn decode(
+    samples: impl Iterator<Item = SampleBlock> + Send + 'static,
+    hypotheses: Vec<Hypothesis>,
+    stats: Arc<DemodulatorStats>,
+) -> impl Iterator<Item = Frame> {
+    let names: Vec<String> = hypotheses.iter().map(|h| h.to_string()).collect();
+    let (frame_tx, frame_rx) = mpsc::channel();
+
+    let mut branches = Vec::new();
+    for (index, hypothesis) in hypotheses.into_iter().enumerate() {
+        let (block_tx, block_rx) = mpsc::sync_channel::<SampleBlock>(BRANCH_QUEUE_LEN);
+        let frame_tx = frame_tx.clone();
+        let stats = stats.clone();
+
+        thread::spawn(move || {
+            let mut decoder = hypothesis.coding.decoder();
+            let bits = hypothesis
+                .fsk
+                .bits(block_rx.into_iter())
+                .filter_map(|bits| stats.record(bits))
+                .map(|bits| decoder.decode(bits));
+
+            for frame in Fx25Deframer::new().frames(bits) {
+                if frame_tx.send((index, frame)).is_err() {
+                    break;
+                }
+            }
+        });
+        branches.push(block_tx);
+    }
+
+    // Fans the samples out, until they run out or every branch is gone.
+    thread::spawn(move || {
+        for block in samples {
+            branches.retain(|branch| branch.send(block.clone()).is_ok());
+            if branches.is_empty() {
+                break;
+            }
+        }
+    });
+
+    let mut recent: VecDeque<(Instant, Option<Vec<u8>>)> = VecDeque::new();
+    frame_rx.into_iter().filter_map(move |(index, frame)| {
+        let name = &names[index];
+        stats.decoded_by(name);
+
+        let now = Instant::now();
+        while recent
+            .front()
+            .is_some_and(|(time, _)| now - *time > DUPLICATE_WINDOW)
+        {
+            recent.pop_front();
+        }
+
+        if recent.iter().any(|(_, info)| *info == frame.info) {
+            return None;
+        }
+        println!("[DECODE] Frame decoded with {}", name);
+        recent.push_back((now, frame.info.clone()));
+
+        Some(frame)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use demod::{
+        fsk::Detector,
+        line_coding::{NrziEncoder, Scrambler},
+    };
+    use sdr::block::{Complex, Timestamp};
+    use std::f64::consts::PI;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    /// 2-FSK at `baud` and `shift`, with `bits` coded as in `coding`.
+    fn transmit(bits: &[bool], baud: f64, shift: f64, coding: LineCoding) -> Vec<SampleBlock> {
+        let mut nrzi = NrziEncoder::new();
+        let mut scrambler = Scrambler::new();
+        let samples_per_bit = SAMPLE_RATE / baud;
+        let mut phase = 0.0;
+        let mut samples = Vec::new();
+
+        for (i, bit) in bits.iter().enumerate() {
+            let mut bit = *bit;
+            if coding.nrzi {
+                bit = nrzi.encode(bit);
+            }
+            if coding.scrambled {
+                bit = scrambler.scramble(bit);
+            }
+            if coding.inverted {
+                bit = !bit;
+            }
+
+            let frequency = if bit { shift / 2.0 } else { -shift / 2.0 };
+            let end = ((i + 1) as f64 * samples_per_bit).round() as usize;
+            while samples.len() < end {
+                phase += 2.0 * PI * frequency / SAMPLE_RATE;
+                samples.push(Complex::new(phase.cos() as f32, phase.sin() as f32));
+            }
+        }
+
+        samples
+            .chunks(1024)
+            .map(|chunk| {
+                SampleBlock::new(
+                    chunk.to_vec(),
+                    SAMPLE_RATE,
+                    0.0,
+                    Timestamp::Hardware(Duration::ZERO),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_with_the_right_hypothesis_only_once() {
+        let coding = LineCoding {
+            inverted: true,
+            scrambled: true,
+            nrzi: false,
+        };
+        let mut bits = vec![false; 64];
+        bits.extend(Frame::new(Some(b"hypothesis".to_vec())).to_bits());
+        bits.extend(vec![false; 64]);
+        let blocks = transmit(&bits, 2400.0, 2400.0, coding);
+
+        let hypotheses = Hypothesis::combinations(
+            &[1200.0, 2400.0],
+            &[2400.0],
+            &LineCoding::combinations(true, true, false),
+            |baud, shift| FskParams::binary(baud, shift, Detector::MatchedFilter),
+        )
+        .unwrap();
+        let stats = Arc::new(DemodulatorStats::default());
+
+        let frames: Vec<Frame> = decode(blocks.into_iter(), hypotheses, stats.clone()).collect();
+
+        assert_eq!(frames, vec![Frame::new(Some(b"hypothesis".to_vec()))]);
+        assert_eq!(
+            stats.hypotheses(),
+            vec![("2400 Bd, 2400 Hz shift, inverted, scrambled".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn decodes_nrzi_coded_scrambled_frames() {
+        let coding = LineCoding {
+            inverted: false,
+            scrambled: true,
+            nrzi: true,
+        };
+        let mut bits = vec![false; 64];
+        bits.extend(Frame::new(Some(b"g3ruh".to_vec())).to_bits());
+        bits.extend(vec![false; 64]);
+        let blocks = transmit(&bits, 2400.0, 2400.0, coding);
+
+        let hypotheses = Hypothesis::combinations(
+            &[2400.0],
+            &[2400.0],
+            &LineCoding::combinations(true, true, true),
+            |baud, shift| FskParams::binary(baud, shift, Detector::MatchedFilter),
+        )
+        .unwrap();
+        let stats = Arc::new(DemodulatorStats::default());
+
+        let frames: Vec<Frame> = decode(blocks.into_iter(), hypotheses, stats.clone()).collect();
+
+        assert_eq!(frames, vec![Frame::new(Some(b"g3ruh".to_vec()))]);
+        assert_eq!(
+            stats.hypotheses(),
+            vec![("2400 Bd, 2400 Hz shift, scrambled, NRZI".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn outputs_frames_decoded_by_several_hypotheses_once() {
+        let mut bits = vec![false; 64];
+        bits.extend(Frame::new(Some(b"twice".to_vec())).to_bits());
+        bits.extend(vec![false; 64]);
+        let blocks = transmit(&bits, 1200.0, 1000.0, LineCoding::default());
+
+        // The same hypothesis twice, so that both decode the frame.
+        let hypotheses = Hypothesis::combinations(
+            &[1200.0],
+            &[1000.0, 1000.0],
+            &[LineCoding::default()],
+            |baud, shift| FskParams::binary(baud, shift, Detector::MatchedFilter),
+        )
+        .unwrap();
+        let stats = Arc::new(DemodulatorStats::default());
+
+        let frames: Vec<Frame> = decode(blocks.into_iter(), hypotheses, stats.clone()).collect();
+
+        assert_eq!(frames, vec![Frame::new(Some(b"twice".to_vec()))]);
+        assert_eq!(
+            stats.hypotheses(),
+            vec![("1200 Bd, 1000 Hz shift".to_string(), 2)]
+        );
+    }
+}
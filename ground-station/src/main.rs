@@ -1,54 +1,396 @@
 mod api;
 mod config;
+mod decode;
+mod diagnostics;
+mod doppler;
+mod hypotheses;
+mod receiver;
+mod recording;
+mod report;
 mod scheduler;
 
 use crate::{
     config::Config,
+    decode::{CspSettings, DownlinkDemodulator, create_demodulator},
+    receiver::{PassReceiver, SharedReceiver},
+    recording::AudioRecording,
+    report::{CspReport, DemodulatorStats, PassReport, SignalReport},
     scheduler::{Scheduler, Task},
 };
 use antenna_controller::{self, AntennaController, mock::MockController};
-use api::{ApiDoc, add_job, root};
+use api::{
+    ApiDoc, SpectrumFrame, StationHealth, add_job, diagnostics_stream, health, root,
+    spectrum_snapshot, spectrum_stream, sstv_picture,
+};
 use axum::{
     Router,
     routing::{get, post},
 };
+use blocking::BlockingStream;
 use chrono::Utc;
-use demod::{Demodulator, example::ExampleDemod};
-use framing::{deframer::Deframer, mock_deframer::MockDeframer};
+use demod::{
+    apt::{AptDecoder, AptImage},
+    diagnostics::DiagnosticsTap,
+    fm::{FmDemodulator, FmParams},
+    sstv::{SstvDecoder, SstvImage},
+};
+use framing::frame::Frame;
 use rumqttc::{AsyncClient, Incoming, MqttOptions, QoS, Transport, tokio_rustls};
 use rustar_types::{
     jobs::{Job, JobStatus},
     mqtt::telemetry::TelemetryMessage,
 };
-use sdr::{MockSdr, SdrCommand, sdr_task};
+use sdr::{
+    MockSdr, SdrStats,
+    correction::{CorrectedSdr, Corrections},
+    doppler::DopplerProfile,
+    metrics::{PassMetrics, SignalMeter},
+    modulation::Modulation,
+    network::{self, NetworkSdr, SampleFormat, StreamStats},
+    sim::SimulatedSignal,
+    spectrum::{SpectrumAnalyzer, SpectrumTap},
+};
+use serde_json::json;
 use std::{
-    sync::{
-        Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
-    },
+    path::Path,
+    sync::{Arc, Mutex, atomic::Ordering},
     time::Duration,
 };
-use tokio::{net::TcpListener, sync::mpsc, time::Instant};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, mpsc, watch},
+    time::Instant,
+};
 use tokio_rustls::rustls::ClientConfig;
+use tokio_stream::StreamExt;
 use tracking::{Elements, Tracker};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-fn create_sdr(sdr_config: &config::SdrConfig) -> Box<dyn sdr::Sdr + Send> {
-    match sdr_config {
-        config::SdrConfig::Mock => {
+/// Sample blocks buffered between the SDR reader and the demodulator before new ones are dropped.
+const SAMPLE_QUEUE_LEN: usize = 64;
+
+/// Symbols of diagnostics buffered between the demodulator and their writer before new ones are
+/// dropped.
+const DIAGNOSTICS_SYMBOL_QUEUE_LEN: usize = 4096;
+
+/// Batches of diagnostics a slow API client may fall behind before skipping some.
+const DIAGNOSTICS_QUEUE_LEN: usize = 16;
+
+/// Largest MQTT packet the station publishes, to fit APT images.
+const MAX_OUTGOING_PACKET_SIZE: usize = 16 * 1024 * 1024;
+
+/// How often the antenna and the downlink frequency follow the satellite during a pass.
+const TRACKING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the SDR health is checked for changes to publish.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bins of the spectra signal metrics are measured on.
+const METRICS_FFT_SIZE: usize = 512;
+/// FFTs averaged into each of the spectra signal metrics are measured on.
+const METRICS_AVERAGES: usize = 8;
+
+// TODO: consider using crate engineering units, might be elegant
+/// Sample rate of the mock SDR when it generates a plain tone, in Hz.
+const MOCK_SAMPLE_RATE: f64 = 48_000.0;
+
+/// Downlink frequency of the tracked satellites, in Hz.
+const DOWNLINK_FREQUENCY: f64 = 435_000_000.0;
+
+/// How a pass is received.
+enum PassMode {
+    /// Telemetry frames, from the configured demodulator.
+    Frames,
+    /// An APT image.
+    Apt,
+    /// An FM audio recording, saved in `directory`, and the SSTV pictures in it if `sstv`.
+    Audio {
+        params: FmParams,
+        directory: String,
+        sstv: bool,
+    },
+}
+
+/// What a pass produced besides telemetry frames.
+enum PassProduct {
+    Image(AptImage),
+    Recording(AudioRecording),
+}
+
+/// How the passes of `satellite_id` are received, and on which frequency.
+fn pass_mode(satellite_id: &str, config: &Config) -> (PassMode, f64) {
+    if let Some(satellite) = config
+        .apt
+        .as_ref()
+        .and_then(|apt| apt.satellite(satellite_id))
+    {
+        return (PassMode::Apt, satellite.frequency);
+    }
+
+    if let Some(audio) = &config.audio
+        && let Some(satellite) = audio.satellite(satellite_id)
+    {
+        let mode = PassMode::Audio {
+            params: fm_params(satellite),
+            directory: audio.directory.clone(),
+            sstv: satellite.sstv,
+        };
+        return (mode, satellite.frequency);
+    }
+
+    (PassMode::Frames, DOWNLINK_FREQUENCY)
+}
+
+fn fm_params(satellite: &config::AudioSatelliteConfig) -> FmParams {
+    FmParams {
+        deviation: satellite.deviation,
+        audio_bandwidth: satellite.audio_bandwidth,
+        deemphasis: satellite.deemphasis.map(|deemphasis| deemphasis * 1e-6),
+        squelch: satellite.squelch,
+    }
+}
+
+/// Sample rate of the samples passes receive, in Hz, if known before the SDR is opened.
+fn pass_sample_rate(config: &Config) -> Option<f64> {
+    let sample_rate = match &config.sdr {
+        config::SdrConfig::Mock { signal: None, .. } => MOCK_SAMPLE_RATE,
+        config::SdrConfig::Mock {
+            signal: Some(signal),
+            ..
+        } => signal.sample_rate,
+        config::SdrConfig::ZmqMock { sample_rate, .. }
+        | config::SdrConfig::Network { sample_rate, .. } => *sample_rate,
+        config::SdrConfig::Soapy { .. } => return None,
+    };
+
+    // Channels are decimated from the shared SDR.
+    Some(match &config.channelizer {
+        Some(channelizer) => sample_rate / channelizer.decimation.max(1) as f64,
+        None => sample_rate,
+    })
+}
+
+/// The configured SDR, and the counters of its lost UDP datagrams if it numbers them.
+fn create_sdr(
+    sdr_config: &config::SdrConfig,
+    job: Option<&Job>,
+    observer: &tracking::Observer,
+) -> (Box<dyn sdr::Sdr + Send>, Option<Arc<StreamStats>>) {
+    let mut stream_stats = None;
+    let sdr: Box<dyn sdr::Sdr + Send> = match sdr_config {
+        config::SdrConfig::Mock { signal: None, .. } => {
             println!("[SDR] Creating Mock SDR");
-            Box::new(MockSdr::new(48_000.0, 1200.0, 512))
+            Box::new(MockSdr::new(MOCK_SAMPLE_RATE, 1200.0, 512))
         }
-        config::SdrConfig::ZmqMock { zmq_endpoint } => {
+        config::SdrConfig::Mock {
+            signal: Some(signal),
+            ..
+        } => {
+            println!("[SDR] Creating Mock SDR simulating {:?}", signal.modulation);
+
+            // A shared SDR isn't opened for a job, so it can't simulate its Doppler.
+            let doppler = match job {
+                Some(job) if signal.doppler => doppler::simulated_profile(job, observer),
+                _ => DopplerProfile::None,
+            };
+
+            let modulation = match signal.modulation {
+                config::ModulationConfig::Afsk {
+                    baud,
+                    mark,
+                    space,
+                    deviation,
+                } => Modulation::Afsk {
+                    baud,
+                    mark,
+                    space,
+                    deviation,
+                },
+                config::ModulationConfig::Gfsk {
+                    baud,
+                    deviation,
+                    bt,
+                } => Modulation::Gfsk {
+                    baud,
+                    deviation,
+                    bt,
+                },
+                config::ModulationConfig::Bpsk { baud } => Modulation::Bpsk { baud },
+            };
+
+            let frame = Frame::new(Some(signal.payload.as_bytes().to_vec()));
+
+            Box::new(MockSdr::simulating(
+                signal.sample_rate,
+                512,
+                SimulatedSignal {
+                    modulation,
+                    frames: vec![frame.to_bits()],
+                    gap: Duration::from_millis(signal.gap_ms),
+                    carrier_frequency: signal.carrier_frequency,
+                    snr_db: signal.snr_db,
+                    doppler,
+                },
+            ))
+        }
+        config::SdrConfig::ZmqMock {
+            zmq_endpoint,
+            sample_rate,
+            ..
+        } => {
             println!("[SDR] Creating ZMQ Mock SDR: {}", zmq_endpoint);
-            Box::new(sdr::ZmqMockSdr::new(zmq_endpoint.clone()))
+            Box::new(sdr::ZmqMockSdr::new(zmq_endpoint.clone(), *sample_rate))
         }
-        config::SdrConfig::Soapy { soapy_string } => {
+        config::SdrConfig::Network {
+            transport,
+            address,
+            format,
+            sample_rate,
+            sequence_numbers,
+            ..
+        } => {
+            println!("[SDR] Creating network SDR: {:?} {}", transport, address);
+
+            let transport = match transport {
+                config::NetworkTransport::TcpConnect => {
+                    network::Transport::TcpConnect(address.clone())
+                }
+                config::NetworkTransport::TcpListen => {
+                    network::Transport::TcpListen(address.clone())
+                }
+                config::NetworkTransport::Udp => network::Transport::Udp {
+                    address: address.clone(),
+                    sequence_numbers: *sequence_numbers,
+                },
+            };
+            let format = match format {
+                config::SampleFormat::Cf32 => SampleFormat::Cf32,
+                config::SampleFormat::Cs16 => SampleFormat::Cs16,
+                config::SampleFormat::Cs8 => SampleFormat::Cs8,
+                config::SampleFormat::Cu8 => SampleFormat::Cu8,
+            };
+
+            let sdr = NetworkSdr::new(transport, format, *sample_rate);
+            if *sequence_numbers {
+                stream_stats = Some(sdr.stats());
+            }
+            Box::new(sdr)
+        }
+        config::SdrConfig::Soapy { soapy_string, .. } => {
             println!("[SDR] Creating SoapySDR: {}", soapy_string);
             panic!("SoapySDR not yet implemented");
         }
+    };
+
+    let corrections = sdr_config.corrections();
+    if *corrections == config::CorrectionsConfig::default() {
+        return (sdr, stream_stats);
+    }
+
+    println!("[SDR] Applying front-end corrections: {:?}", corrections);
+    let sdr = Box::new(CorrectedSdr::new(
+        sdr,
+        Corrections {
+            dc_block: corrections.dc_block,
+            iq_balance: corrections.iq_balance,
+            ppm: corrections.ppm,
+            frequency_offset: corrections.frequency_offset,
+        },
+    ));
+
+    (sdr, stream_stats)
+}
+
+/// Computes the spectra of the samples read by `sdr`, if enabled, and publishes them on
+/// `spectrum_tx`.
+fn tap_spectrum(
+    sdr: Box<dyn sdr::Sdr + Send>,
+    spectrum_config: &Option<config::SpectrumConfig>,
+    spectrum_tx: &watch::Sender<Option<Arc<SpectrumFrame>>>,
+) -> Box<dyn sdr::Sdr + Send> {
+    let Some(spectrum_config) = spectrum_config else {
+        return sdr;
+    };
+
+    let analyzer = SpectrumAnalyzer::new(
+        spectrum_config.fft_size,
+        spectrum_config.averages,
+        spectrum_config.rate,
+    );
+    let spectrum_tx = spectrum_tx.clone();
+
+    Box::new(SpectrumTap::new(sdr, analyzer, move |spectrum| {
+        spectrum_tx.send_replace(Some(Arc::new(spectrum.into())));
+    }))
+}
+
+/// Encodes the image of an APT pass as a PNG, also saving it in the configured directory.
+fn apt_product(
+    image: &AptImage,
+    job_id: i64,
+    satellite_id: &str,
+    apt_config: Option<&config::AptConfig>,
+) -> Option<Vec<u8>> {
+    println!(
+        "[APT] {} lines, {} in sync",
+        image.lines.len(),
+        image.synced_lines
+    );
+    if image.lines.is_empty() {
+        return None;
+    }
+
+    let png = match image.to_png() {
+        Ok(png) => png,
+        Err(err) => {
+            eprintln!("[APT] Failed to encode the image: {}", err);
+            return None;
+        }
+    };
+
+    if let Some(directory) = apt_config.and_then(|apt| apt.directory.as_ref()) {
+        let path = Path::new(directory).join(format!("{}-{}.png", job_id, satellite_id));
+
+        match std::fs::write(&path, &png) {
+            Ok(()) => println!("[APT] Saved {}", path.display()),
+            Err(err) => eprintln!("[APT] Failed to save {}: {}", path.display(), err),
+        }
     }
+
+    Some(png)
+}
+
+/// Encodes an SSTV picture as a PNG, saving it in `directory` as the `number`th of the pass.
+fn sstv_product(
+    picture: &SstvImage,
+    directory: &str,
+    name: &str,
+    number: usize,
+) -> Option<Vec<u8>> {
+    println!(
+        "[SSTV] {} picture, {}x{}, {} lines in sync",
+        picture.mode.name(),
+        picture.width,
+        picture.height,
+        picture.synced_lines
+    );
+
+    let png = match picture.to_png() {
+        Ok(png) => png,
+        Err(err) => {
+            eprintln!("[SSTV] Failed to encode the picture: {}", err);
+            return None;
+        }
+    };
+
+    let path = Path::new(directory).join(format!("{}-sstv-{}.png", name, number));
+    match std::fs::write(&path, &png) {
+        Ok(()) => println!("[SSTV] Saved {}", path.display()),
+        Err(err) => eprintln!("[SSTV] Failed to save {}: {}", path.display(), err),
+    }
+
+    Some(png)
 }
 
 #[tokio::main]
@@ -82,6 +424,28 @@ async fn main() {
     );
     println!("  API: {}:{}", config.api.host, config.api.port);
     println!("  SDR: {:?}", config.sdr);
+    if let Some(channelizer) = &config.channelizer {
+        println!("  Channelizer: {:?}", channelizer);
+    }
+    if let Some(demodulator) = &config.demodulator {
+        println!("  Demodulator: {:?}", demodulator);
+    }
+
+    // Built upfront, so that an invalid configuration fails at startup rather than on a pass.
+    let downlink_demodulator = config
+        .demodulator
+        .as_ref()
+        .map(|demodulator| create_demodulator(demodulator, pass_sample_rate(&config)));
+    let csp = config.csp.as_ref().map(CspSettings::new);
+
+    for satellite in config.audio.iter().flat_map(|audio| &audio.satellites) {
+        if let Err(err) = FmDemodulator::new(fm_params(satellite)) {
+            panic!(
+                "Invalid audio configuration of {}: {:?}",
+                satellite.satellite_id, err
+            );
+        }
+    }
 
     let observer = tracking::Observer::new(
         config.ground_station.location.latitude,
@@ -95,6 +459,7 @@ async fn main() {
         config.mqtt.port,
     );
     mqttoptions.set_keep_alive(Duration::from_secs(config.mqtt.timeout_seconds));
+    mqttoptions.set_max_packet_size(10 * 1024, MAX_OUTGOING_PACKET_SIZE);
 
     if let Some(ref auth) = config.mqtt.auth {
         mqttoptions.set_credentials(&auth.username, &auth.password);
@@ -129,6 +494,76 @@ async fn main() {
     let (job_tx, mut job_rx) = mpsc::unbounded_channel::<Job>();
     let mut scheduler = Scheduler::<Job>::new();
 
+    let (spectrum_tx, spectrum_rx) = watch::channel(None::<Arc<SpectrumFrame>>);
+
+    if config
+        .spectrum
+        .as_ref()
+        .is_some_and(|spectrum| spectrum.mqtt)
+    {
+        let client_clone = client.clone();
+        let spectrum_topic = format!("gs/{}/spectrum", &config.ground_station.id);
+        let mut spectrum_rx = spectrum_rx.clone();
+
+        tokio::spawn(async move {
+            while spectrum_rx.changed().await.is_ok() {
+                let Some(spectrum) = spectrum_rx.borrow_and_update().clone() else {
+                    continue;
+                };
+
+                client_clone
+                    .publish(
+                        &spectrum_topic,
+                        QoS::AtMostOnce,
+                        false,
+                        serde_json::to_string(&*spectrum).unwrap().as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+    }
+
+    // Diagnostics of the demodulator of the current pass.
+    let (diagnostics_tx, _) = broadcast::channel(DIAGNOSTICS_QUEUE_LEN);
+
+    // Latest SSTV picture, as a PNG.
+    let (sstv_tx, sstv_rx) = watch::channel(None::<Arc<Vec<u8>>>);
+
+    // Counters of the SDR in use, the shared one or that of the current pass.
+    let (sdr_stats_tx, sdr_stats_rx) = watch::channel(None::<Arc<SdrStats>>);
+
+    {
+        let client_clone = client.clone();
+        let health_topic = format!("gs/{}/health", &config.ground_station.id);
+        let sdr_stats_rx = sdr_stats_rx.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+            let mut last = None;
+
+            loop {
+                interval.tick().await;
+
+                let health = StationHealth::new(sdr_stats_rx.borrow().as_deref());
+                if last.as_ref() == Some(&health) {
+                    continue;
+                }
+
+                client_clone
+                    .publish(
+                        &health_topic,
+                        QoS::AtLeastOnce,
+                        true,
+                        serde_json::to_string(&health).unwrap().as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+                last = Some(health);
+            }
+        });
+    }
+
     let api_addr = format!("{}:{}", config.api.host, config.api.port);
     let listener = TcpListener::bind(&api_addr).await.unwrap();
 
@@ -136,7 +571,28 @@ async fn main() {
         .merge(SwaggerUi::new("/docs").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .route("/", get(root))
         .route("/jobs", post(add_job))
-        .with_state(job_tx.clone());
+        .with_state(job_tx.clone())
+        .merge(
+            Router::new()
+                .route("/spectrum", get(spectrum_snapshot))
+                .route("/spectrum/stream", get(spectrum_stream))
+                .with_state(spectrum_rx),
+        )
+        .merge(
+            Router::new()
+                .route("/health", get(health))
+                .with_state(sdr_stats_rx),
+        )
+        .merge(
+            Router::new()
+                .route("/sstv", get(sstv_picture))
+                .with_state(sstv_rx),
+        )
+        .merge(
+            Router::new()
+                .route("/diagnostics/stream", get(diagnostics_stream))
+                .with_state(diagnostics_tx.clone()),
+        );
 
     tokio::spawn(async move {
         println!("Swagger UI available at http://{}/docs", api_addr);
@@ -144,6 +600,22 @@ async fn main() {
         axum::serve(listener, router).await.unwrap();
     });
 
+    // One SDR for the whole station, split into a channel per pass.
+    let shared_receiver = config.channelizer.as_ref().map(|channelizer| {
+        let (sdr, stream_stats) = create_sdr(&config.sdr, None, &observer);
+        let sdr = tap_spectrum(sdr, &config.spectrum, &spectrum_tx);
+
+        let shared = SharedReceiver::start(
+            sdr,
+            channelizer.center_frequency,
+            channelizer.decimation,
+            SAMPLE_QUEUE_LEN,
+        );
+        sdr_stats_tx.send_replace(Some(shared.stats().clone()));
+
+        (Arc::new(shared), stream_stats)
+    });
+
     loop {
         tokio::select! {
             // Receive jobs from API and add them to scheduler.
@@ -195,7 +667,28 @@ async fn main() {
 
                 let config_clone = config.clone();
                 let observer_clone = observer.clone();
-                let sdr = create_sdr(&config_clone.sdr);
+                let downlink_demodulator_clone = downlink_demodulator.clone();
+                // APT and audio passes produce an image or a recording instead of frames.
+                let (mode, downlink_frequency) = pass_mode(&job.satellite_id, &config_clone);
+                let (receiver, samp_rx, sdr_handle, stream_stats) = match &shared_receiver {
+                    Some((shared, stream_stats)) => {
+                        let (receiver, samp_rx) =
+                            shared.open_channel(downlink_frequency, SAMPLE_QUEUE_LEN);
+                        (receiver, samp_rx, None, stream_stats.clone())
+                    }
+                    None => {
+                        let (sdr, stream_stats) = create_sdr(
+                            &config_clone.sdr,
+                            Some(&job),
+                            &observer_clone,
+                        );
+                        let sdr = tap_spectrum(sdr, &config_clone.spectrum, &spectrum_tx);
+                        let (receiver, samp_rx, handle) =
+                            PassReceiver::dedicated(sdr, SAMPLE_QUEUE_LEN);
+                        sdr_stats_tx.send_replace(Some(receiver.sdr_stats().clone()));
+                        (receiver, samp_rx, Some(handle), stream_stats)
+                    }
+                };
 
                 let client_for_started = client.clone();
                 let job_id_for_started = job.id;
@@ -216,6 +709,9 @@ async fn main() {
 
                 let client_clone = client.clone();
                 let gs_id_clone = config_clone.ground_station.id.clone();
+                let sdr_stats_tx = sdr_stats_tx.clone();
+                let sstv_tx = sstv_tx.clone();
+                let diagnostics_tx = diagnostics_tx.clone();
 
                 // Lanzar tracking en background
                 tokio::spawn(async move {
@@ -229,29 +725,28 @@ async fn main() {
                     )
                     .unwrap();
                     let tracker = Tracker::new(&observer_clone, elements).unwrap();
-                    let stop = Arc::new(AtomicBool::new(false));
 
-                    let deframer = MockDeframer::new("IN A HOLE IN THE GROUND".as_bytes().to_vec());
-                    let demodulator = ExampleDemod::new();
+                    let deframer = config_clone.deframer;
                     let controller = Arc::new(Mutex::new(MockController));
-
-                    let (cmd_tx, cmd_rx) = mpsc::channel(1); // tokio channel
-                    let (samp_tx, samp_rx) = std::sync::mpsc::channel(); // standard channel
+                    let pass_stats = receiver.stats().clone();
+                    let sdr_stats = receiver.sdr_stats().clone();
                     // END SETUP
 
-                    let sdr_handle = tokio::spawn(sdr_task(sdr, cmd_rx, samp_tx));
-
                     // TRACKING
-                    let stop_clone = stop.clone();
                     let controller_clone = controller.clone();
+                    // The pass lasts until LOS, the end of the job.
+                    let los = job.end;
                     let tracker_handle = tokio::spawn(async move {
-                        // TODO: until los in job
-                        for i in 0..5 {
-                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        let mut interval = tokio::time::interval(TRACKING_INTERVAL);
+                        let mut step = 0;
+
+                        while Utc::now() < los {
+                            interval.tick().await;
                             let obs = tracker.track(Utc::now()).unwrap();
 
                             println!("Tracking step {}: Az={:.1}°, El={:.1}°",
-                            i, obs.azimuth.to_degrees(), obs.elevation.to_degrees());
+                            step, obs.azimuth.to_degrees(), obs.elevation.to_degrees());
+                            step += 1;
 
                             controller_clone
                             .lock()
@@ -259,65 +754,351 @@ async fn main() {
                                 .send(obs.azimuth.to_degrees(), obs.elevation.to_degrees(), "ISS", 145800)
                                 .unwrap();
 
-                            // TODO: consider using crate engineering units, might be elegant
-                            cmd_tx.send(SdrCommand::SetRxFrequency(435_000_000.0)).await.unwrap();
-
-                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            let shift = doppler::doppler_shift(downlink_frequency, obs.range_rate);
+                            receiver.tune(downlink_frequency + shift).await;
                         }
 
                         println!("\nPass ended, stopping SDR and tracker.\n");
-                        stop_clone.store(true, Ordering::Relaxed);
+                        // Stops the SDR or closes the channel, which ends the samples of the pass.
+                        drop(receiver);
                     });
 
-                    // BITS/FRAMES - Move to blocking task to handle std::sync::mpsc
-                    let stop_clone = stop.clone();
+                    // SIGNAL METRICS
+                    let pass_metrics = Arc::new(Mutex::new(PassMetrics::new()));
+                    let mut meter = config_clone.metrics.as_ref().map(|metrics| {
+                        SignalMeter::new(
+                            SpectrumAnalyzer::new(METRICS_FFT_SIZE, METRICS_AVERAGES, metrics.rate),
+                            metrics.bandwidth,
+                            metrics.bit_rate,
+                        )
+                    });
+                    let (metrics_tx, mut metrics_rx) = mpsc::unbounded_channel();
+
+                    let client_for_metrics = client_clone.clone();
+                    let job_id_for_metrics = job.id;
+                    let metrics_handle = tokio::spawn(async move {
+                        while let Some(metrics) = metrics_rx.recv().await {
+                            let msg = json!({
+                                "timestamp": Utc::now().to_rfc3339(),
+                                "metrics": SignalReport::new(metrics),
+                            });
+
+                            client_for_metrics
+                                .publish(
+                                    &format!("job/{}/metrics", job_id_for_metrics),
+                                    QoS::AtMostOnce,
+                                    false,
+                                    msg.to_string().as_bytes(),
+                                )
+                                .await
+                                .unwrap();
+                        }
+                    });
+
+                    // BITS/FRAMES
+                    let pass_metrics_clone = pass_metrics.clone();
                     let satellite_id = job.satellite_id.clone();
                     let (frame_tx, mut frame_rx) = mpsc::unbounded_channel();
+                    let recording_name = format!("{}-{}", job.id, job.satellite_id);
+                    let (picture_tx, mut picture_rx) = mpsc::unbounded_channel();
+                    let demod_stats = Arc::new(DemodulatorStats::default());
+                    let demod_stats_clone = demod_stats.clone();
+                    let (diagnostics_tap, diagnostics_dropped) = match (&config_clone.diagnostics, &downlink_demodulator_clone) {
+                        (Some(diagnostics), Some(DownlinkDemodulator::Fsk(_))) => {
+                            let (tap, diagnostics_rx) = DiagnosticsTap::new(DIAGNOSTICS_SYMBOL_QUEUE_LEN);
+                            let dropped = tap.dropped();
+                            diagnostics::spawn(
+                                diagnostics_rx,
+                                diagnostics.directory.clone(),
+                                recording_name.clone(),
+                                diagnostics_tx.clone(),
+                            );
+                            (Some(tap), Some(dropped))
+                        }
+                        _ => (None, None),
+                    };
 
-                    let frame_handle = tokio::task::spawn_blocking(move || {
-                        let bits = demodulator.bits(samp_rx.into_iter());
-                        let mut frames = deframer.frames(bits);
-
-                        while !stop_clone.load(Ordering::Relaxed) {
-                            if let Some(payload) = frames.next().and_then(|frame| frame.info) {
-                                frame_tx.send(payload).unwrap();
+                    // The samples end at LOS, when the tracker drops the receiver, and so does
+                    // everything decoded from them.
+                    let pass_metrics_for_samples = pass_metrics.clone();
+                    let samples = receiver::sample_stream(samp_rx).map(move |block| {
+                        if let Some(meter) = &mut meter {
+                            for metrics in meter.feed(&block) {
+                                pass_metrics_for_samples.lock().unwrap().add(metrics);
+                                let _ = metrics_tx.send(metrics);
                             }
                         }
+                        block
                     });
 
-                    // NOTE: it really is a pita to have both sync and async mixed contexts.
-                    // TODO: we should finish moving the demodulator and deframer to be async and be done with it.
+                    let frame_handle = tokio::spawn(async move {
+                        match mode {
+                            PassMode::Frames => {}
+                            PassMode::Apt => {
+                                let samples = BlockingStream::new(samples);
+                                let image = tokio::task::spawn_blocking(move || {
+                                    let mut decoder = AptDecoder::new();
+                                    for block in samples {
+                                        decoder.feed(&block);
+                                    }
+                                    decoder.into_image()
+                                });
+
+                                return image.await.ok().map(PassProduct::Image);
+                            }
+                            PassMode::Audio {
+                                params,
+                                directory,
+                                sstv,
+                            } => {
+                                let samples = BlockingStream::new(samples);
+                                let recording = tokio::task::spawn_blocking(move || {
+                                    // SSTV pictures are published as they complete.
+                                    let mut sstv_decoder = sstv.then(SstvDecoder::new);
+                                    let mut pictures = 0;
+                                    let recording = recording::record(
+                                        samples,
+                                        params,
+                                        &directory,
+                                        &recording_name,
+                                        |audio| {
+                                            let Some(decoder) = &mut sstv_decoder else {
+                                                return;
+                                            };
+
+                                            for picture in decoder.feed(audio) {
+                                                pictures += 1;
+                                                if let Some(png) = sstv_product(&picture, &directory, &recording_name, pictures) {
+                                                    let _ = picture_tx.send(png);
+                                                }
+                                            }
+                                        },
+                                    );
+
+                                    // A picture cut off by the end of the pass is published as far
+                                    // as it got.
+                                    if let Some(picture) = sstv_decoder.and_then(SstvDecoder::finish) {
+                                        println!("[SSTV] Pass ended during a picture");
+                                        pictures += 1;
+                                        if let Some(png) = sstv_product(&picture, &directory, &recording_name, pictures) {
+                                            let _ = picture_tx.send(png);
+                                        }
+                                    }
+
+                                    recording
+                                });
+
+                                return match recording.await.ok()? {
+                                    Ok(recording) => recording.map(PassProduct::Recording),
+                                    Err(err) => {
+                                        eprintln!("[AUDIO] Failed to record: {}", err);
+                                        None
+                                    }
+                                };
+                            }
+                        }
+
+                        let mut frames = decode::frames(
+                            samples,
+                            downlink_demodulator_clone,
+                            deframer,
+                            demod_stats_clone,
+                            diagnostics_tap,
+                        );
+
+                        while let Some(frame) = frames.next().await {
+                            if let Some(payload) = frame.info {
+                                let metrics = pass_metrics_clone.lock().unwrap().latest();
+                                frame_tx.send((payload, metrics)).unwrap();
+                            }
+                        }
+
+                        None
+                    });
 
                     // MQTT publisher task
                     let client_for_mqtt = client_clone.clone();
                     let gs_id_for_mqtt = gs_id_clone.clone();
                     let mqtt_handle = tokio::spawn(async move {
-                        while let Some(payload) = frame_rx.recv().await {
-                            let msg = TelemetryMessage::new(gs_id_for_mqtt.clone(), Utc::now(), payload);
+                        let mut frames = 0;
+
+                        while let Some((payload, metrics)) = frame_rx.recv().await {
+                            let timestamp = Utc::now();
+                            let header = csp.and_then(|csp| csp.header(&payload));
+                            let split_topics = csp.is_some_and(|csp| csp.split_topics);
+                            let topic = match header {
+                                Some(header) if split_topics => format!(
+                                    "satellite/{}/telemetry/{}/{}",
+                                    satellite_id, header.source, header.source_port
+                                ),
+                                _ => format!("satellite/{}/telemetry", satellite_id),
+                            };
+                            let msg = TelemetryMessage::new(gs_id_for_mqtt.clone(), timestamp, payload);
 
                             client_for_mqtt
                                 .publish(
-                                    &format!("satellite/{}/telemetry", satellite_id),
+                                    &topic,
                                     QoS::AtLeastOnce,
                                     false,
                                     serde_json::to_string(&msg).unwrap().as_bytes(),
                                 )
                                 .await
                                 .unwrap();
+                            frames += 1;
+
+                            // CSP header of the frame, with the same timestamp as the telemetry
+                            // message.
+                            if let Some(header) = header {
+                                let msg = json!({
+                                    "timestamp": timestamp.to_rfc3339(),
+                                    "csp": CspReport::new(header),
+                                });
+
+                                client_for_mqtt
+                                    .publish(
+                                        &format!("satellite/{}/csp", satellite_id),
+                                        QoS::AtLeastOnce,
+                                        false,
+                                        msg.to_string().as_bytes(),
+                                    )
+                                    .await
+                                    .unwrap();
+                            }
+
+                            // Signal quality when the frame was received, with the same timestamp
+                            // as the telemetry message.
+                            if let Some(metrics) = metrics {
+                                let msg = json!({
+                                    "timestamp": timestamp.to_rfc3339(),
+                                    "metrics": SignalReport::new(metrics),
+                                });
+
+                                client_for_mqtt
+                                    .publish(
+                                        &format!("satellite/{}/signal", satellite_id),
+                                        QoS::AtLeastOnce,
+                                        false,
+                                        msg.to_string().as_bytes(),
+                                    )
+                                    .await
+                                    .unwrap();
+                            }
                         }
+
+                        frames
                     });
 
-                    let _ = tokio::join!(tracker_handle, sdr_handle, frame_handle, mqtt_handle);
+                    // SSTV publisher task
+                    let client_for_sstv = client_clone.clone();
+                    let job_id_for_sstv = job.id;
+                    let sstv_handle = tokio::spawn(async move {
+                        while let Some(png) = picture_rx.recv().await {
+                            let png = Arc::new(png);
+                            client_for_sstv
+                                .publish(
+                                    &format!("job/{}/sstv", job_id_for_sstv),
+                                    QoS::AtLeastOnce,
+                                    false,
+                                    png.to_vec(),
+                                )
+                                .await
+                                .unwrap();
+                            sstv_tx.send_replace(Some(png));
+                        }
+                    });
+
+                    // A channel of the shared SDR has no task of its own.
+                    let dedicated = sdr_handle.is_some();
+                    let sdr_handle = async {
+                        if let Some(handle) = sdr_handle {
+                            let _ = handle.await;
+                        }
+                    };
+
+                    let (_, _, product, frames, _, _) = tokio::join!(
+                        tracker_handle,
+                        sdr_handle,
+                        frame_handle,
+                        mqtt_handle,
+                        metrics_handle,
+                        sstv_handle
+                    );
+
+                    println!(
+                        "[SDR] Read {} blocks, dropped {} blocks ({} samples)",
+                        pass_stats.blocks_read(),
+                        pass_stats.blocks_dropped(),
+                        pass_stats.samples_dropped()
+                    );
+
+                    let report = PassReport::new(
+                        frames.unwrap_or(0),
+                        &pass_stats,
+                        &sdr_stats,
+                        stream_stats.as_deref(),
+                        &demod_stats,
+                        diagnostics_dropped.map(|dropped| dropped.load(Ordering::Relaxed)),
+                        &pass_metrics.lock().unwrap(),
+                    );
+                    println!("[PASS] {:?}", report);
+
+                    // A pass that ended without samples flowing failed, whatever it decoded before.
+                    let health = sdr_stats.health();
+                    let status = if health.is_receiving() {
+                        JobStatus::Completed
+                    } else {
+                        eprintln!("[PASS] SDR {} at the end of the pass", health.name());
+                        JobStatus::Error
+                    };
+
+                    if dedicated {
+                        sdr_stats_tx.send_replace(None);
+                    }
+
+                    // Topic and payload of the product of the pass.
+                    let product = match product.ok().flatten() {
+                        Some(PassProduct::Image(image)) => {
+                            apt_product(&image, job.id, &job.satellite_id, config_clone.apt.as_ref())
+                                .map(|png| ("apt", png))
+                        }
+                        Some(PassProduct::Recording(recording)) => {
+                            println!("[AUDIO] Recorded {:?}", recording);
+                            Some(("audio", serde_json::to_vec(&recording).unwrap()))
+                        }
+                        None => None,
+                    };
 
                     let client_for_completed = client_clone.clone();
                     let job_id_for_completed = job.id;
                     tokio::spawn(async move {
+                        if let Some((topic, payload)) = product {
+                            client_for_completed
+                                .publish(
+                                    &format!("job/{}/{}", job_id_for_completed, topic),
+                                    QoS::AtLeastOnce,
+                                    false,
+                                    payload,
+                                )
+                                .await
+                                .unwrap();
+                        }
+
+                        client_for_completed
+                            .publish(
+                                &format!("job/{}/report", job_id_for_completed),
+                                QoS::AtLeastOnce,
+                                true,
+                                serde_json::to_string(&report).unwrap().as_bytes(),
+                            )
+                            .await
+                            .unwrap();
+
                         client_for_completed
                             .publish(
                                 &format!("job/{}", job_id_for_completed),
                                 QoS::AtLeastOnce,
                                 true,
-                                serde_json::to_string(&JobStatus::Completed)
+                                serde_json::to_string(&status)
                                     .unwrap()
                                     .as_bytes(),
                             )
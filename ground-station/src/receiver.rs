@@ -0,0 +1,188 @@
+use sdr::{
+    OverflowPolicy, Sdr, SdrCommand, SdrStats,
+    block::SampleBlock,
+    channelizer::{ChannelHandle, Channelizer},
+    sdr_task,
+};
+use std::sync::{
+    Arc,
+    mpsc::{Receiver, sync_channel},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// Where a pass gets its samples from.
+pub enum PassReceiver {
+    /// The pass has the SDR to itself.
+    Dedicated {
+        control_tx: mpsc::Sender<SdrCommand>,
+        stats: Arc<SdrStats>,
+    },
+    /// The pass uses a channel of the station's shared wideband receiver.
+    Channel {
+        channel: ChannelHandle,
+        sdr_stats: Arc<SdrStats>,
+    },
+}
+
+impl PassReceiver {
+    /// Starts a dedicated SDR for a pass. The SDR stops when the returned receiver is dropped.
+    pub fn dedicated(
+        sdr: impl Sdr + Send + 'static,
+        queue_len: usize,
+    ) -> (Self, Receiver<SampleBlock>, JoinHandle<()>) {
+        let (control_tx, control_rx) = mpsc::channel(1);
+        let (samples_tx, samples_rx) = sync_channel(queue_len);
+        let stats = Arc::new(SdrStats::new());
+
+        let handle = tokio::spawn(sdr_task(
+            sdr,
+            control_rx,
+            samples_tx,
+            OverflowPolicy::DropNewest,
+            stats.clone(),
+        ));
+
+        (
+            PassReceiver::Dedicated { control_tx, stats },
+            samples_rx,
+            handle,
+        )
+    }
+
+    /// Tunes the receiver to `frequency` Hz.
+    pub async fn tune(&self, frequency: f64) {
+        match self {
+            PassReceiver::Dedicated { control_tx, .. } => {
+                // If the SDR task already finished there is nothing to tune.
+                let _ = control_tx.send(SdrCommand::SetRxFrequency(frequency)).await;
+            }
+            PassReceiver::Channel { channel, .. } => channel.set_frequency(frequency),
+        }
+    }
+
+    /// Counters of the samples received by the pass.
+    pub fn stats(&self) -> &Arc<SdrStats> {
+        match self {
+            PassReceiver::Dedicated { stats, .. } => stats,
+            PassReceiver::Channel { channel, .. } => channel.stats(),
+        }
+    }
+
+    /// Counters and health of the SDR. For a channel, those of the shared SDR since the station
+    /// started.
+    pub fn sdr_stats(&self) -> &Arc<SdrStats> {
+        match self {
+            PassReceiver::Dedicated { stats, .. } => stats,
+            PassReceiver::Channel { sdr_stats, .. } => sdr_stats,
+        }
+    }
+}
+
+/// Streams the samples of a pass, read on a blocking thread. The stream ends with the samples,
+/// and dropping it stops reading them.
+pub fn sample_stream(samples: Receiver<SampleBlock>) -> ReceiverStream<SampleBlock> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::task::spawn_blocking(move || {
+        for block in samples {
+            if tx.blocking_send(block).is_err() {
+                break;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// A wideband receiver shared by all passes, each one getting its own narrowband channel.
+pub struct SharedReceiver {
+    channelizer: Channelizer,
+    decimation: usize,
+    stats: Arc<SdrStats>,
+    /// Keeps the SDR task running for as long as the station runs.
+    _control_tx: mpsc::Sender<SdrCommand>,
+}
+
+impl SharedReceiver {
+    /// Starts `sdr` tuned to `center_frequency` Hz, feeding a channelizer.
+    pub fn start(
+        sdr: impl Sdr + Send + 'static,
+        center_frequency: f64,
+        decimation: usize,
+        queue_len: usize,
+    ) -> Self {
+        let (control_tx, control_rx) = mpsc::channel(1);
+        let (samples_tx, samples_rx) = sync_channel(queue_len);
+        let stats = Arc::new(SdrStats::new());
+
+        control_tx
+            .try_send(SdrCommand::SetRxFrequency(center_frequency))
+            .expect("new control channel can't be full");
+
+        tokio::spawn(sdr_task(
+            sdr,
+            control_rx,
+            samples_tx,
+            OverflowPolicy::DropNewest,
+            stats.clone(),
+        ));
+
+        Self {
+            channelizer: Channelizer::spawn(samples_rx),
+            decimation,
+            stats,
+            _control_tx: control_tx,
+        }
+    }
+
+    pub fn stats(&self) -> &Arc<SdrStats> {
+        &self.stats
+    }
+
+    /// Opens a channel for a pass at `frequency` Hz. The channel is closed when either the
+    /// returned `PassReceiver` or the samples receiver are dropped.
+    pub fn open_channel(
+        &self,
+        frequency: f64,
+        queue_len: usize,
+    ) -> (PassReceiver, Receiver<SampleBlock>) {
+        let (handle, samples_rx) =
+            self.channelizer
+                .add_channel(frequency, self.decimation, queue_len);
+
+        (
+            PassReceiver::Channel {
+                channel: handle,
+                sdr_stats: self.stats.clone(),
+            },
+            samples_rx,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdr::MockSdr;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dropping_a_dedicated_receiver_ends_its_samples() {
+        let (receiver, samples_rx, handle) =
+            PassReceiver::dedicated(MockSdr::new(48_000.0, 1200.0, 512), 4);
+        let stats = receiver.stats().clone();
+        assert!(samples_rx.recv_timeout(Duration::from_secs(1)).is_ok());
+
+        drop(receiver);
+
+        let samples = tokio::task::spawn_blocking(move || samples_rx.into_iter().count());
+        tokio::time::timeout(Duration::from_secs(5), async {
+            handle.await.unwrap();
+            samples.await.unwrap();
+        })
+        .await
+        .expect("the SDR kept streaming");
+        assert!(stats.blocks_read() > 0);
+    }
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use demod::fm::{AUDIO_RATE, FmDemodulator, FmParams};
+use sdr::block::SampleBlock;
+use serde::Serialize;
+use std::path::Path;
+
+/// FM audio recorded during a pass, published on `job/{id}/audio`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioRecording {
+    /// WAV file of the recording.
+    pub path: String,
+    /// Time of the first sample, in RFC 3339. Audio lost during the pass was recorded as silence,
+    /// so every sample is at `start` plus its offset in the file.
+    pub start: String,
+    /// Seconds.
+    pub duration: f64,
+}
+
+/// FM demodulates `samples` until they run out, recording the audio as a 16-bit
+/// mono WAV in `directory`. The file is named `{name}-{start}.wav`, with the UTC time of the
+/// first sample, or the time it arrived if the SDR only gives its own hardware time. Nothing is
+/// recorded if no samples arrive.
+///
+/// The audio is also passed to `tap` as it's demodulated, e.g. to decode it.
+pub fn record(
+    samples: impl Iterator<Item = SampleBlock>,
+    params: FmParams,
+    directory: &str,
+    name: &str,
+    mut tap: impl FnMut(&[f32]),
+) -> Result<Option<AudioRecording>, hound::Error> {
+    let mut demodulator = FmDemodulator::new(params).expect("validated at startup");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: AUDIO_RATE as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut recording = None;
+    let mut audio_samples = 0u64;
+
+    for block in samples {
+        let audio = demodulator.feed(&block);
+
+        let (writer, _) = match &mut recording {
+            Some(recording) => recording,
+            None => {
+                let start = block
+                    .timestamp
+                    .to_system_time()
+                    .map_or_else(Utc::now, DateTime::from);
+                let path = Path::new(directory).join(format!(
+                    "{}-{}.wav",
+                    name,
+                    start.format("%Y%m%dT%H%M%SZ")
+                ));
+                println!("[AUDIO] Recording to {}", path.display());
+
+                recording.insert((hound::WavWriter::create(&path, spec)?, (path, start)))
+            }
+        };
+
+        tap(&audio);
+        audio_samples += audio.len() as u64;
+        for sample in audio {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+    }
+
+    let Some((writer, (path, start))) = recording else {
+        return Ok(None);
+    };
+    writer.finalize()?;
+
+    Ok(Some(AudioRecording {
+        path: path.display().to_string(),
+        start: start.to_rfc3339(),
+        duration: audio_samples as f64 / AUDIO_RATE,
+    }))
+}
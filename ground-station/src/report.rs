@@ -0,0 +1,207 @@
+use demod::DemodulatorError;
+use framing::csp::CspHeader;
+use sdr::{
+    SdrStats,
+    metrics::{PassMetrics, SignalMetrics},
+    network::StreamStats,
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+/// Signal quality at a point of the pass.
+#[derive(Debug, Clone, Serialize)]
+pub struct SignalReport {
+    /// dB relative to full scale per Hz.
+    pub noise_floor_db: f64,
+    /// dB relative to full scale.
+    pub in_band_power_db: f64,
+    pub snr_db: f64,
+    pub ebn0_db: f64,
+    /// Hz, from the Doppler corrected downlink frequency.
+    pub frequency_offset: f64,
+    pub signal_present: bool,
+}
+
+impl SignalReport {
+    pub fn new(metrics: SignalMetrics) -> Self {
+        Self {
+            noise_floor_db: metrics.noise_floor_db,
+            in_band_power_db: metrics.in_band_power_db,
+            snr_db: metrics.snr_db,
+            ebn0_db: metrics.ebn0_db,
+            frequency_offset: metrics.frequency_offset,
+            signal_present: metrics.signal_present(),
+        }
+    }
+}
+
+/// CSP header of a telemetry frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct CspReport {
+    pub priority: u8,
+    pub source: u16,
+    pub destination: u16,
+    pub destination_port: u8,
+    pub source_port: u8,
+    pub fragmentation: bool,
+    pub hmac: bool,
+    pub xtea: bool,
+    pub rdp: bool,
+    pub crc32: bool,
+}
+
+impl CspReport {
+    pub fn new(header: CspHeader) -> Self {
+        Self {
+            priority: header.priority,
+            source: header.source,
+            destination: header.destination,
+            destination_port: header.destination_port,
+            source_port: header.source_port,
+            fragmentation: header.flags.fragmentation,
+            hmac: header.flags.hmac,
+            xtea: header.flags.xtea,
+            rdp: header.flags.rdp,
+            crc32: header.flags.crc32,
+        }
+    }
+}
+
+/// Summary of a completed pass, published on `job/{id}/report`.
+///
+/// A pass with signal but no frames points to a decoding problem; one without signal, to the
+/// satellite, the antenna or the tuning.
+#[derive(Debug, Clone, Serialize)]
+pub struct PassReport {
+    pub frames: u64,
+    pub blocks_read: u64,
+    pub blocks_dropped: u64,
+    /// State of the SDR when the pass ended.
+    pub sdr_health: &'static str,
+    /// Times samples stopped arriving. For a channel of the shared SDR, counted since the station
+    /// started, as are `reconnects` and `gaps`.
+    pub stalls: u64,
+    pub reconnects: u64,
+    /// Blocks that followed lost samples.
+    pub gaps: u64,
+    /// UDP datagrams lost, for a network SDR with sequence numbers. Counted since the SDR was
+    /// opened, as `gaps` are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packets_lost: Option<u64>,
+    /// UDP datagrams dropped for arriving after a later one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packets_out_of_order: Option<u64>,
+    /// Times the demodulator output no bits in time.
+    pub demod_timeouts: u64,
+    /// Errors that stopped the demodulator.
+    pub demod_errors: u64,
+    /// Symbols of diagnostics dropped for falling behind, when they were enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics_dropped: Option<u64>,
+    /// Frames decoded with each configuration of the demodulator, when several were tried.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub decoded_by: BTreeMap<String, u64>,
+    pub measurements: u64,
+    /// Measurements in which a signal was present.
+    pub measurements_with_signal: u64,
+    pub mean_noise_floor_db: Option<f64>,
+    /// Measurement with the highest SNR.
+    pub best: Option<SignalReport>,
+}
+
+impl PassReport {
+    /// `stats` are the counters of the samples received by the pass, `sdr_stats` those of the SDR
+    /// they came from, and `stream_stats` those of its UDP stream, if numbered.
+    pub fn new(
+        frames: u64,
+        stats: &SdrStats,
+        sdr_stats: &SdrStats,
+        stream_stats: Option<&StreamStats>,
+        demod_stats: &DemodulatorStats,
+        diagnostics_dropped: Option<u64>,
+        metrics: &PassMetrics,
+    ) -> Self {
+        Self {
+            frames,
+            blocks_read: stats.blocks_read(),
+            blocks_dropped: stats.blocks_dropped(),
+            sdr_health: sdr_stats.health().name(),
+            stalls: sdr_stats.stalls(),
+            reconnects: sdr_stats.reconnects(),
+            gaps: sdr_stats.gaps(),
+            packets_lost: stream_stats.map(StreamStats::packets_lost),
+            packets_out_of_order: stream_stats.map(StreamStats::packets_out_of_order),
+            demod_timeouts: demod_stats.timeouts.load(Ordering::Relaxed),
+            demod_errors: demod_stats.errors.load(Ordering::Relaxed),
+            diagnostics_dropped,
+            decoded_by: demod_stats.hypotheses().into_iter().collect(),
+            measurements: metrics.measurements(),
+            measurements_with_signal: metrics.with_signal(),
+            mean_noise_floor_db: metrics.mean_noise_floor_db(),
+            best: metrics.best().map(SignalReport::new),
+        }
+    }
+}
+
+/// Errors of the demodulator during a pass.
+#[derive(Debug, Default)]
+pub struct DemodulatorStats {
+    timeouts: AtomicU64,
+    errors: AtomicU64,
+    /// The latest output was a timeout.
+    quiet: AtomicBool,
+    /// Frames decoded with each hypothesis.
+    hypotheses: Mutex<BTreeMap<String, u64>>,
+}
+
+impl DemodulatorStats {
+    /// Passes the output of the demodulator through, bits or packets, counting and logging its
+    /// errors. Timeouts are only logged when they start, since they repeat for as long as there's
+    /// no signal.
+    pub fn record<T>(&self, output: Result<T, DemodulatorError>) -> Option<T> {
+        match output {
+            Ok(output) => {
+                self.quiet.store(false, Ordering::Relaxed);
+                Some(output)
+            }
+            Err(DemodulatorError::Timeout) => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+                if !self.quiet.swap(true, Ordering::Relaxed) {
+                    println!("[DEMOD] No bits from the demodulator, waiting");
+                }
+                None
+            }
+            Err(err) => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("[DEMOD] Demodulator failed: {:?}", err);
+                None
+            }
+        }
+    }
+
+    /// Counts a frame decoded with a hypothesis.
+    pub fn decoded_by(&self, hypothesis: &str) {
+        *self
+            .hypotheses
+            .lock()
+            .unwrap()
+            .entry(hypothesis.to_string())
+            .or_default() += 1;
+    }
+
+    /// Frames decoded with each hypothesis that decoded any, by name.
+    pub fn hypotheses(&self) -> Vec<(String, u64)> {
+        self.hypotheses
+            .lock()
+            .unwrap()
+            .clone()
+            .into_iter()
+            .collect()
+    }
+}
@@ -0,0 +1,213 @@
+pub use num_complex::Complex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Timestamp of the first sample in a [`SampleBlock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    /// Time reported by the device itself, relative to the device's own epoch.
+    Hardware(Duration),
+    /// Host monotonic clock, for sources that don't provide hardware time.
+    Monotonic(Instant),
+}
+
+impl Timestamp {
+    /// Returns the timestamp shifted forward by `offset`.
+    pub fn offset(&self, offset: Duration) -> Self {
+        match self {
+            Timestamp::Hardware(t) => Timestamp::Hardware(*t + offset),
+            Timestamp::Monotonic(t) => Timestamp::Monotonic(*t + offset),
+        }
+    }
+
+    /// Wall clock time of the timestamp. `None` for hardware time, whose epoch is unknown.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let Timestamp::Monotonic(t) = self else {
+            return None;
+        };
+
+        let (now, system_now) = (Instant::now(), SystemTime::now());
+        match now.checked_duration_since(*t) {
+            Some(ago) => system_now.checked_sub(ago),
+            None => system_now.checked_add(t.duration_since(now)),
+        }
+    }
+}
+
+/// A block of complex baseband samples, as produced by an [`Sdr`](crate::Sdr).
+///
+/// Along with the samples it carries everything needed to interpret them: the sample rate,
+/// the center frequency the receiver was tuned to and the time of the first sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleBlock {
+    pub samples: Vec<Complex<f32>>,
+    /// Sample rate, in Hz.
+    pub sample_rate: f64,
+    /// Center frequency of the receiver, in Hz.
+    pub center_frequency: f64,
+    /// Time of the first sample.
+    pub timestamp: Timestamp,
+    /// Samples were lost right before this block (e.g. an overflow, a stall or lost packets), so
+    /// it doesn't continue the previous one.
+    pub discontinuity: bool,
+}
+
+impl SampleBlock {
+    pub fn new(
+        samples: Vec<Complex<f32>>,
+        sample_rate: f64,
+        center_frequency: f64,
+        timestamp: Timestamp,
+    ) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            center_frequency,
+            timestamp,
+            discontinuity: false,
+        }
+    }
+
+    /// Builds a block from interleaved I/Q values (`[i0, q0, i1, q1, ...]`).
+    ///
+    /// A trailing unpaired value is ignored.
+    pub fn from_interleaved(
+        interleaved: &[f64],
+        sample_rate: f64,
+        center_frequency: f64,
+        timestamp: Timestamp,
+    ) -> Self {
+        let samples = interleaved
+            .chunks_exact(2)
+            .map(|iq| Complex::new(iq[0] as f32, iq[1] as f32))
+            .collect();
+
+        Self::new(samples, sample_rate, center_frequency, timestamp)
+    }
+
+    /// Converts the samples to interleaved I/Q values (`[i0, q0, i1, q1, ...]`), which is what
+    /// the consumers that predate `SampleBlock` expect.
+    pub fn to_interleaved(&self) -> Vec<f64> {
+        self.samples
+            .iter()
+            .flat_map(|s| [s.re as f64, s.im as f64])
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Time spanned by the block.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.samples.len() as f64 / self.sample_rate)
+    }
+
+    /// Time of the sample at `index`.
+    pub fn timestamp_of(&self, index: usize) -> Timestamp {
+        self.timestamp
+            .offset(Duration::from_secs_f64(index as f64 / self.sample_rate))
+    }
+}
+
+impl From<SampleBlock> for Vec<f64> {
+    fn from(block: SampleBlock) -> Self {
+        block.to_interleaved()
+    }
+}
+
+/// Iterator adapter that turns a stream of [`SampleBlock`]s into interleaved `Vec<f64>` batches.
+pub struct Interleaved<I> {
+    inner: I,
+}
+
+impl<I> Iterator for Interleaved<I>
+where
+    I: Iterator<Item = SampleBlock>,
+{
+    type Item = Vec<f64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(Vec::from)
+    }
+}
+
+/// Extension trait to adapt [`SampleBlock`] iterators for consumers of interleaved `Vec<f64>`.
+pub trait SampleBlockIteratorExt: Iterator<Item = SampleBlock> + Sized {
+    fn interleaved(self) -> Interleaved<Self> {
+        Interleaved { inner: self }
+    }
+}
+
+impl<I> SampleBlockIteratorExt for I where I: Iterator<Item = SampleBlock> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaved_roundtrip() {
+        let interleaved = vec![1.0, -1.0, 0.5, 0.25, 0.0, 2.0];
+        let block = SampleBlock::from_interleaved(
+            &interleaved,
+            48_000.0,
+            435e6,
+            Timestamp::Hardware(Duration::ZERO),
+        );
+
+        assert_eq!(block.len(), 3);
+        assert_eq!(block.samples[1], Complex::new(0.5, 0.25));
+        assert_eq!(block.to_interleaved(), interleaved);
+    }
+
+    #[test]
+    fn timestamp_of_sample() {
+        let block = SampleBlock::new(
+            vec![Complex::new(0.0, 0.0); 48_000],
+            48_000.0,
+            435e6,
+            Timestamp::Hardware(Duration::from_secs(10)),
+        );
+
+        assert_eq!(block.duration(), Duration::from_secs(1));
+        assert_eq!(
+            block.timestamp_of(24_000),
+            Timestamp::Hardware(Duration::from_millis(10_500))
+        );
+    }
+
+    #[test]
+    fn wall_clock_time() {
+        let ago = Duration::from_secs(5);
+        let time = Timestamp::Monotonic(Instant::now() - ago)
+            .to_system_time()
+            .unwrap();
+        let error = SystemTime::now()
+            .duration_since(time)
+            .unwrap()
+            .abs_diff(ago);
+
+        assert!(error < Duration::from_secs(1), "{:?}", error);
+        assert_eq!(Timestamp::Hardware(ago).to_system_time(), None);
+    }
+
+    #[test]
+    fn iterator_adapter() {
+        let blocks = vec![
+            SampleBlock::from_interleaved(
+                &[1.0, 2.0],
+                1.0,
+                0.0,
+                Timestamp::Hardware(Duration::ZERO),
+            );
+            2
+        ];
+
+        let batches: Vec<Vec<f64>> = blocks.into_iter().interleaved().collect();
+
+        assert_eq!(batches, vec![vec![1.0, 2.0], vec![1.0, 2.0]]);
+    }
+}
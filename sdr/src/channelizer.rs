@@ -0,0 +1,354 @@
+//! Splits one wideband sample stream into several narrowband channels.
+//!
+//! Each channel is mixed down from its own (Doppler corrected) frequency, low-pass filtered and
+//! decimated, so several satellites inside the receiver's bandwidth can be worked at once, each
+//! with its own demodulator.
+
+use crate::{
+    SdrStats,
+    block::{Complex, SampleBlock},
+};
+use std::{
+    f64::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread,
+};
+
+/// Taps of the channel filter per unit of decimation.
+const TAPS_PER_DECIMATION: usize = 8;
+/// Cutoff of the channel filter, as a fraction of the output Nyquist frequency.
+const PASSBAND: f64 = 0.8;
+
+/// Handle to a running channelizer, used to open new channels.
+#[derive(Clone)]
+pub struct Channelizer {
+    new_channels: mpsc::Sender<Channel>,
+}
+
+impl Channelizer {
+    /// Starts a channelizer on a dedicated thread, fed with the wideband blocks from `input`.
+    ///
+    /// The thread finishes when `input` is disconnected.
+    pub fn spawn(input: Receiver<SampleBlock>) -> Self {
+        let (new_channels, new_channels_rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("channelizer".to_string())
+            .spawn(move || run(input, new_channels_rx))
+            .expect("failed to spawn channelizer thread");
+
+        Self { new_channels }
+    }
+
+    /// Opens a channel centered at `frequency` Hz whose sample rate is the wideband rate divided
+    /// by `decimation`.
+    ///
+    /// Up to `queue_len` output blocks are buffered; further blocks are dropped and counted in the
+    /// handle's stats. The channel is closed when either the handle or the receiver is dropped.
+    pub fn add_channel(
+        &self,
+        frequency: f64,
+        decimation: usize,
+        queue_len: usize,
+    ) -> (ChannelHandle, Receiver<SampleBlock>) {
+        let (tx, rx) = mpsc::sync_channel(queue_len);
+        let channel = Channel::new(frequency, decimation, tx);
+        let handle = ChannelHandle {
+            frequency: channel.frequency.clone(),
+            stats: channel.stats.clone(),
+        };
+
+        // If the channelizer is gone, the receiver is disconnected right away.
+        let _ = self.new_channels.send(channel);
+
+        (handle, rx)
+    }
+}
+
+/// Controls a channel opened with [`Channelizer::add_channel`].
+pub struct ChannelHandle {
+    frequency: Arc<AtomicU64>,
+    stats: Arc<SdrStats>,
+}
+
+impl ChannelHandle {
+    /// Retunes the channel, e.g. to follow the Doppler shift of a satellite.
+    pub fn set_frequency(&self, frequency: f64) {
+        self.frequency.store(frequency.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn frequency(&self) -> f64 {
+        f64::from_bits(self.frequency.load(Ordering::Relaxed))
+    }
+
+    pub fn stats(&self) -> &Arc<SdrStats> {
+        &self.stats
+    }
+}
+
+fn run(input: Receiver<SampleBlock>, new_channels: Receiver<Channel>) {
+    println!("[CHANNELIZER] Start");
+
+    let mut channels: Vec<Channel> = Vec::new();
+
+    for block in input {
+        while let Ok(channel) = new_channels.try_recv() {
+            channels.push(channel);
+        }
+
+        channels.retain_mut(|channel| channel.feed(&block));
+    }
+
+    println!("[CHANNELIZER] End");
+}
+
+/// A single narrowband channel.
+pub(crate) struct Channel {
+    /// Center frequency, in Hz, stored as `f64` bits so the handle can update it.
+    frequency: Arc<AtomicU64>,
+    decimation: usize,
+    taps: Vec<f32>,
+    nco_phase: f64,
+    /// Mixed samples that haven't been fully consumed by the filter yet.
+    history: Vec<Complex<f32>>,
+    /// Start, in `history`, of the next filter window.
+    next_window: usize,
+    /// An input discontinuity not yet passed on to an output block.
+    discontinuity: bool,
+    tx: SyncSender<SampleBlock>,
+    stats: Arc<SdrStats>,
+}
+
+impl Channel {
+    pub(crate) fn new(frequency: f64, decimation: usize, tx: SyncSender<SampleBlock>) -> Self {
+        let decimation = decimation.max(1);
+
+        Self {
+            frequency: Arc::new(AtomicU64::new(frequency.to_bits())),
+            decimation,
+            taps: lowpass_taps(
+                TAPS_PER_DECIMATION * decimation + 1,
+                PASSBAND * 0.5 / decimation as f64,
+            ),
+            nco_phase: 0.0,
+            history: Vec::new(),
+            next_window: 0,
+            discontinuity: false,
+            tx,
+            stats: Arc::new(SdrStats::new()),
+        }
+    }
+
+    /// Processes a wideband block and sends the result. Returns `false` once the channel has been
+    /// closed.
+    fn feed(&mut self, block: &SampleBlock) -> bool {
+        // Only the channelizer holds the frequency: the handle was dropped.
+        if Arc::strong_count(&self.frequency) == 1 {
+            return false;
+        }
+
+        let Some(out) = self.process(block) else {
+            return true;
+        };
+        self.stats.blocks_read.fetch_add(1, Ordering::Relaxed);
+
+        match self.tx.try_send(out) {
+            Ok(()) => true,
+            Err(TrySendError::Full(out)) => {
+                self.stats.blocks_dropped.fetch_add(1, Ordering::Relaxed);
+                self.stats
+                    .samples_dropped
+                    .fetch_add(out.len() as u64, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    /// Mixes, filters and decimates a wideband block. Returns `None` if the block didn't complete
+    /// any output sample.
+    pub(crate) fn process(&mut self, block: &SampleBlock) -> Option<SampleBlock> {
+        let frequency = f64::from_bits(self.frequency.load(Ordering::Relaxed));
+        let phase_inc = -2.0 * PI * (frequency - block.center_frequency) / block.sample_rate;
+
+        self.discontinuity |= block.discontinuity;
+
+        let carried = self.history.len();
+        self.history.extend(block.samples.iter().map(|&sample| {
+            let mixed = sample * Complex::from_polar(1.0, self.nco_phase as f32);
+            self.nco_phase = (self.nco_phase + phase_inc).rem_euclid(2.0 * PI);
+            mixed
+        }));
+
+        let n_taps = self.taps.len();
+        let first_window = self.next_window;
+        let mut start = self.next_window;
+        let mut out = Vec::new();
+
+        while start + n_taps <= self.history.len() {
+            let window = &self.history[start..start + n_taps];
+            out.push(
+                window
+                    .iter()
+                    .zip(self.taps.iter().rev())
+                    .map(|(sample, tap)| sample * tap)
+                    .sum(),
+            );
+            start += self.decimation;
+        }
+
+        let consumed = start.min(self.history.len());
+        self.history.drain(..consumed);
+        self.next_window = start - consumed;
+
+        if out.is_empty() {
+            return None;
+        }
+
+        // The first output is stamped with the time of the newest input sample it depends on,
+        // which always belongs to this block.
+        let newest = first_window + n_taps - 1 - carried;
+
+        Some(SampleBlock {
+            discontinuity: std::mem::take(&mut self.discontinuity),
+            ..SampleBlock::new(
+                out,
+                block.sample_rate / self.decimation as f64,
+                frequency,
+                block.timestamp_of(newest),
+            )
+        })
+    }
+}
+
+/// Hamming windowed sinc low-pass filter with unit DC gain. `cutoff` is relative to the sample
+/// rate.
+fn lowpass_taps(len: usize, cutoff: f64) -> Vec<f32> {
+    let mid = (len - 1) as f64 / 2.0;
+
+    let taps: Vec<f64> = (0..len)
+        .map(|n| {
+            let x = n as f64 - mid;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let window = 0.54 - 0.46 * (2.0 * PI * n as f64 / (len - 1).max(1) as f64).cos();
+            sinc * window
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+
+    taps.into_iter().map(|t| (t / sum) as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Timestamp;
+    use std::time::Duration;
+
+    const SAMPLE_RATE: f64 = 480_000.0;
+    const CENTER: f64 = 435e6;
+
+    fn tone(offset: f64, len: usize) -> SampleBlock {
+        let samples = (0..len)
+            .map(|n| Complex::from_polar(1.0, (2.0 * PI * offset * n as f64 / SAMPLE_RATE) as f32))
+            .collect();
+
+        SampleBlock::new(
+            samples,
+            SAMPLE_RATE,
+            CENTER,
+            Timestamp::Hardware(Duration::ZERO),
+        )
+    }
+
+    fn power(samples: &[Complex<f32>]) -> f64 {
+        samples.iter().map(|s| s.norm_sqr() as f64).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn decimates_and_retags_blocks() {
+        let (tx, _rx) = mpsc::sync_channel(1);
+        let mut channel = Channel::new(CENTER + 50_000.0, 10, tx);
+
+        let out = channel.process(&tone(50_000.0, 4800)).unwrap();
+
+        assert_eq!(out.sample_rate, 48_000.0);
+        assert_eq!(out.center_frequency, CENTER + 50_000.0);
+        // The first output needs a full filter window.
+        assert_eq!(out.len(), (4800 - 81) / 10 + 1);
+        assert_eq!(
+            out.timestamp,
+            Timestamp::Hardware(Duration::from_secs_f64(80.0 / SAMPLE_RATE))
+        );
+    }
+
+    #[test]
+    fn keeps_in_channel_signal_and_rejects_others() {
+        let (tx, _rx) = mpsc::sync_channel(1);
+        let mut in_channel = Channel::new(CENTER + 100_000.0, 10, tx.clone());
+        let mut out_of_channel = Channel::new(CENTER - 100_000.0, 10, tx);
+
+        let block = tone(100_000.0, 48_000);
+        let wanted = in_channel.process(&block).unwrap();
+        let unwanted = out_of_channel.process(&block).unwrap();
+
+        // Skip the filter's transient.
+        assert!(power(&wanted.samples[10..]) > 0.95);
+        assert!(power(&unwanted.samples[10..]) < 1e-3);
+
+        // The tone ends up at DC.
+        let n = wanted.len() / 2;
+        let freq = (wanted.samples[n + 1] * wanted.samples[n].conj()).arg();
+        assert!(freq.abs() < 1e-3);
+    }
+
+    #[test]
+    fn output_is_continuous_across_blocks() {
+        let (tx, _rx) = mpsc::sync_channel(1);
+        let mut whole = Channel::new(CENTER + 1_000.0, 10, tx.clone());
+        let mut split = Channel::new(CENTER + 1_000.0, 10, tx);
+
+        let block = tone(3_000.0, 1000);
+        let expected = whole.process(&block).unwrap().samples;
+
+        let mut first = block.clone();
+        first.samples.truncate(333);
+        let mut second = block.clone();
+        second.samples.drain(..333);
+
+        let mut got = split.process(&first).unwrap().samples;
+        got.extend(split.process(&second).unwrap().samples);
+
+        assert_eq!(got.len(), expected.len());
+        for (a, b) in got.iter().zip(expected.iter()) {
+            assert!((a - b).norm() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn channel_closes_with_its_handle() {
+        let (wide_tx, wide_rx) = mpsc::sync_channel(4);
+        let channelizer = Channelizer::spawn(wide_rx);
+
+        let (handle, rx) = channelizer.add_channel(CENTER, 10, 4);
+        handle.set_frequency(CENTER + 1_000.0);
+        assert_eq!(handle.frequency(), CENTER + 1_000.0);
+
+        wide_tx.send(tone(0.0, 4800)).unwrap();
+        let out = rx.recv().unwrap();
+        assert_eq!(out.center_frequency, CENTER + 1_000.0);
+
+        drop(handle);
+        wide_tx.send(tone(0.0, 4800)).unwrap();
+        // The pending block may or may not have been processed before the handle was dropped.
+        while rx.recv().is_ok() {}
+    }
+}
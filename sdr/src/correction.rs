@@ -0,0 +1,237 @@
+//! Front-end corrections for the impairments of cheap SDRs: DC offset, IQ imbalance and a static
+//! frequency error of the local oscillator.
+
+use crate::{
+    Sdr,
+    block::{Complex, SampleBlock},
+};
+use std::f64::consts::PI;
+
+/// Time constant of the DC offset estimate, in seconds.
+const DC_TIME_CONSTANT: f64 = 0.1;
+/// Time constant of the IQ imbalance estimate, in seconds.
+const IQ_TIME_CONSTANT: f64 = 1.0;
+
+/// Which corrections to apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Corrections {
+    /// Remove the DC offset, i.e. the spike at the center of the spectrum.
+    pub dc_block: bool,
+    /// Estimate and correct the amplitude and phase imbalance between I and Q, which shows up as
+    /// a mirror image of every signal.
+    pub iq_balance: bool,
+    /// Frequency error of the local oscillator, in parts per million. A positive value means the
+    /// oscillator runs fast, so signals show up lower than they are.
+    pub ppm: f64,
+    /// Additional static frequency error, in Hz, with the same sign as `ppm`.
+    pub frequency_offset: f64,
+}
+
+/// Applies [`Corrections`] to a stream of blocks.
+pub struct Corrector {
+    corrections: Corrections,
+    dc: Complex<f32>,
+    /// Smoothed `E[I²]`, `E[Q²]` and `E[IQ]`.
+    iq_stats: Option<(f64, f64, f64)>,
+    nco_phase: f64,
+}
+
+impl Corrector {
+    pub fn new(corrections: Corrections) -> Self {
+        Self {
+            corrections,
+            dc: Complex::new(0.0, 0.0),
+            iq_stats: None,
+            nco_phase: 0.0,
+        }
+    }
+
+    pub fn apply(&mut self, block: &mut SampleBlock) {
+        if self.corrections.dc_block {
+            self.block_dc(block);
+        }
+
+        if self.corrections.iq_balance {
+            self.balance_iq(block);
+        }
+
+        // The oscillator error scales with the tuned frequency.
+        let error = self.corrections.ppm * 1e-6 * block.center_frequency
+            + self.corrections.frequency_offset;
+        if error != 0.0 {
+            let phase_inc = 2.0 * PI * error / block.sample_rate;
+
+            for sample in block.samples.iter_mut() {
+                *sample *= Complex::from_polar(1.0, self.nco_phase as f32);
+                self.nco_phase = (self.nco_phase + phase_inc).rem_euclid(2.0 * PI);
+            }
+        }
+    }
+
+    fn block_dc(&mut self, block: &mut SampleBlock) {
+        let alpha = (1.0 - (-1.0 / (DC_TIME_CONSTANT * block.sample_rate)).exp()) as f32;
+
+        for sample in block.samples.iter_mut() {
+            self.dc += (*sample - self.dc) * alpha;
+            *sample -= self.dc;
+        }
+    }
+
+    /// Blind estimation: for a balanced signal `E[I²] = E[Q²]` and `E[IQ] = 0`. With a gain
+    /// mismatch `g` and a phase error `φ` on Q, `E[Q²] / E[I²] = g²` and `E[IQ] / √(E[I²] E[Q²]) =
+    /// sin φ`.
+    fn balance_iq(&mut self, block: &mut SampleBlock) {
+        if block.is_empty() {
+            return;
+        }
+
+        let n = block.len() as f64;
+        let (ii, qq, iq) = block
+            .samples
+            .iter()
+            .fold((0.0, 0.0, 0.0), |(ii, qq, iq), s| {
+                let (i, q) = (s.re as f64, s.im as f64);
+                (ii + i * i / n, qq + q * q / n, iq + i * q / n)
+            });
+
+        let weight = 1.0 - (-n / (IQ_TIME_CONSTANT * block.sample_rate)).exp();
+        let (ii, qq, iq) = match self.iq_stats {
+            Some((ii_avg, qq_avg, iq_avg)) => (
+                ii_avg + (ii - ii_avg) * weight,
+                qq_avg + (qq - qq_avg) * weight,
+                iq_avg + (iq - iq_avg) * weight,
+            ),
+            None => (ii, qq, iq),
+        };
+        self.iq_stats = Some((ii, qq, iq));
+
+        if ii <= 0.0 || qq <= 0.0 {
+            return;
+        }
+
+        let gain = (qq / ii).sqrt();
+        let sin_phi = (iq / (ii * qq).sqrt()).clamp(-0.99, 0.99);
+        let cos_phi = (1.0 - sin_phi * sin_phi).sqrt();
+
+        for sample in block.samples.iter_mut() {
+            let (i, q) = (sample.re as f64, sample.im as f64);
+            sample.im = ((q / gain - i * sin_phi) / cos_phi) as f32;
+        }
+    }
+}
+
+/// Wraps an [`Sdr`], applying front-end corrections to the samples it reads.
+pub struct CorrectedSdr<S> {
+    sdr: S,
+    corrector: Corrector,
+}
+
+impl<S: Sdr> CorrectedSdr<S> {
+    pub fn new(sdr: S, corrections: Corrections) -> Self {
+        Self {
+            sdr,
+            corrector: Corrector::new(corrections),
+        }
+    }
+}
+
+impl<S: Sdr> Sdr for CorrectedSdr<S> {
+    fn set_rx_frequency(&mut self, frequency: f64) {
+        self.sdr.set_rx_frequency(frequency);
+    }
+
+    fn read_samples(&mut self) -> Option<SampleBlock> {
+        let mut block = self.sdr.read_samples()?;
+        self.corrector.apply(&mut block);
+
+        Some(block)
+    }
+
+    fn reconnect(&mut self) -> bool {
+        self.sdr.reconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Timestamp;
+    use std::time::Duration;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    /// A tone at `freq` Hz as received with a DC offset, Q gain `gain` and Q phase error `phi`.
+    fn impaired_tone(freq: f64, dc: Complex<f32>, gain: f64, phi: f64) -> SampleBlock {
+        let samples = (0..48_000)
+            .map(|n| {
+                let theta = 2.0 * PI * freq * n as f64 / SAMPLE_RATE;
+                Complex::new(theta.cos() as f32, (gain * (theta + phi).sin()) as f32) + dc
+            })
+            .collect();
+
+        SampleBlock::new(
+            samples,
+            SAMPLE_RATE,
+            435e6,
+            Timestamp::Hardware(Duration::ZERO),
+        )
+    }
+
+    /// Power at `freq` Hz over the last half of the samples, once the estimates settled.
+    fn power_at(block: &SampleBlock, freq: f64) -> f64 {
+        let half = block.len() / 2;
+        let sum: Complex<f64> = block.samples[half..]
+            .iter()
+            .enumerate()
+            .map(|(n, s)| {
+                let phase = -2.0 * PI * freq * n as f64 / SAMPLE_RATE;
+                Complex::new(s.re as f64, s.im as f64) * Complex::from_polar(1.0, phase)
+            })
+            .sum();
+
+        (sum / (block.len() - half) as f64).norm_sqr()
+    }
+
+    #[test]
+    fn removes_dc_offset() {
+        let mut block = impaired_tone(1000.0, Complex::new(0.3, -0.2), 1.0, 0.0);
+        Corrector::new(Corrections {
+            dc_block: true,
+            ..Default::default()
+        })
+        .apply(&mut block);
+
+        assert!(power_at(&block, 0.0) < 1e-4);
+        assert!((power_at(&block, 1000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn suppresses_iq_image() {
+        let mut block = impaired_tone(1000.0, Complex::new(0.0, 0.0), 1.2, 10f64.to_radians());
+        let image_before = power_at(&block, -1000.0);
+
+        Corrector::new(Corrections {
+            iq_balance: true,
+            ..Default::default()
+        })
+        .apply(&mut block);
+
+        assert!(image_before > 1e-2);
+        assert!(power_at(&block, -1000.0) < 1e-5);
+        assert!((power_at(&block, 1000.0) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn compensates_oscillator_error() {
+        // 2 ppm at 435 MHz is 870 Hz, plus a 30 Hz offset.
+        let mut block = impaired_tone(-900.0, Complex::new(0.0, 0.0), 1.0, 0.0);
+        Corrector::new(Corrections {
+            ppm: 2.0,
+            frequency_offset: 30.0,
+            ..Default::default()
+        })
+        .apply(&mut block);
+
+        assert!((power_at(&block, 0.0) - 1.0).abs() < 0.01);
+    }
+}
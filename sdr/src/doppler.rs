@@ -0,0 +1,82 @@
+//! Doppler profiles for simulated downlinks.
+//!
+//! The simulator doesn't propagate orbits: a satellite's profile follows the range rate a tracker
+//! reports for it, the same one receivers are tuned from.
+
+use std::{fmt, sync::Arc, time::SystemTime};
+
+/// Speed of light, in m/s.
+const C: f64 = 299_792_458.0;
+
+/// Range rate of a satellite at some instant, in m/s, positive when it moves away, or `None`
+/// while it is below the horizon.
+pub type RangeRate = Arc<dyn Fn(SystemTime) -> Option<f64> + Send + Sync>;
+
+/// Doppler shift, in Hz, seen on a carrier transmitted at `carrier` Hz by a satellite whose
+/// range changes at `range_rate` m/s.
+pub fn doppler_shift(carrier: f64, range_rate: f64) -> f64 {
+    -carrier * range_rate / C
+}
+
+/// How the frequency of a simulated downlink evolves over time.
+#[derive(Clone)]
+pub enum DopplerProfile {
+    /// No Doppler shift, the satellite is always visible.
+    None,
+    /// A fixed frequency offset, in Hz. The satellite is always visible.
+    Constant(f64),
+    /// Doppler of a tracked satellite, only visible above the horizon.
+    Tracked(RangeRate),
+}
+
+impl fmt::Debug for DopplerProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DopplerProfile::None => write!(f, "None"),
+            DopplerProfile::Constant(offset) => f.debug_tuple("Constant").field(offset).finish(),
+            DopplerProfile::Tracked(_) => write!(f, "Tracked"),
+        }
+    }
+}
+
+impl DopplerProfile {
+    /// Frequency offset at `time` for a carrier transmitted at `carrier` Hz, or `None` if the
+    /// satellite is below the horizon.
+    pub fn offset(&self, carrier: f64, time: SystemTime) -> Option<f64> {
+        match self {
+            DopplerProfile::None => Some(0.0),
+            DopplerProfile::Constant(offset) => Some(*offset),
+            DopplerProfile::Tracked(range_rate) => {
+                range_rate(time).map(|range_rate| doppler_shift(carrier, range_rate))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_profiles_are_always_visible() {
+        let now = SystemTime::now();
+
+        assert_eq!(DopplerProfile::None.offset(437e6, now), Some(0.0));
+        assert_eq!(
+            DopplerProfile::Constant(-500.0).offset(437e6, now),
+            Some(-500.0)
+        );
+    }
+
+    #[test]
+    fn tracked_profiles_follow_the_range_rate() {
+        let now = SystemTime::now();
+        let receding = DopplerProfile::Tracked(Arc::new(|_| Some(7_000.0)));
+        let set = DopplerProfile::Tracked(Arc::new(|_| None));
+
+        // 7 km/s away lowers a 437 MHz carrier by about 10.2 kHz.
+        let offset = receding.offset(437e6, now).unwrap();
+        assert!((offset + 10_204.0).abs() < 1.0, "{}", offset);
+        assert_eq!(set.offset(437e6, now), None);
+    }
+}
@@ -1,80 +1,225 @@
-use std::f64::consts::PI;
+pub mod block;
+pub mod channelizer;
+pub mod correction;
+pub mod doppler;
+pub mod metrics;
+pub mod modulation;
+pub mod network;
+pub mod sim;
+pub mod spectrum;
+
+use block::{Complex, SampleBlock, Timestamp};
+use sim::{SimulatedSignal, Simulator};
+use std::{
+    f64::consts::PI,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, AtomicU64, Ordering},
+        mpsc::{SyncSender, TrySendError},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::mpsc::error::TryRecvError;
 
 pub trait Sdr {
     fn set_rx_frequency(&mut self, frequency: f64);
-    fn read_samples(&mut self) -> Option<Vec<f64>>;
+    /// Reads the next block. `None` if no samples arrived in time or the device failed.
+    fn read_samples(&mut self) -> Option<SampleBlock>;
+    /// Reopens the device after reads kept failing. Returns whether it's available again.
+    ///
+    /// Devices that can't be reopened just report themselves as available.
+    fn reconnect(&mut self) -> bool {
+        true
+    }
 }
 
-/// Mock SDR that generates a synthetic sine wave in baseband IQ
+/// Mock SDR, paced at its sample rate like real hardware.
+///
+/// It either generates a synthetic sine wave in baseband IQ, or simulates the downlink of a
+/// satellite (see [`SimulatedSignal`]).
 pub struct MockSdr {
     sample_rate: f64,
-    freq: f64,
-    phase: f64,
+    center_frequency: f64,
+    source: MockSource,
     block_size: usize,
+    start: Instant,
+    start_time: SystemTime,
+    samples_read: u64,
+}
+
+enum MockSource {
+    /// Tone at `freq` Hz relative to the center frequency.
+    Tone {
+        freq: f64,
+        phase: f64,
+    },
+    Simulation(Box<Simulator>),
 }
 
 impl MockSdr {
+    /// Creates a mock SDR that generates a tone `freq` Hz away from the center frequency.
     pub fn new(sample_rate: f64, freq: f64, block_size: usize) -> Self {
+        Self::with_source(
+            sample_rate,
+            block_size,
+            MockSource::Tone { freq, phase: 0.0 },
+        )
+    }
+
+    /// Creates a mock SDR that receives a simulated satellite downlink.
+    ///
+    /// The signal is only received when the SDR is tuned close enough to its carrier (see
+    /// [`Sdr::set_rx_frequency`]) and the satellite is visible.
+    pub fn simulating(sample_rate: f64, block_size: usize, signal: SimulatedSignal) -> Self {
+        Self::with_source(
+            sample_rate,
+            block_size,
+            MockSource::Simulation(Box::new(Simulator::new(signal))),
+        )
+    }
+
+    fn with_source(sample_rate: f64, block_size: usize, source: MockSource) -> Self {
         Self {
             sample_rate,
-            freq,
-            phase: 0.0,
+            center_frequency: 0.0,
+            source,
             block_size,
+            start: Instant::now(),
+            start_time: SystemTime::now(),
+            samples_read: 0,
         }
     }
 }
 
 impl Sdr for MockSdr {
+    /// Tunes the center frequency, which is what the blocks report and what a simulated signal is
+    /// received relative to. The tone stays `freq` Hz away from it.
     fn set_rx_frequency(&mut self, freq_hz: f64) {
         println!("[SDR] Setting frequency to {}", freq_hz);
 
-        self.freq = freq_hz;
+        self.center_frequency = freq_hz;
     }
 
-    fn read_samples(&mut self) -> Option<Vec<f64>> {
-        let mut out = Vec::with_capacity(2 * self.block_size);
-        let phase_inc = 2.0 * PI * self.freq / self.sample_rate;
+    fn read_samples(&mut self) -> Option<SampleBlock> {
+        // Samples are generated on demand, so time them by count rather than by the wall clock.
+        let elapsed = Duration::from_secs_f64(self.samples_read as f64 / self.sample_rate);
 
-        for _ in 0..self.block_size {
-            let i = self.phase.cos();
-            let q = self.phase.sin();
-            out.push(i);
-            out.push(q);
-            self.phase = (self.phase + phase_inc) % (2.0 * PI);
-        }
+        let out = match &mut self.source {
+            MockSource::Tone { freq, phase } => {
+                let phase_inc = 2.0 * PI * *freq / self.sample_rate;
+
+                (0..self.block_size)
+                    .map(|_| {
+                        let sample = Complex::new(phase.cos() as f32, phase.sin() as f32);
+                        *phase = (*phase + phase_inc) % (2.0 * PI);
+                        sample
+                    })
+                    .collect()
+            }
+            MockSource::Simulation(simulator) => simulator.generate(
+                self.block_size,
+                self.sample_rate,
+                self.center_frequency,
+                self.start_time + elapsed,
+            ),
+        };
+
+        let timestamp = Timestamp::Monotonic(self.start).offset(elapsed);
+        self.samples_read += self.block_size as u64;
+
+        // Deliver samples at the configured rate, like real hardware would.
+        let ready_at =
+            self.start + Duration::from_secs_f64(self.samples_read as f64 / self.sample_rate);
+        thread::sleep(ready_at.saturating_duration_since(Instant::now()));
 
         // println!("[SDR] Pushing samples");
 
-        Some(out)
+        Some(SampleBlock::new(
+            out,
+            self.sample_rate,
+            self.center_frequency,
+            timestamp,
+        ))
     }
 }
 
+/// Receives complex samples (native endian `f32` I/Q pairs, as sent by GNU Radio's ZMQ PUB Sink)
+/// from a ZMQ publisher.
 pub struct ZmqMockSdr {
+    endpoint: String,
     sub_sock: zmq::Socket,
+    sample_rate: f64,
+    center_frequency: f64,
 }
 
 impl ZmqMockSdr {
-    pub fn new(endpoint: String) -> Self {
+    pub fn new(endpoint: String, sample_rate: f64) -> Self {
+        let sub_sock = Self::connect(&endpoint).unwrap();
+
+        Self {
+            endpoint,
+            sub_sock,
+            sample_rate,
+            center_frequency: 0.0,
+        }
+    }
+
+    fn connect(endpoint: &str) -> zmq::Result<zmq::Socket> {
         let ctx = zmq::Context::new();
-        let sub_sock = ctx.socket(zmq::SUB).unwrap();
-        sub_sock.connect(&endpoint).unwrap();
-        sub_sock.set_subscribe(b"").unwrap();
-        sub_sock.set_rcvtimeo(1000).unwrap();
+        let sub_sock = ctx.socket(zmq::SUB)?;
+        sub_sock.connect(endpoint)?;
+        sub_sock.set_subscribe(b"")?;
+        sub_sock.set_rcvtimeo(1000)?;
 
-        Self { sub_sock }
+        Ok(sub_sock)
     }
 }
 
 impl Sdr for ZmqMockSdr {
     fn set_rx_frequency(&mut self, freq_hz: f64) {
         println!("[ZMQ SDR] Setting frequency to {} Hz", freq_hz);
+
+        self.center_frequency = freq_hz;
     }
 
-    fn read_samples(&mut self) -> Option<Vec<f64>> {
-        if let Ok(msg) = self.sub_sock.recv_bytes(0) {
-            Some(msg.iter().map(|&b| b as f64).collect())
-        } else {
-            None
+    fn read_samples(&mut self) -> Option<SampleBlock> {
+        let msg = self.sub_sock.recv_bytes(0).ok()?;
+        let received_at = Instant::now();
+
+        let samples: Vec<Complex<f32>> = msg
+            .chunks_exact(8)
+            .map(|iq| {
+                let i = f32::from_ne_bytes(iq[0..4].try_into().unwrap());
+                let q = f32::from_ne_bytes(iq[4..8].try_into().unwrap());
+                Complex::new(i, q)
+            })
+            .collect();
+
+        // The whole message arrives at once, so the first sample is one block duration older.
+        let span = Duration::from_secs_f64(samples.len() as f64 / self.sample_rate);
+        let first_sample = received_at.checked_sub(span).unwrap_or(received_at);
+
+        Some(SampleBlock::new(
+            samples,
+            self.sample_rate,
+            self.center_frequency,
+            Timestamp::Monotonic(first_sample),
+        ))
+    }
+
+    fn reconnect(&mut self) -> bool {
+        println!("[ZMQ SDR] Reconnecting to {}", self.endpoint);
+
+        match Self::connect(&self.endpoint) {
+            Ok(sub_sock) => {
+                self.sub_sock = sub_sock;
+                true
+            }
+            Err(err) => {
+                eprintln!("[ZMQ SDR] Failed to reconnect: {}", err);
+                false
+            }
         }
     }
 }
@@ -84,38 +229,408 @@ impl Sdr for Box<dyn Sdr + Send> {
         (**self).set_rx_frequency(frequency)
     }
 
-    fn read_samples(&mut self) -> Option<Vec<f64>> {
+    fn read_samples(&mut self) -> Option<SampleBlock> {
         (**self).read_samples()
     }
+
+    fn reconnect(&mut self) -> bool {
+        (**self).reconnect()
+    }
 }
 
 pub enum SdrCommand {
     SetRxFrequency(f64),
 }
 
+/// What the SDR reader does with a new block when the samples channel is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the consumer makes room. The device may overflow instead if this lasts.
+    Block,
+    /// Discard the new block and count it in [`SdrStats`].
+    DropNewest,
+}
+
+/// Failed reads in a row after which the SDR is reconnected.
+const FAILED_READS_BEFORE_RECONNECT: u32 = 3;
+/// Wait before the first reconnection attempt, doubled on every further attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// State of the SDR, as seen by [`sdr_task`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SdrHealth {
+    /// Samples are flowing.
+    Ok,
+    /// Blocks are being dropped because the consumer can't keep up.
+    Overflowing,
+    /// Samples stopped arriving; the SDR is being reconnected.
+    Stalled,
+    /// The SDR couldn't be reopened; retrying with backoff.
+    Disconnected,
+}
+
+impl SdrHealth {
+    pub fn name(self) -> &'static str {
+        match self {
+            SdrHealth::Ok => "ok",
+            SdrHealth::Overflowing => "overflowing",
+            SdrHealth::Stalled => "stalled",
+            SdrHealth::Disconnected => "disconnected",
+        }
+    }
+
+    /// Whether samples are reaching the consumer.
+    pub fn is_receiving(self) -> bool {
+        matches!(self, SdrHealth::Ok | SdrHealth::Overflowing)
+    }
+}
+
+/// Counters kept by [`sdr_task`], readable while the task runs.
+#[derive(Debug, Default)]
+pub struct SdrStats {
+    blocks_read: AtomicU64,
+    blocks_dropped: AtomicU64,
+    samples_dropped: AtomicU64,
+    stalls: AtomicU64,
+    reconnects: AtomicU64,
+    gaps: AtomicU64,
+    /// [`SdrHealth`], as its index.
+    health: AtomicU8,
+}
+
+impl SdrStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Blocks read from the SDR, including dropped ones.
+    pub fn blocks_read(&self) -> u64 {
+        self.blocks_read.load(Ordering::Relaxed)
+    }
+
+    /// Blocks discarded because the samples channel was full.
+    pub fn blocks_dropped(&self) -> u64 {
+        self.blocks_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Samples discarded because the samples channel was full.
+    pub fn samples_dropped(&self) -> u64 {
+        self.samples_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Times samples stopped arriving.
+    pub fn stalls(&self) -> u64 {
+        self.stalls.load(Ordering::Relaxed)
+    }
+
+    /// Successful reconnections of the SDR.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Blocks sent with [`SampleBlock::discontinuity`] set.
+    pub fn gaps(&self) -> u64 {
+        self.gaps.load(Ordering::Relaxed)
+    }
+
+    pub fn health(&self) -> SdrHealth {
+        match self.health.load(Ordering::Relaxed) {
+            0 => SdrHealth::Ok,
+            1 => SdrHealth::Overflowing,
+            2 => SdrHealth::Stalled,
+            _ => SdrHealth::Disconnected,
+        }
+    }
+
+    fn set_health(&self, health: SdrHealth) {
+        let previous = self.health();
+        if previous != health {
+            println!(
+                "[SDR TASK] Health: {} -> {}",
+                previous.name(),
+                health.name()
+            );
+            self.health.store(health as u8, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Reads samples from `sdr` and sends them through `samples_tx`, applying the commands received
+/// through `control_rx` in between reads.
+///
+/// The reads happen on a dedicated thread, since [`Sdr::read_samples`] blocks until samples are
+/// available, so this future just waits for that thread to finish. It finishes when either the
+/// control channel or the samples channel is closed.
+///
+/// When reads keep failing the SDR is reconnected, with an exponential backoff between attempts.
+/// Blocks following lost samples, be it because of a stall or an overflow, are marked with
+/// [`SampleBlock::discontinuity`]. The state of the SDR is kept in `stats`.
 pub async fn sdr_task(
+    sdr: impl Sdr + Send + 'static,
+    control_rx: tokio::sync::mpsc::Receiver<SdrCommand>,
+    samples_tx: SyncSender<SampleBlock>,
+    overflow: OverflowPolicy,
+    stats: Arc<SdrStats>,
+) {
+    println!("[SDR TASK] Start");
+
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+
+    thread::Builder::new()
+        .name("sdr-reader".to_string())
+        .spawn(move || {
+            read_loop(sdr, control_rx, samples_tx, overflow, &stats);
+            let _ = done_tx.send(());
+        })
+        .expect("failed to spawn SDR reader thread");
+
+    let _ = done_rx.await;
+
+    println!("[SDR TASK] End");
+}
+
+fn read_loop(
     mut sdr: impl Sdr,
     mut control_rx: tokio::sync::mpsc::Receiver<SdrCommand>,
-    samples_tx: std::sync::mpsc::Sender<Vec<f64>>, // normal channel, not async, unbounded
+    samples_tx: SyncSender<SampleBlock>,
+    overflow: OverflowPolicy,
+    stats: &SdrStats,
 ) {
-    println!("[SDR TASK] Start");
+    let mut stalled = false;
+    let mut failed_reads = 0;
+    let mut reconnect_attempts = 0;
+    // Samples were lost since the last block sent.
+    let mut gap = false;
 
-    // NOTE: maybe read_samples() can be async and we can use in a select! arm
     loop {
-        match control_rx.try_recv() {
-            Ok(SdrCommand::SetRxFrequency(freq)) => {
-                sdr.set_rx_frequency(freq);
+        // Apply every pending command before the next read.
+        loop {
+            match control_rx.try_recv() {
+                Ok(SdrCommand::SetRxFrequency(freq)) => sdr.set_rx_frequency(freq),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+
+        let Some(mut block) = sdr.read_samples() else {
+            failed_reads += 1;
+            gap = true;
+
+            if !stalled {
+                stalled = true;
+                stats.stalls.fetch_add(1, Ordering::Relaxed);
+                stats.set_health(SdrHealth::Stalled);
+            }
+
+            if failed_reads >= FAILED_READS_BEFORE_RECONNECT {
+                let backoff = INITIAL_BACKOFF
+                    .saturating_mul(1 << reconnect_attempts.min(16))
+                    .min(MAX_BACKOFF);
+                reconnect_attempts += 1;
+                thread::sleep(backoff);
+
+                if sdr.reconnect() {
+                    stats.reconnects.fetch_add(1, Ordering::Relaxed);
+                    stats.set_health(SdrHealth::Stalled);
+                    failed_reads = 0;
+                } else {
+                    stats.set_health(SdrHealth::Disconnected);
+                }
+            }
+
+            continue;
+        };
+
+        stalled = false;
+        failed_reads = 0;
+        reconnect_attempts = 0;
+        stats.blocks_read.fetch_add(1, Ordering::Relaxed);
+
+        block.discontinuity |= std::mem::take(&mut gap);
+        let discontinuity = block.discontinuity;
+
+        match overflow {
+            OverflowPolicy::Block => {
+                // Counted before sending, so the consumer sees the stats of the blocks it got.
+                delivered(stats, discontinuity);
+                if samples_tx.send(block).is_err() {
+                    // The consumer went away.
+                    return;
+                }
             }
-            Err(tokio::sync::mpsc::error::TryRecvError::Empty) => {
-                // no command right now, go generate samples
-                if let Some(samples) = sdr.read_samples() {
-                    // NOTE: We'll have to be careful with this. The std channel sender is not
-                    // blocking so it's okay to use here, but if we move to a _bounded_ channel,
-                    // then we will have to consider making it async bc it blocks when full.
-                    samples_tx.send(samples).unwrap(); // panicked here once
+            OverflowPolicy::DropNewest => match samples_tx.try_send(block) {
+                Ok(()) => delivered(stats, discontinuity),
+                Err(TrySendError::Full(block)) => {
+                    gap = true;
+                    stats.set_health(SdrHealth::Overflowing);
+                    stats.blocks_dropped.fetch_add(1, Ordering::Relaxed);
+                    stats
+                        .samples_dropped
+                        .fetch_add(block.len() as u64, Ordering::Relaxed);
                 }
+                Err(TrySendError::Disconnected(_)) => return,
+            },
+        }
+    }
+}
+
+fn delivered(stats: &SdrStats, discontinuity: bool) {
+    stats.set_health(SdrHealth::Ok);
+    if discontinuity {
+        stats.gaps.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// Fails `failures` reads after the first block, then recovers once reconnected.
+    struct FlakySdr {
+        inner: MockSdr,
+        reads: u32,
+        failures: u32,
+        reconnects: Arc<AtomicU64>,
+    }
+
+    impl Sdr for FlakySdr {
+        fn set_rx_frequency(&mut self, frequency: f64) {
+            self.inner.set_rx_frequency(frequency);
+        }
+
+        fn read_samples(&mut self) -> Option<SampleBlock> {
+            self.reads += 1;
+            if self.reads > 1 && self.failures > 0 {
+                self.failures -= 1;
+                return None;
             }
-            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => break,
+
+            self.inner.read_samples()
+        }
+
+        fn reconnect(&mut self) -> bool {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+            // Fail the first attempt.
+            self.reconnects.load(Ordering::Relaxed) > 1
+        }
+    }
+
+    #[tokio::test]
+    async fn recovers_from_stalls_and_marks_the_gap() {
+        let reconnects = Arc::new(AtomicU64::new(0));
+        let sdr = FlakySdr {
+            inner: MockSdr::new(1_000_000.0, 1200.0, 100),
+            reads: 0,
+            failures: 2 * FAILED_READS_BEFORE_RECONNECT,
+            reconnects: reconnects.clone(),
+        };
+        let (_cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(1);
+        let (samp_tx, samp_rx) = mpsc::sync_channel(1);
+        let stats = Arc::new(SdrStats::new());
+
+        let handle = tokio::spawn(sdr_task(
+            sdr,
+            cmd_rx,
+            samp_tx,
+            OverflowPolicy::Block,
+            stats.clone(),
+        ));
+
+        let blocks = tokio::task::spawn_blocking(move || {
+            let blocks = [samp_rx.recv().unwrap(), samp_rx.recv().unwrap()];
+            drop(samp_rx);
+            blocks
+        })
+        .await
+        .unwrap();
+
+        assert!(!blocks[0].discontinuity);
+        assert!(blocks[1].discontinuity);
+        assert_eq!(reconnects.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.stalls(), 1);
+        assert_eq!(stats.reconnects(), 1);
+        assert_eq!(stats.gaps(), 1);
+        assert_eq!(stats.health(), SdrHealth::Ok);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn drop_newest_counts_dropped_blocks() {
+        let sdr = MockSdr::new(1_000_000.0, 1200.0, 100);
+        let (_cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(1);
+        let (samp_tx, samp_rx) = mpsc::sync_channel(2);
+        let stats = Arc::new(SdrStats::new());
+
+        let handle = tokio::spawn(sdr_task(
+            sdr,
+            cmd_rx,
+            samp_tx,
+            OverflowPolicy::DropNewest,
+            stats.clone(),
+        ));
+
+        // Nobody reads the channel, so it fills up and the following blocks are dropped.
+        while stats.blocks_dropped() < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
         }
+        assert_eq!(stats.samples_dropped(), 100 * stats.blocks_dropped());
+
+        drop(samp_rx);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_when_consumer_goes_away() {
+        let sdr = MockSdr::new(1_000_000.0, 1200.0, 100);
+        let (_cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(1);
+        let (samp_tx, samp_rx) = mpsc::sync_channel(1);
+
+        let handle = tokio::spawn(sdr_task(
+            sdr,
+            cmd_rx,
+            samp_tx,
+            OverflowPolicy::Block,
+            Arc::new(SdrStats::new()),
+        ));
+
+        // The receiver is dropped once the first block arrives.
+        let received = tokio::task::spawn_blocking(move || samp_rx.recv().is_ok())
+            .await
+            .unwrap();
+        assert!(received);
+
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("SDR task did not stop")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_when_control_channel_closes() {
+        let sdr = MockSdr::new(1_000_000.0, 1200.0, 100);
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(1);
+        let (samp_tx, _samp_rx) = mpsc::sync_channel(1);
+
+        let handle = tokio::spawn(sdr_task(
+            sdr,
+            cmd_rx,
+            samp_tx,
+            OverflowPolicy::DropNewest,
+            Arc::new(SdrStats::new()),
+        ));
+
+        cmd_tx
+            .send(SdrCommand::SetRxFrequency(435e6))
+            .await
+            .unwrap();
+        drop(cmd_tx);
+
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("SDR task did not stop")
+            .unwrap();
     }
 }
@@ -0,0 +1,285 @@
+//! Signal quality estimates, to tell a pass without signal from one the demodulator couldn't
+//! decode.
+//!
+//! Everything is derived from averaged power spectra: the noise floor is the median bin, which is
+//! robust as long as the signal occupies less than half of the spectrum, and the signal is
+//! whatever rises above it within the expected bandwidth around the tuned frequency.
+
+use crate::{
+    block::SampleBlock,
+    spectrum::{Spectrum, SpectrumAnalyzer},
+};
+
+/// SNR above which a signal is considered present, in dB.
+pub const SIGNAL_PRESENT_SNR_DB: f64 = 3.0;
+
+/// Signal quality measured on a spectrum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SignalMetrics {
+    /// Noise power density, in dB relative to full scale per Hz.
+    pub noise_floor_db: f64,
+    /// Total power (signal and noise) within the signal bandwidth, in dB relative to full scale.
+    pub in_band_power_db: f64,
+    /// Signal to noise ratio within the signal bandwidth, in dB.
+    pub snr_db: f64,
+    /// Energy per bit to noise power density ratio, in dB.
+    pub ebn0_db: f64,
+    /// Offset of the signal's power centroid from the tuned frequency, in Hz. Zero if no signal
+    /// rises above the noise.
+    pub frequency_offset: f64,
+}
+
+impl SignalMetrics {
+    /// Measures a signal `bandwidth` Hz wide, centered on the spectrum's center frequency and
+    /// carrying `bit_rate` bits per second.
+    pub fn measure(spectrum: &Spectrum, bandwidth: f64, bit_rate: f64) -> Self {
+        let power: Vec<f64> = spectrum
+            .power_db
+            .iter()
+            .map(|db| 10f64.powf(*db as f64 / 10.0))
+            .collect();
+
+        let mut sorted = power.clone();
+        sorted.sort_by(f64::total_cmp);
+        let noise_per_bin = sorted[sorted.len() / 2];
+        let noise_density = noise_per_bin / spectrum.noise_bandwidth;
+
+        // Bins are normalized so that a tone reads its power in a single bin, while its energy is
+        // actually spread over the window's noise bandwidth.
+        let to_power = spectrum.bin_width / spectrum.noise_bandwidth;
+        let middle = (power.len() / 2) as f64;
+
+        let mut in_band = 0.0;
+        let mut in_band_bins = 0;
+        let mut excess = 0.0;
+        let mut weighted_frequency = 0.0;
+
+        for (i, bin) in power.iter().enumerate() {
+            let frequency = (i as f64 - middle) * spectrum.bin_width;
+            if frequency.abs() > bandwidth / 2.0 {
+                continue;
+            }
+
+            in_band += bin * to_power;
+            in_band_bins += 1;
+
+            let above_noise = (bin - noise_per_bin).max(0.0);
+            excess += above_noise;
+            weighted_frequency += above_noise * frequency;
+        }
+
+        let noise = noise_density * in_band_bins as f64 * spectrum.bin_width;
+        let signal = (in_band - noise).max(f64::MIN_POSITIVE);
+
+        Self {
+            noise_floor_db: db(noise_density),
+            in_band_power_db: db(in_band),
+            snr_db: db(signal / noise),
+            ebn0_db: db(signal / (noise_density * bit_rate)),
+            frequency_offset: if excess > 0.0 {
+                weighted_frequency / excess
+            } else {
+                0.0
+            },
+        }
+    }
+
+    pub fn signal_present(&self) -> bool {
+        self.snr_db > SIGNAL_PRESENT_SNR_DB
+    }
+}
+
+fn db(ratio: f64) -> f64 {
+    10.0 * ratio.max(1e-30).log10()
+}
+
+/// Measures the signal quality of a sample stream at a fixed rate.
+pub struct SignalMeter {
+    analyzer: SpectrumAnalyzer,
+    bandwidth: f64,
+    bit_rate: f64,
+}
+
+impl SignalMeter {
+    /// Measures a signal `bandwidth` Hz wide at the tuned frequency, carrying `bit_rate` bits per
+    /// second, on the spectra computed by `analyzer`.
+    pub fn new(analyzer: SpectrumAnalyzer, bandwidth: f64, bit_rate: f64) -> Self {
+        Self {
+            analyzer,
+            bandwidth,
+            bit_rate,
+        }
+    }
+
+    /// Feeds a block of samples, returning the measurements it completed.
+    pub fn feed(&mut self, block: &SampleBlock) -> Vec<SignalMetrics> {
+        self.analyzer
+            .feed(block)
+            .iter()
+            .map(|spectrum| SignalMetrics::measure(spectrum, self.bandwidth, self.bit_rate))
+            .collect()
+    }
+}
+
+/// Summary of the measurements taken over a pass.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PassMetrics {
+    measurements: u64,
+    with_signal: u64,
+    noise_floor_sum: f64,
+    best: Option<SignalMetrics>,
+    latest: Option<SignalMetrics>,
+}
+
+impl PassMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, metrics: SignalMetrics) {
+        self.measurements += 1;
+        if metrics.signal_present() {
+            self.with_signal += 1;
+        }
+        self.noise_floor_sum += metrics.noise_floor_db;
+
+        if self.best.is_none_or(|best| metrics.snr_db > best.snr_db) {
+            self.best = Some(metrics);
+        }
+        self.latest = Some(metrics);
+    }
+
+    pub fn measurements(&self) -> u64 {
+        self.measurements
+    }
+
+    /// Measurements in which a signal was present.
+    pub fn with_signal(&self) -> u64 {
+        self.with_signal
+    }
+
+    /// Average noise floor, in dB relative to full scale per Hz.
+    pub fn mean_noise_floor_db(&self) -> Option<f64> {
+        (self.measurements > 0).then(|| self.noise_floor_sum / self.measurements as f64)
+    }
+
+    /// Measurement with the highest SNR.
+    pub fn best(&self) -> Option<SignalMetrics> {
+        self.best
+    }
+
+    pub fn latest(&self) -> Option<SignalMetrics> {
+        self.latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::{Complex, Timestamp},
+        modulation::Modulation,
+    };
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use std::{f64::consts::PI, time::Duration};
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    /// Unit power GFSK at `offset` Hz with complex white noise of `noise_power`.
+    fn block(offset: f64, noise_power: f64) -> SampleBlock {
+        let mut rng = StdRng::seed_from_u64(1);
+        let bits: Vec<bool> = (0..1200).map(|_| rng.random()).collect();
+        let modulation = Modulation::Gfsk {
+            baud: 1200.0,
+            deviation: 600.0,
+            bt: 0.5,
+        };
+        let signal = modulation.modulate(&bits, SAMPLE_RATE);
+
+        let std = (noise_power / 2.0).sqrt();
+        let mut gaussian = move || {
+            let u1: f64 = 1.0 - rng.random::<f64>();
+            let u2: f64 = rng.random();
+            ((-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos() * std) as f32
+        };
+
+        let samples = signal
+            .iter()
+            .enumerate()
+            .map(|(n, s)| {
+                let phase = 2.0 * PI * offset * n as f64 / SAMPLE_RATE;
+                s * Complex::from_polar(1.0, phase as f32) + Complex::new(gaussian(), gaussian())
+            })
+            .collect();
+
+        SampleBlock::new(
+            samples,
+            SAMPLE_RATE,
+            435e6,
+            Timestamp::Hardware(Duration::ZERO),
+        )
+    }
+
+    fn meter() -> SignalMeter {
+        SignalMeter::new(SpectrumAnalyzer::new(512, 16, 1.0), 4800.0, 1200.0)
+    }
+
+    #[test]
+    fn estimates_snr_and_noise_floor() {
+        // Noise at 0.1 over 48 kHz is -56.8 dBFS/Hz. The signal falls within 4800 Hz, where the
+        // noise is 0.01.
+        let metrics = meter().feed(&block(0.0, 0.1))[0];
+
+        assert!(
+            (metrics.noise_floor_db + 56.8).abs() < 0.5,
+            "{}",
+            metrics.noise_floor_db
+        );
+        assert!((metrics.snr_db - 20.0).abs() < 0.5, "{}", metrics.snr_db);
+        // Eb/N0 is the SNR scaled by the bandwidth to bit rate ratio.
+        assert!(
+            (metrics.ebn0_db - metrics.snr_db - 6.0).abs() < 0.1,
+            "{}",
+            metrics.ebn0_db
+        );
+        assert!(metrics.signal_present());
+    }
+
+    #[test]
+    fn estimates_frequency_offset() {
+        let metrics = meter().feed(&block(1000.0, 0.01))[0];
+
+        assert!(
+            (metrics.frequency_offset - 1000.0).abs() < 50.0,
+            "{}",
+            metrics.frequency_offset
+        );
+    }
+
+    #[test]
+    fn no_signal_in_noise() {
+        let noise = block(20_000.0, 0.1);
+        let metrics = meter().feed(&noise)[0];
+
+        assert!(!metrics.signal_present(), "{}", metrics.snr_db);
+    }
+
+    #[test]
+    fn pass_summary_keeps_best_measurement() {
+        let mut meter = meter();
+        let mut pass = PassMetrics::new();
+
+        for metrics in meter
+            .feed(&block(0.0, 0.1))
+            .into_iter()
+            .chain(meter.feed(&block(20_000.0, 0.1)))
+        {
+            pass.add(metrics);
+        }
+
+        assert_eq!(pass.measurements(), 2);
+        assert_eq!(pass.with_signal(), 1);
+        assert!(pass.best().unwrap().signal_present());
+        assert!(!pass.latest().unwrap().signal_present());
+    }
+}
@@ -0,0 +1,192 @@
+use crate::block::Complex;
+use std::f64::consts::PI;
+
+/// Digital modulation schemes that can be synthesized into complex baseband.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Modulation {
+    /// Audio frequency-shift keying (e.g. Bell 202), frequency modulated onto the carrier.
+    Afsk {
+        baud: f64,
+        /// Audio tone for a `1` bit, in Hz.
+        mark: f64,
+        /// Audio tone for a `0` bit, in Hz.
+        space: f64,
+        /// Peak FM deviation of the carrier, in Hz.
+        deviation: f64,
+    },
+    /// Gaussian frequency-shift keying. `1` bits shift the carrier up by `deviation` Hz, `0` bits
+    /// shift it down.
+    Gfsk {
+        baud: f64,
+        deviation: f64,
+        /// Bandwidth-time product of the Gaussian filter.
+        bt: f64,
+    },
+    /// Binary phase-shift keying with rectangular pulses. `1` bits are sent as `+1`, `0` bits as
+    /// `-1`.
+    Bpsk { baud: f64 },
+}
+
+impl Modulation {
+    pub fn baud(&self) -> f64 {
+        match self {
+            Modulation::Afsk { baud, .. }
+            | Modulation::Gfsk { baud, .. }
+            | Modulation::Bpsk { baud } => *baud,
+        }
+    }
+
+    /// Modulates `bits` into unit power complex baseband samples at `sample_rate`.
+    pub fn modulate(&self, bits: &[bool], sample_rate: f64) -> Vec<Complex<f32>> {
+        if bits.is_empty() {
+            return Vec::new();
+        }
+
+        let n_samples = (bits.len() as f64 * sample_rate / self.baud()).round() as usize;
+        // Bit being sent at sample `n`.
+        let bit_at =
+            |n: usize| bits[((n as f64 * self.baud() / sample_rate) as usize).min(bits.len() - 1)];
+
+        match self {
+            Modulation::Afsk {
+                mark,
+                space,
+                deviation,
+                ..
+            } => {
+                let mut tone_phase = 0.0;
+                let mut carrier_phase = 0.0;
+
+                (0..n_samples)
+                    .map(|n| {
+                        let tone = if bit_at(n) { *mark } else { *space };
+                        tone_phase = (tone_phase + 2.0 * PI * tone / sample_rate) % (2.0 * PI);
+                        let audio = tone_phase.sin();
+                        carrier_phase = (carrier_phase
+                            + 2.0 * PI * deviation * audio / sample_rate)
+                            % (2.0 * PI);
+                        Complex::from_polar(1.0, carrier_phase as f32)
+                    })
+                    .collect()
+            }
+            Modulation::Gfsk {
+                baud,
+                deviation,
+                bt,
+            } => {
+                let nrz: Vec<f64> = (0..n_samples)
+                    .map(|n| if bit_at(n) { 1.0 } else { -1.0 })
+                    .collect();
+                let taps = gaussian_taps(*bt, sample_rate / baud);
+                let delay = taps.len() / 2;
+
+                let mut phase = 0.0;
+                (0..n_samples)
+                    .map(|n| {
+                        // Filtered frequency pulse, centered on the current sample.
+                        let freq: f64 = taps
+                            .iter()
+                            .enumerate()
+                            .map(|(k, tap)| {
+                                let idx = (n + delay).checked_sub(k);
+                                let symbol = idx.and_then(|i| nrz.get(i)).copied();
+                                // Hold the first and last symbols at the edges of the burst.
+                                let symbol = symbol.unwrap_or(if idx.is_none() {
+                                    nrz[0]
+                                } else {
+                                    nrz[n_samples - 1]
+                                });
+                                tap * symbol
+                            })
+                            .sum();
+                        phase = (phase + 2.0 * PI * deviation * freq / sample_rate) % (2.0 * PI);
+                        Complex::from_polar(1.0, phase as f32)
+                    })
+                    .collect()
+            }
+            Modulation::Bpsk { .. } => (0..n_samples)
+                .map(|n| Complex::new(if bit_at(n) { 1.0 } else { -1.0 }, 0.0))
+                .collect(),
+        }
+    }
+}
+
+/// Unit DC gain Gaussian filter taps spanning 4 symbols.
+fn gaussian_taps(bt: f64, samples_per_symbol: f64) -> Vec<f64> {
+    let half_len = (2.0 * samples_per_symbol).ceil() as i64;
+    let sigma = (2f64.ln()).sqrt() / (2.0 * PI * bt) * samples_per_symbol;
+
+    let taps: Vec<f64> = (-half_len..=half_len)
+        .map(|k| (-(k as f64).powi(2) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = taps.iter().sum();
+
+    taps.into_iter().map(|t| t / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BITS: [bool; 8] = [true, false, false, true, true, true, false, true];
+
+    #[test]
+    fn sample_count_follows_baud_rate() {
+        let modulations = [
+            Modulation::Afsk {
+                baud: 1200.0,
+                mark: 1200.0,
+                space: 2200.0,
+                deviation: 3000.0,
+            },
+            Modulation::Gfsk {
+                baud: 9600.0,
+                deviation: 2400.0,
+                bt: 0.5,
+            },
+            Modulation::Bpsk { baud: 1200.0 },
+        ];
+
+        for modulation in modulations {
+            let samples = modulation.modulate(&BITS, 48_000.0);
+            let expected = (BITS.len() as f64 * 48_000.0 / modulation.baud()) as usize;
+            assert_eq!(samples.len(), expected, "{:?}", modulation);
+        }
+    }
+
+    #[test]
+    fn fsk_has_constant_envelope() {
+        let modulation = Modulation::Gfsk {
+            baud: 1200.0,
+            deviation: 600.0,
+            bt: 0.5,
+        };
+
+        for sample in modulation.modulate(&BITS, 48_000.0) {
+            assert!((sample.norm() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn gfsk_shifts_frequency_by_bit() {
+        let modulation = Modulation::Gfsk {
+            baud: 1200.0,
+            deviation: 600.0,
+            bt: 0.5,
+        };
+        let samples = modulation.modulate(&[true; 4], 48_000.0);
+
+        // Instantaneous frequency in the middle of the burst.
+        let n = samples.len() / 2;
+        let freq = (samples[n + 1] * samples[n].conj()).arg() as f64 * 48_000.0 / (2.0 * PI);
+        assert!((freq - 600.0).abs() < 1.0, "{}", freq);
+    }
+
+    #[test]
+    fn bpsk_maps_bits_to_sign() {
+        let samples = Modulation::Bpsk { baud: 12_000.0 }.modulate(&[true, false], 48_000.0);
+
+        assert_eq!(samples[..4], [Complex::new(1.0, 0.0); 4]);
+        assert_eq!(samples[4..], [Complex::new(-1.0, 0.0); 4]);
+    }
+}
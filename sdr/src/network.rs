@@ -0,0 +1,501 @@
+//! IQ samples streamed over plain UDP or TCP sockets, as pushed by recorders and SDR servers.
+
+use crate::{
+    Sdr,
+    block::{Complex, SampleBlock, Timestamp},
+};
+use std::{
+    io::{self, Read},
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time without samples after which the stream is considered over.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time waiting for a TCP client before giving up on a read, so the reader thread gets to see
+/// whether it should stop.
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often a waiting listener checks for a client.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Largest read from the socket. Also the largest UDP datagram.
+const MAX_READ: usize = 65_536;
+
+/// Encoding of each complex sample on the wire. All formats are interleaved I/Q, little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// 32 bit floats, e.g. GNU Radio's `gr_complex`.
+    Cf32,
+    /// Signed 16 bit integers, full scale at ±32768.
+    Cs16,
+    /// Signed 8 bit integers, full scale at ±128.
+    Cs8,
+    /// Unsigned 8 bit integers centered at 127.5, as sent by `rtl_tcp`.
+    Cu8,
+}
+
+impl SampleFormat {
+    /// Bytes per complex sample.
+    pub fn sample_size(self) -> usize {
+        match self {
+            SampleFormat::Cf32 => 8,
+            SampleFormat::Cs16 => 4,
+            SampleFormat::Cs8 | SampleFormat::Cu8 => 2,
+        }
+    }
+
+    /// Decodes whole samples from `bytes`. Trailing bytes of an incomplete sample are ignored.
+    pub fn decode(self, bytes: &[u8]) -> Vec<Complex<f32>> {
+        bytes
+            .chunks_exact(self.sample_size())
+            .map(|iq| {
+                let (i, q) = iq.split_at(iq.len() / 2);
+                Complex::new(self.decode_component(i), self.decode_component(q))
+            })
+            .collect()
+    }
+
+    fn decode_component(self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::Cf32 => f32::from_le_bytes(bytes.try_into().unwrap()),
+            SampleFormat::Cs16 => i16::from_le_bytes(bytes.try_into().unwrap()) as f32 / 32_768.0,
+            SampleFormat::Cs8 => bytes[0] as i8 as f32 / 128.0,
+            SampleFormat::Cu8 => (bytes[0] as f32 - 127.5) / 128.0,
+        }
+    }
+}
+
+/// How the samples reach the ground station.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// Connects to a TCP server pushing samples.
+    TcpConnect(String),
+    /// Waits for a TCP client to connect and push samples.
+    TcpListen(String),
+    /// Receives UDP datagrams on the given address. With `sequence_numbers`, each datagram starts
+    /// with a little endian `u32` counter, used to detect lost and reordered datagrams.
+    Udp {
+        address: String,
+        sequence_numbers: bool,
+    },
+}
+
+/// Gaps detected in a sample stream.
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    discontinuities: AtomicU64,
+    packets_lost: AtomicU64,
+    packets_out_of_order: AtomicU64,
+}
+
+impl StreamStats {
+    /// Times the stream skipped samples.
+    pub fn discontinuities(&self) -> u64 {
+        self.discontinuities.load(Ordering::Relaxed)
+    }
+
+    pub fn packets_lost(&self) -> u64 {
+        self.packets_lost.load(Ordering::Relaxed)
+    }
+
+    /// Datagrams that arrived after a later one. They are dropped.
+    pub fn packets_out_of_order(&self) -> u64 {
+        self.packets_out_of_order.load(Ordering::Relaxed)
+    }
+}
+
+enum Socket {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+/// Receives IQ samples from a UDP or TCP socket.
+///
+/// The socket is opened on the first read, so waiting for a TCP client happens on the reader
+/// thread. A read that finds no client within a second returns `None`, like one that times out,
+/// and the listener stays bound until the SDR is dropped. Samples are timed by count from the first one received, so lost UDP datagrams move the
+/// following samples forward in time instead of silently closing the gap.
+pub struct NetworkSdr {
+    transport: Transport,
+    /// Bound on the first read, for `TcpListen`.
+    listener: Option<TcpListener>,
+    socket: Option<Socket>,
+    format: SampleFormat,
+    sample_rate: f64,
+    center_frequency: f64,
+    buffer: Vec<u8>,
+    /// Bytes of an incomplete sample, waiting for the rest of it on the TCP stream.
+    partial: Vec<u8>,
+    next_sequence: Option<u32>,
+    /// Datagrams were lost since the last block was returned.
+    lost: bool,
+    start: Option<Instant>,
+    samples_elapsed: u64,
+    stats: Arc<StreamStats>,
+}
+
+impl NetworkSdr {
+    pub fn new(transport: Transport, format: SampleFormat, sample_rate: f64) -> Self {
+        Self {
+            transport,
+            listener: None,
+            socket: None,
+            format,
+            sample_rate,
+            center_frequency: 0.0,
+            buffer: vec![0; MAX_READ],
+            partial: Vec::new(),
+            next_sequence: None,
+            lost: false,
+            start: None,
+            samples_elapsed: 0,
+            stats: Arc::new(StreamStats::default()),
+        }
+    }
+
+    /// Gaps detected in the stream, readable while the SDR runs on another thread.
+    pub fn stats(&self) -> Arc<StreamStats> {
+        self.stats.clone()
+    }
+
+    fn open(&mut self) -> io::Result<Socket> {
+        let socket = match &self.transport {
+            Transport::TcpConnect(address) => {
+                println!("[NET SDR] Connecting to {}", address);
+                Socket::Tcp(TcpStream::connect(address)?)
+            }
+            Transport::TcpListen(address) => {
+                let listener = match &self.listener {
+                    Some(listener) => listener,
+                    None => {
+                        println!("[NET SDR] Waiting for a connection on {}", address);
+                        let listener = TcpListener::bind(address)?;
+                        listener.set_nonblocking(true)?;
+                        self.listener.insert(listener)
+                    }
+                };
+                let (stream, peer) = accept(listener)?;
+                println!("[NET SDR] Connection from {}", peer);
+                stream.set_nonblocking(false)?;
+                Socket::Tcp(stream)
+            }
+            Transport::Udp { address, .. } => {
+                println!("[NET SDR] Listening on {}", address);
+                Socket::Udp(UdpSocket::bind(address)?)
+            }
+        };
+
+        match &socket {
+            Socket::Tcp(stream) => stream.set_read_timeout(Some(READ_TIMEOUT))?,
+            Socket::Udp(socket) => socket.set_read_timeout(Some(READ_TIMEOUT))?,
+        }
+
+        Ok(socket)
+    }
+
+    /// Reads the next samples from the socket. `None` when the stream ended or failed.
+    fn receive(&mut self) -> Option<Vec<Complex<f32>>> {
+        loop {
+            let result = match self.socket.as_mut()? {
+                Socket::Tcp(stream) => stream.read(&mut self.buffer),
+                Socket::Udp(socket) => socket.recv(&mut self.buffer),
+            };
+
+            let len = match result {
+                Ok(0) if matches!(self.socket, Some(Socket::Tcp(_))) => {
+                    println!("[NET SDR] Connection closed");
+                    return None;
+                }
+                Ok(len) => len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    eprintln!("[NET SDR] Failed to receive samples: {}", err);
+                    return None;
+                }
+            };
+
+            let bytes = self.buffer[..len].to_vec();
+            let samples = match self.socket {
+                Some(Socket::Tcp(_)) => Some(self.stream_samples(&bytes)),
+                _ => self.datagram_samples(&bytes),
+            };
+
+            match samples {
+                Some(samples) if !samples.is_empty() => return Some(samples),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Decodes bytes from a TCP stream, where samples may be split across reads.
+    fn stream_samples(&mut self, bytes: &[u8]) -> Vec<Complex<f32>> {
+        self.partial.extend_from_slice(bytes);
+
+        let whole = self.partial.len() / self.format.sample_size() * self.format.sample_size();
+        let samples = self.format.decode(&self.partial[..whole]);
+        self.partial.drain(..whole);
+
+        samples
+    }
+
+    /// Decodes a UDP datagram, checking its sequence number if enabled. Returns `None` if the
+    /// datagram arrived out of order. Lost datagrams before this one are flagged in `lost`.
+    fn datagram_samples(&mut self, datagram: &[u8]) -> Option<Vec<Complex<f32>>> {
+        let Transport::Udp {
+            sequence_numbers: true,
+            ..
+        } = self.transport
+        else {
+            return Some(self.format.decode(datagram));
+        };
+
+        let (header, payload) = datagram.split_at_checked(4)?;
+        let sequence = u32::from_le_bytes(header.try_into().unwrap());
+        let samples = self.format.decode(payload);
+
+        let expected = self.next_sequence.unwrap_or(sequence);
+        // Wrapping difference, so the counter can roll over.
+        let gap = sequence.wrapping_sub(expected) as i32;
+
+        if gap < 0 {
+            self.stats
+                .packets_out_of_order
+                .fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        if gap > 0 {
+            // Assume the lost datagrams were the same size as this one.
+            let lost_samples = gap as u64 * samples.len() as u64;
+            println!(
+                "[NET SDR] Lost {} datagrams (~{} samples) before #{}",
+                gap, lost_samples, sequence
+            );
+
+            self.stats.discontinuities.fetch_add(1, Ordering::Relaxed);
+            self.stats
+                .packets_lost
+                .fetch_add(gap as u64, Ordering::Relaxed);
+            self.samples_elapsed += lost_samples;
+            self.lost = true;
+        }
+
+        self.next_sequence = Some(sequence.wrapping_add(1));
+
+        Some(samples)
+    }
+}
+
+/// Waits up to `ACCEPT_TIMEOUT` for a client of a non-blocking `listener`.
+fn accept(listener: &TcpListener) -> io::Result<(TcpStream, SocketAddr)> {
+    let deadline = Instant::now() + ACCEPT_TIMEOUT;
+
+    loop {
+        match listener.accept() {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "no client connected",
+                    ));
+                }
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            result => return result,
+        }
+    }
+}
+
+impl Sdr for NetworkSdr {
+    fn set_rx_frequency(&mut self, freq_hz: f64) {
+        println!("[NET SDR] Setting frequency to {} Hz", freq_hz);
+
+        self.center_frequency = freq_hz;
+    }
+
+    fn read_samples(&mut self) -> Option<SampleBlock> {
+        if self.socket.is_none() {
+            match self.open() {
+                Ok(socket) => self.socket = Some(socket),
+                Err(err) => {
+                    eprintln!("[NET SDR] Failed to open socket: {}", err);
+                    return None;
+                }
+            }
+        }
+
+        let samples = self.receive()?;
+
+        let start = *self.start.get_or_insert_with(|| {
+            // The first samples arrive all at once, so the first one is a block duration older.
+            let span = Duration::from_secs_f64(samples.len() as f64 / self.sample_rate);
+            let now = Instant::now();
+            now.checked_sub(span).unwrap_or(now)
+        });
+        let elapsed = Duration::from_secs_f64(self.samples_elapsed as f64 / self.sample_rate);
+        self.samples_elapsed += samples.len() as u64;
+
+        Some(SampleBlock {
+            discontinuity: std::mem::take(&mut self.lost),
+            ..SampleBlock::new(
+                samples,
+                self.sample_rate,
+                self.center_frequency,
+                Timestamp::Monotonic(start).offset(elapsed),
+            )
+        })
+    }
+
+    fn reconnect(&mut self) -> bool {
+        // Start over: the new stream has nothing to do with the old one. A listener stays bound.
+        self.socket = None;
+        self.partial.clear();
+        self.next_sequence = None;
+        self.start = None;
+        self.samples_elapsed = 0;
+
+        match self.open() {
+            Ok(socket) => {
+                self.socket = Some(socket);
+                true
+            }
+            Err(err) => {
+                eprintln!("[NET SDR] Failed to reopen socket: {}", err);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn udp_sdr() -> NetworkSdr {
+        NetworkSdr::new(
+            Transport::Udp {
+                address: "127.0.0.1:0".to_string(),
+                sequence_numbers: true,
+            },
+            SampleFormat::Cs8,
+            48_000.0,
+        )
+    }
+
+    fn datagram(sequence: u32, samples: usize) -> Vec<u8> {
+        let mut datagram = sequence.to_le_bytes().to_vec();
+        datagram.extend(std::iter::repeat_n(64, samples * 2));
+        datagram
+    }
+
+    #[test]
+    fn decodes_sample_formats() {
+        let cases = [
+            (
+                SampleFormat::Cf32,
+                [0.5f32, -0.25].map(f32::to_le_bytes).concat(),
+            ),
+            (
+                SampleFormat::Cs16,
+                [16_384i16, -8_192].map(i16::to_le_bytes).concat(),
+            ),
+            (SampleFormat::Cs8, vec![64, (-32i8) as u8]),
+            (SampleFormat::Cu8, vec![191, 95]),
+        ];
+
+        for (format, bytes) in cases {
+            let samples = format.decode(&bytes);
+            assert_eq!(samples.len(), 1);
+            assert!((samples[0].re - 0.5).abs() < 0.01, "{:?}", format);
+            assert!((samples[0].im + 0.25).abs() < 0.01, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn counts_lost_and_reordered_datagrams() {
+        let mut sdr = udp_sdr();
+
+        assert_eq!(sdr.datagram_samples(&datagram(7, 100)).unwrap().len(), 100);
+        assert!(sdr.datagram_samples(&datagram(8, 100)).is_some());
+        assert!(sdr.datagram_samples(&datagram(11, 100)).is_some());
+        assert!(sdr.datagram_samples(&datagram(9, 100)).is_none());
+
+        let stats = sdr.stats();
+        assert_eq!(stats.discontinuities(), 1);
+        assert_eq!(stats.packets_lost(), 2);
+        assert_eq!(stats.packets_out_of_order(), 1);
+        // The next block is flagged.
+        assert!(sdr.lost);
+        // The lost samples still count towards the time of the following ones.
+        assert_eq!(sdr.samples_elapsed, 200);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let mut sdr = udp_sdr();
+
+        sdr.datagram_samples(&datagram(u32::MAX, 10));
+        assert!(sdr.datagram_samples(&datagram(0, 10)).is_some());
+        assert_eq!(sdr.stats().discontinuities(), 0);
+    }
+
+    #[test]
+    fn reassembles_samples_split_across_tcp_reads() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let sender = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let bytes = [1.0f32, 0.0, 0.0, 1.0].map(f32::to_le_bytes).concat();
+            // Split in the middle of the second sample.
+            stream.write_all(&bytes[..12]).unwrap();
+            stream.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            stream.write_all(&bytes[12..]).unwrap();
+        });
+
+        let mut sdr = NetworkSdr::new(Transport::TcpConnect(address), SampleFormat::Cf32, 48_000.0);
+        sdr.set_rx_frequency(435e6);
+
+        let mut samples = Vec::new();
+        while let Some(block) = sdr.read_samples() {
+            assert_eq!(block.center_frequency, 435e6);
+            samples.extend(block.samples);
+        }
+        sender.join().unwrap();
+
+        assert_eq!(samples, [Complex::new(1.0, 0.0), Complex::new(0.0, 1.0)]);
+    }
+
+    #[test]
+    fn stops_waiting_for_a_client_and_keeps_listening() {
+        let address = {
+            let probe = TcpListener::bind("127.0.0.1:0").unwrap();
+            probe.local_addr().unwrap().to_string()
+        };
+        let mut sdr = NetworkSdr::new(
+            Transport::TcpListen(address.clone()),
+            SampleFormat::Cs8,
+            48_000.0,
+        );
+
+        let start = Instant::now();
+        assert!(sdr.read_samples().is_none());
+        assert!(start.elapsed() < ACCEPT_TIMEOUT * 2);
+
+        // A client that connects later is accepted on the next read.
+        let sender = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(&[64, 64]).unwrap();
+        });
+        let block = sdr.read_samples().unwrap();
+        sender.join().unwrap();
+
+        assert_eq!(block.samples, [Complex::new(0.5, 0.5)]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{block::Complex, doppler::DopplerProfile, modulation::Modulation};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{f64::consts::PI, time::Duration, time::SystemTime};
+
+/// Downlink transmitted by a simulated satellite.
+#[derive(Debug, Clone)]
+pub struct SimulatedSignal {
+    pub modulation: Modulation,
+    /// Frames to transmit, as bits (e.g. the output of `Frame::to_bits`). They are repeated in a
+    /// loop.
+    pub frames: Vec<Vec<bool>>,
+    /// Silence after each frame.
+    pub gap: Duration,
+    /// Frequency the satellite transmits at, in Hz.
+    pub carrier_frequency: f64,
+    /// Signal to noise ratio over the whole sampled bandwidth, in dB. `None` for no noise.
+    pub snr_db: Option<f64>,
+    pub doppler: DopplerProfile,
+}
+
+/// Generates the samples a receiver would see from a [`SimulatedSignal`].
+pub(crate) struct Simulator {
+    signal: SimulatedSignal,
+    /// Modulated samples of the frame being sent, followed by the gap.
+    burst: Vec<Complex<f32>>,
+    position: usize,
+    next_frame: usize,
+    mixer_phase: f64,
+    noise: Option<Awgn>,
+}
+
+impl Simulator {
+    pub(crate) fn new(signal: SimulatedSignal) -> Self {
+        Self {
+            noise: signal.snr_db.map(Awgn::new),
+            signal,
+            burst: Vec::new(),
+            position: 0,
+            next_frame: 0,
+            mixer_phase: 0.0,
+        }
+    }
+
+    /// Generates `len` samples, starting at `time`, as received by a receiver tuned to
+    /// `center_frequency`.
+    pub(crate) fn generate(
+        &mut self,
+        len: usize,
+        sample_rate: f64,
+        center_frequency: f64,
+        time: SystemTime,
+    ) -> Vec<Complex<f32>> {
+        // The offset is evaluated once per block; it changes by a few Hz per second at most.
+        let offset = self
+            .signal
+            .doppler
+            .offset(self.signal.carrier_frequency, time)
+            .map(|doppler| self.signal.carrier_frequency + doppler - center_frequency)
+            .filter(|offset| offset.abs() < sample_rate / 2.0);
+
+        (0..len)
+            .map(|_| {
+                let burst_sample = self.next_burst_sample(sample_rate);
+
+                let sample = match offset {
+                    Some(offset) => {
+                        let mixed =
+                            burst_sample * Complex::from_polar(1.0, self.mixer_phase as f32);
+                        self.mixer_phase =
+                            (self.mixer_phase + 2.0 * PI * offset / sample_rate) % (2.0 * PI);
+                        mixed
+                    }
+                    // Out of band or below the horizon.
+                    None => Complex::new(0.0, 0.0),
+                };
+
+                match &mut self.noise {
+                    Some(noise) => noise.add(sample),
+                    None => sample,
+                }
+            })
+            .collect()
+    }
+
+    fn next_burst_sample(&mut self, sample_rate: f64) -> Complex<f32> {
+        if self.position >= self.burst.len() {
+            self.load_next_burst(sample_rate);
+        }
+
+        let sample = self
+            .burst
+            .get(self.position)
+            .copied()
+            .unwrap_or(Complex::new(0.0, 0.0));
+        self.position += 1;
+
+        sample
+    }
+
+    fn load_next_burst(&mut self, sample_rate: f64) {
+        self.position = 0;
+
+        let gap =
+            vec![Complex::new(0.0, 0.0); (self.signal.gap.as_secs_f64() * sample_rate) as usize];
+
+        self.burst = match self.signal.frames.get(self.next_frame) {
+            Some(bits) => {
+                let mut burst = self.signal.modulation.modulate(bits, sample_rate);
+                burst.extend(gap);
+                burst
+            }
+            None => gap,
+        };
+
+        self.next_frame = (self.next_frame + 1) % self.signal.frames.len().max(1);
+    }
+}
+
+/// Additive white Gaussian noise, at a signal to noise ratio over the whole sampled bandwidth for
+/// a signal of unit power.
+#[derive(Debug, Clone)]
+pub struct Awgn {
+    /// Of each of the I and Q components.
+    std: f64,
+    rng: StdRng,
+}
+
+impl Awgn {
+    pub fn new(snr_db: f64) -> Self {
+        Self::with_rng(snr_db, StdRng::from_os_rng())
+    }
+
+    /// Noise that is the same on every run with the same `seed`.
+    pub fn seeded(snr_db: f64, seed: u64) -> Self {
+        Self::with_rng(snr_db, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(snr_db: f64, rng: StdRng) -> Self {
+        Self {
+            std: (10f64.powf(-snr_db / 10.0) / 2.0).sqrt(),
+            rng,
+        }
+    }
+
+    pub fn add(&mut self, sample: Complex<f32>) -> Complex<f32> {
+        sample
+            + Complex::new(
+                (self.std * self.gaussian()) as f32,
+                (self.std * self.gaussian()) as f32,
+            )
+    }
+
+    /// Standard normal sample, using the Box-Muller transform.
+    fn gaussian(&mut self) -> f64 {
+        let u1: f64 = 1.0 - self.rng.random::<f64>(); // (0, 1], avoids ln(0)
+        let u2: f64 = self.rng.random();
+
+        (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(snr_db: Option<f64>, doppler: DopplerProfile) -> SimulatedSignal {
+        SimulatedSignal {
+            modulation: Modulation::Bpsk { baud: 1200.0 },
+            frames: vec![vec![true, false, true, true]],
+            gap: Duration::from_millis(10),
+            carrier_frequency: 435e6,
+            snr_db,
+            doppler,
+        }
+    }
+
+    fn power(samples: &[Complex<f32>]) -> f64 {
+        samples.iter().map(|s| s.norm_sqr() as f64).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn signal_only_when_tuned_to_carrier() {
+        let now = SystemTime::now();
+
+        let tuned =
+            Simulator::new(signal(None, DopplerProfile::None)).generate(160, 48_000.0, 435e6, now);
+        let detuned =
+            Simulator::new(signal(None, DopplerProfile::None)).generate(160, 48_000.0, 436e6, now);
+
+        assert!(power(&tuned) > 0.99);
+        assert_eq!(power(&detuned), 0.0);
+    }
+
+    #[test]
+    fn noise_power_follows_snr() {
+        // Tuned far away, so only noise is received.
+        let mut sim = Simulator::new(signal(Some(10.0), DopplerProfile::None));
+        let noise = sim.generate(100_000, 48_000.0, 0.0, SystemTime::now());
+
+        assert!((power(&noise) - 0.1).abs() < 0.005, "{}", power(&noise));
+    }
+
+    #[test]
+    fn offset_shifts_the_signal() {
+        let mut sim = Simulator::new(SimulatedSignal {
+            frames: vec![vec![true; 100]],
+            ..signal(None, DopplerProfile::Constant(1000.0))
+        });
+        let samples = sim.generate(1000, 48_000.0, 435e6, SystemTime::now());
+
+        let freq = (samples[501] * samples[500].conj()).arg() as f64 * 48_000.0 / (2.0 * PI);
+        assert!((freq - 1000.0).abs() < 1.0, "{}", freq);
+    }
+}
@@ -0,0 +1,298 @@
+//! Averaged power spectra of a sample stream, e.g. for a live spectrum or waterfall display.
+
+use crate::{
+    Sdr,
+    block::{Complex, SampleBlock, Timestamp},
+};
+use rustfft::{Fft, FftPlanner};
+use std::{f64::consts::PI, sync::Arc};
+
+/// Power spectrum of a stretch of samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// Frequency of the middle bin, in Hz.
+    pub center_frequency: f64,
+    /// Width of each bin, in Hz.
+    pub bin_width: f64,
+    /// Equivalent noise bandwidth of each bin, in Hz. Wider than `bin_width` because of the window,
+    /// so the power density of noise is a bin's power divided by this.
+    pub noise_bandwidth: f64,
+    /// Power of each bin in dB relative to a full scale tone, from the lowest frequency to the
+    /// highest. Bin `len / 2` is at the center frequency.
+    pub power_db: Vec<f32>,
+    /// Time of the first sample that went into the spectrum.
+    pub timestamp: Timestamp,
+}
+
+/// Computes power spectra at a fixed rate, each one the average of several FFTs.
+///
+/// Only the samples needed for the averages are transformed; the rest of each interval is
+/// skipped, so the cost depends on the output rate rather than on the sample rate.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    /// Squared sum of the window, used to normalize the power.
+    window_power: f32,
+    /// Equivalent noise bandwidth of the window, in bins.
+    enbw: f64,
+    averages: usize,
+    /// Spectra per second.
+    rate: f64,
+    center_frequency: f64,
+    sample_rate: f64,
+    /// Samples waiting for a full FFT.
+    buffer: Vec<Complex<f32>>,
+    accumulated: Vec<f32>,
+    /// FFTs accumulated in the current interval.
+    frames: usize,
+    /// Samples seen in the current interval.
+    position: u64,
+    start: Option<Timestamp>,
+}
+
+impl SpectrumAnalyzer {
+    /// Creates an analyzer producing `rate` spectra per second, each with `fft_size` bins and
+    /// averaged over `averages` FFTs.
+    ///
+    /// If the averages span more than `1 / rate` seconds of samples, spectra are produced as fast
+    /// as the averaging allows.
+    pub fn new(fft_size: usize, averages: usize, rate: f64) -> Self {
+        let fft_size = fft_size.max(1);
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+
+        // Hann window.
+        let window: Vec<f32> = (0..fft_size)
+            .map(|n| (0.5 - 0.5 * (2.0 * PI * n as f64 / fft_size as f64).cos()) as f32)
+            .collect();
+        let window_power = window.iter().sum::<f32>().powi(2);
+        let enbw = window.iter().map(|w| w * w).sum::<f32>() as f64 * fft_size as f64
+            / window_power as f64;
+
+        Self {
+            fft,
+            window,
+            window_power,
+            enbw,
+            averages: averages.max(1),
+            rate,
+            center_frequency: 0.0,
+            sample_rate: 0.0,
+            buffer: Vec::with_capacity(fft_size),
+            accumulated: vec![0.0; fft_size],
+            frames: 0,
+            position: 0,
+            start: None,
+        }
+    }
+
+    /// Feeds a block of samples, returning the spectra it completed.
+    ///
+    /// Retuning or changing the sample rate discards the spectrum being averaged.
+    pub fn feed(&mut self, block: &SampleBlock) -> Vec<Spectrum> {
+        if block.center_frequency != self.center_frequency || block.sample_rate != self.sample_rate
+        {
+            self.center_frequency = block.center_frequency;
+            self.sample_rate = block.sample_rate;
+            self.restart();
+        }
+
+        let interval = (self.sample_rate / self.rate).round() as u64;
+        let mut spectra = Vec::new();
+        let mut i = 0;
+
+        while i < block.len() {
+            if self.frames == self.averages {
+                // Done averaging, skip to the start of the next interval.
+                let skip = interval
+                    .saturating_sub(self.position)
+                    .min((block.len() - i) as u64);
+                i += skip as usize;
+                self.position += skip;
+
+                if self.position >= interval {
+                    self.restart();
+                }
+                continue;
+            }
+
+            if self.start.is_none() {
+                self.start = Some(block.timestamp_of(i));
+            }
+
+            let take = (self.window.len() - self.buffer.len()).min(block.len() - i);
+            self.buffer.extend_from_slice(&block.samples[i..i + take]);
+            i += take;
+            self.position += take as u64;
+
+            if self.buffer.len() == self.window.len() {
+                self.accumulate();
+
+                if self.frames == self.averages {
+                    spectra.push(self.spectrum());
+                }
+            }
+        }
+
+        spectra
+    }
+
+    fn accumulate(&mut self) {
+        for (sample, w) in self.buffer.iter_mut().zip(self.window.iter()) {
+            *sample *= w;
+        }
+        self.fft.process(&mut self.buffer);
+
+        for (acc, bin) in self.accumulated.iter_mut().zip(self.buffer.iter()) {
+            *acc += bin.norm_sqr();
+        }
+
+        self.buffer.clear();
+        self.frames += 1;
+    }
+
+    fn spectrum(&self) -> Spectrum {
+        let n = self.accumulated.len();
+        let scale = self.window_power * self.frames as f32;
+
+        // Reorder the bins from the lowest (negative) frequency to the highest.
+        let power_db = self.accumulated[n.div_ceil(2)..]
+            .iter()
+            .chain(self.accumulated[..n.div_ceil(2)].iter())
+            .map(|power| 10.0 * (power / scale).max(1e-20).log10())
+            .collect();
+
+        Spectrum {
+            center_frequency: self.center_frequency,
+            bin_width: self.sample_rate / n as f64,
+            noise_bandwidth: self.enbw * self.sample_rate / n as f64,
+            power_db,
+            timestamp: self.start.expect("a spectrum has at least one sample"),
+        }
+    }
+
+    fn restart(&mut self) {
+        self.buffer.clear();
+        self.accumulated.fill(0.0);
+        self.frames = 0;
+        self.position = 0;
+        self.start = None;
+    }
+}
+
+/// Wraps an [`Sdr`], computing spectra of the samples it reads.
+pub struct SpectrumTap<S, F> {
+    sdr: S,
+    analyzer: SpectrumAnalyzer,
+    on_spectrum: F,
+}
+
+impl<S, F> SpectrumTap<S, F>
+where
+    S: Sdr,
+    F: FnMut(Spectrum),
+{
+    /// Calls `on_spectrum` with every spectrum `analyzer` computes. It's called from the thread
+    /// reading the SDR, so it shouldn't block.
+    pub fn new(sdr: S, analyzer: SpectrumAnalyzer, on_spectrum: F) -> Self {
+        Self {
+            sdr,
+            analyzer,
+            on_spectrum,
+        }
+    }
+}
+
+impl<S, F> Sdr for SpectrumTap<S, F>
+where
+    S: Sdr,
+    F: FnMut(Spectrum),
+{
+    fn set_rx_frequency(&mut self, frequency: f64) {
+        self.sdr.set_rx_frequency(frequency);
+    }
+
+    fn read_samples(&mut self) -> Option<SampleBlock> {
+        let block = self.sdr.read_samples()?;
+
+        for spectrum in self.analyzer.feed(&block) {
+            (self.on_spectrum)(spectrum);
+        }
+
+        Some(block)
+    }
+
+    fn reconnect(&mut self) -> bool {
+        self.sdr.reconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    fn tone(freq: f64, len: usize, start: Duration) -> SampleBlock {
+        let samples = (0..len)
+            .map(|n| Complex::from_polar(1.0, (2.0 * PI * freq * n as f64 / SAMPLE_RATE) as f32))
+            .collect();
+
+        SampleBlock::new(samples, SAMPLE_RATE, 435e6, Timestamp::Hardware(start))
+    }
+
+    #[test]
+    fn tone_shows_up_in_its_bin() {
+        let mut analyzer = SpectrumAnalyzer::new(256, 4, 10.0);
+
+        // 3000 Hz is exactly 16 bins above the center.
+        let spectra = analyzer.feed(&tone(3000.0, 4800, Duration::ZERO));
+        let spectrum = &spectra[0];
+
+        assert_eq!(spectrum.bin_width, 187.5);
+        assert_eq!(spectrum.center_frequency, 435e6);
+
+        let peak = spectrum
+            .power_db
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap();
+        assert_eq!(peak.0, 128 + 16);
+        assert!(peak.1.abs() < 0.1, "{}", peak.1);
+        assert!(spectrum.power_db[64] < -60.0);
+    }
+
+    #[test]
+    fn produces_spectra_at_the_configured_rate() {
+        let mut analyzer = SpectrumAnalyzer::new(256, 4, 10.0);
+
+        // One second of samples, in odd sized blocks.
+        let spectra: Vec<Spectrum> = (0..100)
+            .flat_map(|i| {
+                let start = Duration::from_secs_f64(i as f64 * 480.0 / SAMPLE_RATE);
+                analyzer.feed(&tone(0.0, 480, start))
+            })
+            .collect();
+
+        assert_eq!(spectra.len(), 10);
+        assert_eq!(
+            spectra[1].timestamp,
+            Timestamp::Hardware(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn retuning_restarts_the_average() {
+        let mut analyzer = SpectrumAnalyzer::new(256, 4, 10.0);
+
+        assert!(analyzer.feed(&tone(0.0, 512, Duration::ZERO)).is_empty());
+
+        let mut retuned = tone(0.0, 1024, Duration::ZERO);
+        retuned.center_frequency = 436e6;
+        let spectra = analyzer.feed(&retuned);
+
+        assert_eq!(spectra.len(), 1);
+        assert_eq!(spectra[0].center_frequency, 436e6);
+    }
+}